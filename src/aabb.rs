@@ -0,0 +1,125 @@
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+
+// Slack applied to the far slab distance so rounding in the slab test
+// never culls a hit that the primitive itself would report (pbrt's gamma(3)).
+const FAR_SLACK: f64 = 1.0 + 2.0 * (3.0 * f64::EPSILON * 0.5) / (1.0 - 3.0 * f64::EPSILON * 0.5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    /// A box that contains nothing; the identity for `surrounding`.
+    pub fn empty() -> Self {
+        Self {
+            min: Vec3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Vec3::new(f64::NEG_INFINITY, f64::NEG_INFINITY, f64::NEG_INFINITY),
+        }
+    }
+
+    pub fn surrounding(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x().min(other.min.x()),
+                self.min.y().min(other.min.y()),
+                self.min.z().min(other.min.z()),
+            ),
+            max: Vec3::new(
+                self.max.x().max(other.max.x()),
+                self.max.y().max(other.max.y()),
+                self.max.z().max(other.max.z()),
+            ),
+        }
+    }
+
+    /// Box shared by both, inverted (and so empty) if they don't meet.
+    pub fn overlap(&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Vec3::new(
+                self.min.x().max(other.min.x()),
+                self.min.y().max(other.min.y()),
+                self.min.z().max(other.min.z()),
+            ),
+            max: Vec3::new(
+                self.max.x().min(other.max.x()),
+                self.max.y().min(other.max.y()),
+                self.max.z().min(other.max.z()),
+            ),
+        }
+    }
+
+    pub fn include(&self, p: &Vec3) -> Aabb {
+        self.surrounding(&Aabb::new(p.clone(), p.clone()))
+    }
+
+    /// Where `p` lies in the box, 0 at `min` and 1 at `max` on each axis.
+    pub fn relative(&self, p: &Vec3) -> Vec3 {
+        let extent = self.extent();
+        Vec3::new(
+            (p.x() - self.min.x()) / extent.x(),
+            (p.y() - self.min.y()) / extent.y(),
+            (p.z() - self.min.z()) / extent.z(),
+        )
+    }
+
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (&self.min + &self.max)
+    }
+
+    pub fn extent(&self) -> Vec3 {
+        &self.max - &self.min
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        let d = self.extent();
+        if d.x() < 0.0 || d.y() < 0.0 || d.z() < 0.0 {
+            return 0.0;
+        }
+        2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
+    pub fn longest_axis(&self) -> usize {
+        let d = self.extent();
+        if d.x() > d.y() && d.x() > d.z() {
+            0
+        } else if d.y() > d.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test. The interval is closed so that a primitive hit at exactly
+    /// `t_max` is never culled by its own box.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> bool {
+        let mut t0 = t_min;
+        let mut t1 = t_max;
+        for axis in 0..3 {
+            let inv_d = 1.0 / ray.direction.data[axis];
+            let mut near = (self.min.data[axis] - ray.origin.data[axis]) * inv_d;
+            let mut far = (self.max.data[axis] - ray.origin.data[axis]) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut near, &mut far);
+            }
+            far *= FAR_SLACK;
+            // NaN (origin on a slab plane with a parallel ray) leaves the bound unchanged.
+            if near > t0 {
+                t0 = near;
+            }
+            if far < t1 {
+                t1 = far;
+            }
+            if t1 < t0 {
+                return false;
+            }
+        }
+        true
+    }
+}
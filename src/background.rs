@@ -0,0 +1,101 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::light::LightSample;
+use crate::environment::Environment;
+use crate::daylight::Daylight;
+
+use rand::Rng;
+use serde::{Deserialize, Deserializer};
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// What rays that leave the scene see.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Background {
+    /// White to blue gradient along the up axis.
+    Sky,
+    Constant(Color),
+    /// An image of the surroundings, sampled like a light.
+    Environment(Arc<Environment>),
+    /// Analytic clear sky with a sun.
+    Daylight(Daylight),
+}
+
+impl Background {
+    pub fn color(&self, r: &Ray) -> Color {
+        match self {
+            Background::Sky => {
+                let unit_direction = r.direction.unit_vector();
+                let t = 0.5f64 * (unit_direction.y() + 1.0f64);
+                let white = Color::new_color(1.0, 1.0, 1.0);
+                let col = Color::new_color(0.5, 0.7, 1.0);
+                ((1.0f64 - t) * &white) + (t * &col)
+            }
+            Background::Constant(color) => color.clone(),
+            Background::Environment(environment) => environment.radiance(&r.direction),
+            Background::Daylight(daylight) => daylight.radiance(&r.direction),
+        }
+    }
+
+    /// Whether shadow rays should aim at the background.
+    pub fn is_sampled(&self) -> bool {
+        matches!(self, Background::Environment(_) | Background::Daylight(_))
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        match self {
+            Background::Environment(environment) => environment.sample(rng),
+            Background::Daylight(daylight) => daylight.sample(rng),
+            _ => None,
+        }
+    }
+
+    /// Solid angle density of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        match self {
+            Background::Environment(environment) => environment.pdf(direction),
+            Background::Daylight(daylight) => daylight.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+/// Parses `sky`, `black` or an `r,g,b` triple.
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "sky" => Ok(Background::Sky),
+            "black" => Ok(Background::Constant(Color::new_color(0.0, 0.0, 0.0))),
+            rgb => {
+                let channels = rgb
+                    .split(',')
+                    .map(|c| c.trim().parse::<f64>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()
+                    .filter(|c| c.len() == 3)
+                    .ok_or_else(|| format!("invalid background `{}`, expected sky, black or r,g,b", s))?;
+                Ok(Background::Constant(Color::new_color(channels[0], channels[1], channels[2])))
+            }
+        }
+    }
+}
+
+/// Scenes write either a name (`"sky"`, `"black"`) or a color array.
+impl<'de> Deserialize<'de> for Background {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Desc {
+            Name(String),
+            Color([f64; 3]),
+        }
+
+        match Desc::deserialize(deserializer)? {
+            Desc::Name(name) => name.parse().map_err(serde::de::Error::custom),
+            Desc::Color([r, g, b]) => Ok(Background::Constant(Color::new_color(r, g, b))),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::aabb::Aabb;
+use crate::hitrecord::HitRecord;
+use crate::ray::Ray;
+use crate::vec3::Vec3;
+
+const BUCKETS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+// Relative cost of visiting an interior node versus intersecting a primitive.
+const TRAVERSAL_COST: f64 = 0.125;
+
+struct Node {
+    bbox: Aabb,
+    // Leaves index into `Bvh::indices`; interior nodes keep their first
+    // child right after themselves and the second one at `second_child`.
+    start: usize,
+    count: usize,
+    second_child: usize,
+    axis: usize,
+}
+
+struct BuildItem {
+    index: usize,
+    bbox: Aabb,
+    centroid: Vec3,
+}
+
+/// Bounding volume hierarchy over anything that can report a bounding box,
+/// built with a binned surface area heuristic. It only stores primitive
+/// indices; intersecting an index is left to the caller.
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>,
+}
+
+impl Bvh {
+    /// Builds the tree from `(primitive index, bounding box)` pairs.
+    pub fn new(boxes: Vec<(usize, Aabb)>) -> Self {
+        let mut items: Vec<BuildItem> = boxes
+            .into_iter()
+            .map(|(index, bbox)| {
+                let centroid = bbox.centroid();
+                BuildItem { index, bbox, centroid }
+            })
+            .collect();
+
+        let mut bvh = Bvh { nodes: Vec::new(), indices: Vec::with_capacity(items.len()) };
+        if !items.is_empty() {
+            bvh.build(&mut items);
+        }
+        bvh
+    }
+
+    pub fn bounding_box(&self) -> Option<&Aabb> {
+        self.nodes.first().map(|n| &n.bbox)
+    }
+
+    fn build(&mut self, items: &mut [BuildItem]) -> usize {
+        let bbox = items.iter().fold(Aabb::empty(), |acc, i| acc.surrounding(&i.bbox));
+        let node_index = self.nodes.len();
+        self.nodes.push(Node { bbox, start: 0, count: 0, second_child: 0, axis: 0 });
+
+        match self.split(items) {
+            Some((axis, mid)) => {
+                let (left, right) = items.split_at_mut(mid);
+                self.build(left);
+                let second = self.build(right);
+                let node = &mut self.nodes[node_index];
+                node.second_child = second;
+                node.axis = axis;
+            }
+            None => {
+                let node = &mut self.nodes[node_index];
+                node.start = self.indices.len();
+                node.count = items.len();
+                self.indices.extend(items.iter().map(|i| i.index));
+            }
+        }
+        node_index
+    }
+
+    /// Picks a split with the surface area heuristic, partitioning `items`
+    /// in place. Returns `None` when a leaf is cheaper.
+    fn split(&self, items: &mut [BuildItem]) -> Option<(usize, usize)> {
+        let n = items.len();
+        if n == 1 {
+            return None;
+        }
+
+        let centroid_bounds = items.iter().fold(Aabb::empty(), |acc, i| acc.include(&i.centroid));
+        let axis = centroid_bounds.longest_axis();
+        let lo = centroid_bounds.min.data[axis];
+        let hi = centroid_bounds.max.data[axis];
+
+        if hi <= lo {
+            // Every centroid coincides, so no plane can separate them.
+            if n <= MAX_LEAF_SIZE {
+                return None;
+            }
+            return Some((axis, n / 2));
+        }
+
+        let bucket_of = |c: f64| (((c - lo) / (hi - lo)) * BUCKETS as f64).min(BUCKETS as f64 - 1.0) as usize;
+
+        let mut counts = [0usize; BUCKETS];
+        let mut bounds: Vec<Aabb> = (0..BUCKETS).map(|_| Aabb::empty()).collect();
+        for item in items.iter() {
+            let b = bucket_of(item.centroid.data[axis]);
+            counts[b] += 1;
+            bounds[b] = bounds[b].surrounding(&item.bbox);
+        }
+
+        // Sweep from both ends so every split is costed in linear time.
+        let mut left_area = [0.0; BUCKETS - 1];
+        let mut left_count = [0usize; BUCKETS - 1];
+        let mut acc = Aabb::empty();
+        let mut count = 0;
+        for i in 0..BUCKETS - 1 {
+            acc = acc.surrounding(&bounds[i]);
+            count += counts[i];
+            left_area[i] = acc.surface_area();
+            left_count[i] = count;
+        }
+
+        let total_area = items.iter().fold(Aabb::empty(), |acc, i| acc.surrounding(&i.bbox)).surface_area();
+        let mut best_cost = f64::INFINITY;
+        let mut best_bucket = 0;
+        let mut acc = Aabb::empty();
+        let mut count = 0;
+        for i in (1..BUCKETS).rev() {
+            acc = acc.surrounding(&bounds[i]);
+            count += counts[i];
+            let cost = TRAVERSAL_COST
+                + (left_area[i - 1] * left_count[i - 1] as f64 + acc.surface_area() * count as f64)
+                    / total_area.max(f64::MIN_POSITIVE);
+            if cost < best_cost {
+                best_cost = cost;
+                best_bucket = i - 1;
+            }
+        }
+
+        if n <= MAX_LEAF_SIZE && best_cost >= n as f64 {
+            return None;
+        }
+
+        let mut mid = 0;
+        for i in 0..n {
+            if bucket_of(items[i].centroid.data[axis]) <= best_bucket {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+
+        if mid == 0 || mid == n {
+            return Some((axis, n / 2));
+        }
+        Some((axis, mid))
+    }
+
+    /// Finds the closest hit, calling `hit_primitive(index, t_max)` for each
+    /// candidate. When two primitives report the same `t` the one with the
+    /// higher index wins, which is what a front-to-back linear scan does.
+    pub fn hit<'a, F>(&self, ray: &Ray, t_min: f64, t_max: f64, mut hit_primitive: F) -> Option<(usize, HitRecord<'a>)>
+    where
+        F: FnMut(usize, f64) -> Option<HitRecord<'a>>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let negative = [
+            ray.direction.x() < 0.0,
+            ray.direction.y() < 0.0,
+            ray.direction.z() < 0.0,
+        ];
+
+        let mut closest: Option<(usize, HitRecord<'a>)> = None;
+        let mut closest_so_far = t_max;
+        let mut stack = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            if !node.bbox.hit(ray, t_min, closest_so_far) {
+                continue;
+            }
+
+            if node.count > 0 {
+                for &index in &self.indices[node.start..node.start + node.count] {
+                    if let Some(rec) = hit_primitive(index, closest_so_far) {
+                        let better = match &closest {
+                            None => true,
+                            Some((best, best_rec)) => rec.t < best_rec.t || index > *best,
+                        };
+                        if better {
+                            closest_so_far = rec.t;
+                            closest = Some((index, rec));
+                        }
+                    }
+                }
+            } else if negative[node.axis] {
+                stack.push(node_index + 1);
+                stack.push(node.second_child);
+            } else {
+                stack.push(node.second_child);
+                stack.push(node_index + 1);
+            }
+        }
+
+        closest
+    }
+}
@@ -2,15 +2,28 @@ use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::util::degrees_to_radians;
 
+use rand::Rng;
+
 pub struct Camera {
     pub origin: Vec3,
     pub lower_left_corner: Vec3,
     pub horizontal: Vec3,
     pub vertical: Vec3,
+    // Lens basis, u to the right and v up.
+    pub u: Vec3,
+    pub v: Vec3,
+    pub lens_radius: f64,
+    pub focus_dist: f64,
+    // Shutter interval; every ray gets a uniform time within it.
+    pub time0: f64,
+    pub time1: f64,
 }
 
 impl Camera {
-    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: f64, aspect: f64) -> Self {
+    /// A thin lens camera. Points `focus_dist` away from `lookfrom` are in
+    /// focus and the blur grows with `aperture`, the lens diameter; an
+    /// aperture of 0 is a pinhole with everything in focus.
+    pub fn new(lookfrom: Vec3, lookat: Vec3, vup: Vec3, vfov: f64, aspect: f64, aperture: f64, focus_dist: f64) -> Self {
         //let image_height = (image_width as f64 / aspect_ratio) as i64;
 
         let focal_length = 1.0;
@@ -24,8 +37,8 @@ impl Camera {
         let v = w.cross(&u);
 
         let origin = lookfrom;
-        let horizontal = viewport_width * u;
-        let vertical = viewport_height * v;
+        let horizontal = viewport_width * &u;
+        let vertical = viewport_height * &v;
         let lower_left_corner = &origin - (&horizontal / 2.0) - (&vertical / 2.0) - w;
 
         Self {
@@ -33,13 +46,90 @@ impl Camera {
             horizontal,
             vertical,
             lower_left_corner,
+            u,
+            v,
+            lens_radius: aperture / 2.0,
+            focus_dist,
+            time0: 0.0,
+            time1: 0.0,
         }
     }
 
-    pub fn get_ray(&self, u: f64, v: f64) -> Ray {
+    /// Keeps the shutter open from `open` to `close`, in the same time
+    /// units as the keyframes of moving figures.
+    pub fn with_shutter(mut self, open: f64, close: f64) -> Self {
+        self.time0 = open;
+        self.time1 = close;
+        self
+    }
+
+    pub fn get_ray<R: Rng>(&self, s: f64, t: f64, rng: &mut R) -> Ray {
+        let time = if self.time1 > self.time0 {
+            rng.gen_range(self.time0..self.time1)
+        } else {
+            self.time0
+        };
         let origin = self.origin.clone();
-        let direction = &self.lower_left_corner + (&self.horizontal * u) + (&self.vertical * v) - &self.origin;
-        Ray::new(origin, direction)
+        let direction = &self.lower_left_corner + (&self.horizontal * s) + (&self.vertical * t) - &self.origin;
+        if self.lens_radius <= 0.0 {
+            return Ray::new(origin, direction).with_time(time);
+        }
+
+        // The viewport sits at distance 1, so scaling the pinhole direction
+        // by the focus distance lands on the plane in focus.
+        let focus = &origin + (self.focus_dist * direction);
+        let rd = self.lens_radius * Vec3::rand_in_unit_disk(rng);
+        let lens_point = origin + (&self.u * rd.x()) + (&self.v * rd.y());
+        let direction = focus - &lens_point;
+        Ray::new(lens_point, direction).with_time(time)
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn camera(aperture: f64) -> Camera {
+        let lookfrom = Vec3::new(1.0, 2.0, 3.0);
+        Camera::new(lookfrom, Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 40.0, 1.5, aperture, 5.0)
+    }
+
+    #[test]
+    fn test_pinhole() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let ray = camera(0.0).get_ray(0.5, 0.5, &mut rng);
+        assert_eq!(ray.origin, Vec3::new(1.0, 2.0, 3.0));
+        let expected = Vec3::new(-1.0, -2.0, -3.0).unit_vector();
+        assert!((ray.direction.unit_vector() - expected).near_zero());
     }
 
+    #[test]
+    fn test_rays_meet_on_focus_plane() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let pinhole = camera(0.0);
+        let lens = camera(0.5);
+        for (s, t) in [(0.5, 0.5), (0.1, 0.9), (1.0, 0.0)] {
+            let center = pinhole.get_ray(s, t, &mut rng);
+            let focus = center.at(5.0);
+            for _ in 0..20 {
+                let ray = lens.get_ray(s, t, &mut rng);
+                assert!((&ray.origin - &lens.origin).length() <= 0.25 + 1e-12);
+                assert!((ray.at(1.0) - &focus).near_zero());
+            }
+        }
+    }
+
+    #[test]
+    fn test_shutter() {
+        let mut rng = StdRng::seed_from_u64(3);
+        assert_eq!(camera(0.0).get_ray(0.5, 0.5, &mut rng).time, 0.0);
+        let camera = camera(0.0).with_shutter(1.0, 1.5);
+        let times: Vec<f64> = (0..1000).map(|_| camera.get_ray(0.5, 0.5, &mut rng).time).collect();
+        assert!(times.iter().all(|t| (1.0..1.5).contains(t)));
+        let mean = times.iter().sum::<f64>() / times.len() as f64;
+        assert!((mean - 1.25).abs() < 0.02);
+    }
 }
@@ -22,15 +22,11 @@ impl Color {
         self.data[2]
     }
 
-    pub fn ppm_color(&self, samples: f64) -> String {
-        let r = (self.r() / samples).sqrt();
-        let g = (self.g() / samples).sqrt();
-        let b = (self.b() / samples).sqrt();
-
-        let rd = (256.0 * clamp(r, 0.0, 0.999)) as i32;
-        let gd = (256.0 * clamp(g, 0.0, 0.999)) as i32;
-        let bd = (256.0 * clamp(b, 0.0, 0.999)) as i32;
-
-        format!("{} {} {}", rd, gd, bd)
+    /// Gamma corrects (gamma 2) a linear color and scales each channel to
+    /// `0..=max_value`, e.g. 255 for 8 bit and 65535 for 16 bit output.
+    pub fn quantize(&self, max_value: u32) -> [u32; 3] {
+        let levels = max_value as f64 + 1.0;
+        let channel = |c: f64| (levels * clamp(c.sqrt(), 0.0, 1.0)).min(max_value as f64) as u32;
+        [channel(self.r()), channel(self.g()), channel(self.b())]
     }
 }
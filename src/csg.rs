@@ -0,0 +1,274 @@
+use crate::hitrecord::HitRecord;
+use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::figure::Figure;
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOp {
+    Union,
+    Intersection,
+    /// The left figure with the right one carved out of it.
+    Difference,
+}
+
+impl CsgOp {
+    fn inside(&self, left: bool, right: bool) -> bool {
+        match self {
+            CsgOp::Union => left || right,
+            CsgOp::Intersection => left && right,
+            CsgOp::Difference => left && !right,
+        }
+    }
+}
+
+/// Every surface a ray crosses from `t_min` on, in order. Each record's
+/// `front_face` tells whether the ray is inside the solid after it, so a
+/// missed tangent hit can't leave the count of crossings out of step.
+///
+/// The search never stops at a `t_max`: whether the ray starts inside is
+/// read from the first crossing, which a short range could cut off.
+struct Crossings<'a> {
+    starts_inside: bool,
+    records: Vec<HitRecord<'a>>,
+}
+
+impl<'a> Crossings<'a> {
+    fn of(figure: &'a Figure, ray: &Ray, t_min: f64) -> Self {
+        if let Figure::Csg(csg) = figure {
+            return csg.crossings(ray, t_min);
+        }
+        let mut records: Vec<HitRecord> = Vec::new();
+        let mut from = t_min;
+        while let Some(rec) = figure.hit(ray, from, f64::INFINITY) {
+            from = rec.t + 1e-9 * rec.t.abs().max(1.0);
+            records.push(rec);
+        }
+        // Leaving first means the ray started inside.
+        let starts_inside = records.first().is_some_and(|rec| !rec.front_face);
+        Self { starts_inside, records }
+    }
+}
+
+/// Stretches of `[t_min, t_max]` the ray spends inside a closed figure,
+/// in order.
+pub fn inside_spans(figure: &Figure, ray: &Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+    let crossings = Crossings::of(figure, ray, t_min);
+    let mut spans = Vec::new();
+    let mut entered = crossings.starts_inside.then_some(t_min);
+    for rec in crossings.records.iter().take_while(|rec| rec.t <= t_max) {
+        match (entered, rec.front_face) {
+            (None, true) => entered = Some(rec.t),
+            (Some(start), false) => {
+                spans.push((start, rec.t));
+                entered = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = entered {
+        spans.push((start, t_max));
+    }
+    spans
+}
+
+/// Boolean combination of two solids. Surfaces keep the material of the
+/// figure they come from, so the walls carved by a difference show the
+/// right figure's material, facing into the hole.
+///
+/// Both figures should be closed; the inside of an open surface is
+/// undefined.
+pub struct Csg {
+    pub op: CsgOp,
+    pub left: Box<Figure>,
+    pub right: Box<Figure>,
+}
+
+impl Csg {
+    pub fn new(op: CsgOp, left: Figure, right: Figure) -> Self {
+        Self { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    /// Merges the crossings of both figures, keeping the ones where the
+    /// ray enters or leaves the combination.
+    fn crossings<'a>(&'a self, ray: &Ray, t_min: f64) -> Crossings<'a> {
+        let left = Crossings::of(&self.left, ray, t_min);
+        let right = Crossings::of(&self.right, ray, t_min);
+        let mut inside = (left.starts_inside, right.starts_inside);
+        let starts_inside = self.op.inside(inside.0, inside.1);
+
+        let mut events: Vec<(bool, HitRecord)> = left
+            .records
+            .into_iter()
+            .map(|rec| (true, rec))
+            .chain(right.records.into_iter().map(|rec| (false, rec)))
+            .collect();
+        events.sort_by(|a, b| a.1.t.total_cmp(&b.1.t));
+
+        let mut records = Vec::new();
+        let mut was_inside = starts_inside;
+        let mut events = events.into_iter().peekable();
+        while let Some((is_left, mut rec)) = events.next() {
+            if is_left {
+                inside.0 = rec.front_face;
+            } else {
+                inside.1 = rec.front_face;
+            }
+            // Surfaces the two figures share are crossed together, so a
+            // coincident face doesn't leave a sliver behind.
+            if events.peek().is_some_and(|(_, next)| next.t - rec.t <= 1e-9 * rec.t.abs().max(1.0)) {
+                continue;
+            }
+            let is_inside = self.op.inside(inside.0, inside.1);
+            if is_inside != was_inside {
+                // The normal already faces the ray; only which side is
+                // the inside changes, e.g. leaving a carved-out figure
+                // enters the difference.
+                rec.front_face = is_inside;
+                records.push(rec);
+                was_inside = is_inside;
+            }
+        }
+        Crossings { starts_inside, records }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.crossings(ray, t_min).records.into_iter().next().filter(|rec| rec.t <= t_max)
+    }
+
+    /// Box around the combination, or `None` if it is unbounded.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounding_box(), self.right.bounding_box());
+        match self.op {
+            CsgOp::Union => left.zip(right).map(|(l, r)| l.surrounding(&r)),
+            CsgOp::Intersection => match (left, right) {
+                (Some(l), Some(r)) => Some(l.overlap(&r)),
+                (l, r) => l.or(r),
+            },
+            CsgOp::Difference => left,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use crate::color::Color;
+    use crate::material::Material;
+
+    fn sphere(x: f64) -> Figure {
+        Figure::sphere(Vec3::new(x, 0.0, 0.0), 1.0, Material::lambertian(Color::new(1.0, 1.0, 1.0)))
+    }
+
+    fn along_x() -> Ray {
+        Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0))
+    }
+
+    /// Where the ray enters or leaves the figure, with the side it is on
+    /// afterwards, collected by stepping from hit to hit.
+    fn boundaries(figure: &Figure, ray: &Ray) -> Vec<(f64, bool, Vec3)> {
+        let mut out = Vec::new();
+        let mut from = 0.001;
+        while let Some(rec) = figure.hit(ray, from, f64::INFINITY) {
+            out.push((rec.t, rec.front_face, rec.normal.clone()));
+            from = rec.t + 1e-6;
+        }
+        out
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        // Spheres at x = -0.5 and 0.5 overlap between -0.5 and 0.5.
+        let union = Figure::csg(CsgOp::Union, sphere(-0.5), sphere(0.5));
+        let hits = boundaries(&union, &along_x());
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].0 - 3.5).abs() < 1e-9 && hits[0].1);
+        assert!((hits[1].0 - 6.5).abs() < 1e-9 && !hits[1].1);
+
+        let both = Figure::csg(CsgOp::Intersection, sphere(-0.5), sphere(0.5));
+        let hits = boundaries(&both, &along_x());
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].0 - 4.5).abs() < 1e-9 && hits[0].1);
+        assert!((hits[1].0 - 5.5).abs() < 1e-9 && !hits[1].1);
+
+        let bounds = both.bounding_box().unwrap();
+        assert_eq!((bounds.min.x(), bounds.max.x()), (-0.5, 0.5));
+
+        // Disjoint spheres have nothing in common.
+        let apart = Figure::csg(CsgOp::Intersection, sphere(-2.0), sphere(2.0));
+        assert!(apart.hit(&along_x(), 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_difference_carves() {
+        // The left sphere with a bite taken out of its right side.
+        let bitten = Figure::csg(CsgOp::Difference, sphere(-0.5), sphere(0.5));
+        let hits = boundaries(&bitten, &along_x());
+        assert_eq!(hits.len(), 2);
+        assert!((hits[0].0 - 3.5).abs() < 1e-9 && hits[0].1);
+        // Leaving through the carved wall: the ray exits where it enters
+        // the right sphere, and the wall faces into the hole.
+        let (t, front_face, normal) = &hits[1];
+        assert!((t - 4.5).abs() < 1e-9 && !front_face);
+        assert_eq!(*normal, Vec3::new(-1.0, 0.0, 0.0));
+
+        // From the other side the carved wall is where the ray enters.
+        let back = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = bitten.hit(&back, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 5.5).abs() < 1e-9 && rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_inside_origin_and_nesting() {
+        // A hollow shell: starting in the cavity, the first surface enters
+        // the solid.
+        let shell = Figure::csg(
+            CsgOp::Difference,
+            Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 2.0, Material::lambertian(Color::new(1.0, 1.0, 1.0))),
+            sphere(0.0),
+        );
+        let out = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = shell.hit(&out, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-9 && rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(-1.0, 0.0, 0.0));
+        let rec = shell.hit(&out, 1.5, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-9 && !rec.front_face);
+
+        // Nested nodes combine the full intervals of their children.
+        let ends = Figure::csg(CsgOp::Union, sphere(-3.0), sphere(3.0));
+        let shell_with_ends = Figure::csg(CsgOp::Difference, ends, sphere(3.0));
+        let ray = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let hits = boundaries(&shell_with_ends, &ray);
+        assert_eq!(hits.iter().map(|h| h.0.round()).collect::<Vec<_>>(), vec![6.0, 8.0]);
+    }
+
+    #[test]
+    fn test_short_range_from_inside() {
+        // A big sphere with a bite out of its right side. Starting inside
+        // the solid, the first surface is the carved wall at x = 1, so a
+        // range ending before it must find nothing.
+        let bitten = Figure::csg(
+            CsgOp::Difference,
+            Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 2.0, Material::lambertian(Color::new(1.0, 1.0, 1.0))),
+            sphere(2.0),
+        );
+        let ray = Ray::new(Vec3::new(0.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(bitten.hit(&ray, 0.001, 0.4).is_none());
+        let rec = bitten.hit(&ray, 0.001, 1.0).unwrap();
+        assert!((rec.t - 0.5).abs() < 1e-9 && !rec.front_face);
+
+        // Starting in the bite, the range decides nothing either.
+        let ray = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(bitten.hit(&ray, 0.001, 1.0).is_none());
+        assert!(bitten.hit(&ray, 0.001, f64::INFINITY).is_none());
+
+        // Spans are clipped to the range, not cut short by it.
+        let big = Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 2.0, Material::lambertian(Color::new(1.0, 1.0, 1.0)));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert_eq!(inside_spans(&big, &ray, 0.001, 0.4), vec![(0.001, 0.4)]);
+    }
+}
@@ -0,0 +1,186 @@
+use crate::hitrecord::{HitRecord, FaceNormal};
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::aabb::Aabb;
+
+/// Rectangular box, possibly rotated. Each face has UVs from 0 to 1.
+#[derive(Debug, Clone)]
+pub struct Cuboid {
+    pub center: Vec3,
+    /// Orthonormal edge directions.
+    pub axes: [Vec3; 3],
+    /// Half the edge length along each axis.
+    pub half_extents: [f64; 3],
+    pub material: Material,
+}
+
+impl Cuboid {
+    /// Axis-aligned box between two opposite corners.
+    pub fn new(a: Vec3, b: Vec3, material: Material) -> Self {
+        let center = 0.5 * (&a + &b);
+        let half_extents = std::array::from_fn(|i| 0.5 * (a.data[i] - b.data[i]).abs());
+        let axes = [Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)];
+        Self { center, axes, half_extents, material }
+    }
+
+    /// Box with its first edge along `x_axis` and its second in the plane
+    /// of `x_axis` and `y_axis`.
+    pub fn oriented(center: Vec3, half_extents: [f64; 3], x_axis: &Vec3, y_axis: &Vec3, material: Material) -> Self {
+        let x = x_axis.unit_vector();
+        let y = (y_axis - (y_axis.dot(&x) * &x)).unit_vector();
+        assert!(x.iter().chain(y.iter()).all(|c| c.is_finite()), "box axes must not be parallel");
+        let z = x.cross(&y);
+        let half_extents = half_extents.map(f64::abs);
+        Self { center, axes: [x, y, z], half_extents, material }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let offset = &ray.origin - &self.center;
+
+        // Slab test in the box's frame, remembering which face each end is on.
+        let (mut t_enter, mut enter_axis) = (f64::NEG_INFINITY, 0);
+        let (mut t_exit, mut exit_axis) = (f64::INFINITY, 0);
+        for (axis, (a, h)) in self.axes.iter().zip(self.half_extents).enumerate() {
+            let o = offset.dot(a);
+            let d = ray.direction.dot(a);
+            if d == 0.0 {
+                if o.abs() > h {
+                    return None;
+                }
+                continue;
+            }
+            let (t0, t1) = {
+                let (t0, t1) = ((-h - o) / d, (h - o) / d);
+                if t0 < t1 { (t0, t1) } else { (t1, t0) }
+            };
+            if t0 > t_enter {
+                (t_enter, enter_axis) = (t0, axis);
+            }
+            if t1 < t_exit {
+                (t_exit, exit_axis) = (t1, axis);
+            }
+        }
+        if t_enter > t_exit {
+            return None;
+        }
+
+        let (t, axis, entering) = if (t_min..=t_max).contains(&t_enter) {
+            (t_enter, enter_axis, true)
+        } else if (t_min..=t_max).contains(&t_exit) {
+            (t_exit, exit_axis, false)
+        } else {
+            return None;
+        };
+
+        // Rays enter through the face looking against them and leave
+        // through the face looking along.
+        let along = ray.direction.dot(&self.axes[axis]) > 0.0;
+        let sign = if along == entering { -1.0 } else { 1.0 };
+        let outward_normal = sign * &self.axes[axis];
+
+        let point = ray.at(t);
+        let local = &point - &self.center;
+        let face_coord = |i: usize| {
+            let i = (axis + i) % 3;
+            let h = self.half_extents[i];
+            if h > 0.0 { 0.5 * (local.dot(&self.axes[i]) / h + 1.0) } else { 0.5 }
+        };
+        let (u, v) = (face_coord(1), face_coord(2));
+
+        let rec = match HitRecord::get_face_normal(ray, &outward_normal) {
+            FaceNormal::Front(normal) => HitRecord::new(point, normal, t, true, &self.material),
+            FaceNormal::Back(normal) => HitRecord::new(point, normal, t, false, &self.material),
+        };
+        Some(rec.with_uv(u, v))
+    }
+
+    /// Stretch of `[t_min, t_max]` the ray spends inside the box, if any,
+    /// for media that fill it.
+    pub fn span(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(f64, f64)> {
+        let offset = &ray.origin - &self.center;
+        let (mut t0, mut t1) = (t_min, t_max);
+        for (a, h) in self.axes.iter().zip(self.half_extents) {
+            let o = offset.dot(a);
+            let d = ray.direction.dot(a);
+            if d == 0.0 {
+                if o.abs() > h {
+                    return None;
+                }
+                continue;
+            }
+            let (near, far) = ((-h - o) / d, (h - o) / d);
+            let (near, far) = if near < far { (near, far) } else { (far, near) };
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+        }
+        (t0 < t1).then_some((t0, t1))
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        let reach: [f64; 3] = std::array::from_fn(|j| {
+            self.axes
+                .iter()
+                .zip(self.half_extents)
+                .map(|(a, h)| a.data[j].abs() * h)
+                .sum()
+        });
+        let half = Vec3::new(reach[0], reach[1], reach[2]);
+        Aabb::new(&self.center - &half, &self.center + &half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn white() -> Material {
+        Material::lambertian(Color::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_axis_aligned() {
+        let cuboid = Cuboid::new(Vec3::new(1.0, 2.0, 3.0), Vec3::new(-1.0, 0.0, 1.0), white());
+        let ray = Ray::new(Vec3::new(5.0, 1.5, 2.0), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = cuboid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 4.0);
+        assert_eq!(rec.normal, Vec3::new(1.0, 0.0, 0.0));
+        assert!(rec.front_face);
+        assert_eq!((rec.u, rec.v), (0.75, 0.5));
+
+        // From inside the ray leaves through the far face.
+        let inside = Ray::new(Vec3::new(0.0, 1.0, 2.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = cuboid.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+        assert!(!rec.front_face);
+
+        let miss = Ray::new(Vec3::new(5.0, 2.5, 2.0), Vec3::new(-1.0, 0.0, 0.0));
+        assert!(cuboid.hit(&miss, 0.001, f64::INFINITY).is_none());
+
+        let bounds = cuboid.bounding_box();
+        assert_eq!((bounds.min, bounds.max), (Vec3::new(-1.0, 0.0, 1.0), Vec3::new(1.0, 2.0, 3.0)));
+    }
+
+    #[test]
+    fn test_oriented() {
+        // A unit cube turned 45 degrees around y.
+        let d = Vec3::new(1.0, 0.0, 1.0);
+        let cuboid = Cuboid::oriented(Vec3::new(0.0, 0.0, 0.0), [0.5, 0.5, 0.5], &d, &Vec3::new(0.0, 1.0, 0.0), white());
+        let ray = Ray::new(Vec3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0));
+        let rec = cuboid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let h = 0.5f64.sqrt();
+        assert!((rec.t - (5.0 - h)).abs() < 1e-12);
+        assert!(rec.normal.x() > 0.0 && rec.normal.y().abs() < 1e-12);
+
+        let bounds = cuboid.bounding_box();
+        assert!((bounds.max - Vec3::new(h, 0.5, h)).near_zero());
+
+        // The span inside runs corner to corner, clipped to the range.
+        let (enter, exit) = cuboid.span(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((enter - (5.0 - h)).abs() < 1e-12 && (exit - (5.0 + h)).abs() < 1e-12);
+        assert_eq!(cuboid.span(&ray, 5.0, 5.25), Some((5.0, 5.25)));
+        assert!(cuboid.span(&ray, 0.001, 1.0).is_none());
+    }
+}
@@ -0,0 +1,297 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::onb::Onb;
+use crate::light::LightSample;
+use crate::util::degrees_to_radians;
+
+use rand::Rng;
+use std::f64::consts::PI;
+
+/*
+ * A clear daylight sky after Preetham, Shirley and Smits, "A Practical
+ * Analytic Model for Daylight" (1999). The sky's luminance and
+ * chromaticity are Perez functions of the angle to the zenith and the
+ * angle to the sun, fitted for turbidity 2 (very clear) to 10 (hazy).
+ *
+ * The world's up axis is +y and north is -z, so east is +x. Radiance is
+ * in units of 10 kcd/m², which puts a clear midday zenith around one.
+ */
+
+/// Perez coefficients A to E of luminance Y and chromaticities x and y,
+/// as `[slope, offset]` in turbidity.
+const PEREZ: [[[f64; 2]; 5]; 3] = [
+    [[0.1787, -1.4630], [-0.3554, 0.4275], [-0.0227, 5.3251], [0.1206, -2.5771], [-0.0670, 0.3703]],
+    [[-0.0193, -0.2592], [-0.0665, 0.0008], [-0.0004, 0.2125], [-0.0641, -0.8989], [-0.0033, 0.0452]],
+    [[-0.0167, -0.2608], [-0.0950, 0.0092], [-0.0079, 0.2102], [-0.0441, -1.6537], [-0.0109, 0.0529]],
+];
+
+/// Zenith chromaticity x and y: rows for T², T and 1, each a cubic in the
+/// sun's zenith angle, highest power first.
+const ZENITH_X: [[f64; 4]; 3] = [
+    [0.00166, -0.00375, 0.00209, 0.0],
+    [-0.02903, 0.06377, -0.03202, 0.00394],
+    [0.11693, -0.21196, 0.06052, 0.25886],
+];
+const ZENITH_Y: [[f64; 4]; 3] = [
+    [0.00275, -0.00610, 0.00317, 0.0],
+    [-0.04214, 0.08970, -0.04153, 0.00516],
+    [0.15346, -0.26756, 0.06670, 0.26688],
+];
+
+/// Luminance of the sun above the atmosphere, in the sky's units.
+const SUN_LUMINANCE: f64 = 1.9e5;
+/// Angular radius of the sun.
+const SUN_RADIUS: f64 = 0.2667 * PI / 180.0;
+
+/// Wavelengths in micrometres the sun's transmittance is evaluated at,
+/// standing in for the red, green and blue channels.
+const WAVELENGTHS: [f64; 3] = [0.68, 0.55, 0.44];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Daylight {
+    /// Unit direction towards the sun.
+    sun: Vec3,
+    intensity: f64,
+    /// Perez coefficients of Y, x and y for the turbidity.
+    perez: [[f64; 5]; 3],
+    /// Y, x and y at the zenith, divided by the Perez function there so
+    /// that other directions only need their own.
+    zenith: [f64; 3],
+    sun_radiance: Color,
+    cos_sun_radius: f64,
+}
+
+fn perez(c: &[f64; 5], cos_theta: f64, gamma: f64) -> f64 {
+    (1.0 + c[0] * (c[1] / cos_theta.max(1e-4)).exp()) * (1.0 + c[2] * (c[3] * gamma).exp() + c[4] * gamma.cos().powi(2))
+}
+
+fn cubic(rows: &[[f64; 4]; 3], turbidity: f64, theta: f64) -> f64 {
+    let powers = [turbidity * turbidity, turbidity, 1.0];
+    rows.iter()
+        .zip(powers)
+        .map(|(c, t)| t * (((c[0] * theta + c[1]) * theta + c[2]) * theta + c[3]))
+        .sum()
+}
+
+/// Linear sRGB from luminance and chromaticity.
+fn yxy_to_rgb(luminance: f64, x: f64, y: f64) -> Color {
+    if y <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let (cx, cy, cz) = (x * luminance / y, luminance, (1.0 - x - y) * luminance / y);
+    Color::new(
+        (3.2406 * cx - 1.5372 * cy - 0.4986 * cz).max(0.0),
+        (-0.9689 * cx + 1.8758 * cy + 0.0415 * cz).max(0.0),
+        (0.0557 * cx - 0.2040 * cy + 1.0570 * cz).max(0.0),
+    )
+}
+
+impl Daylight {
+    /// `sun` points towards the sun; `turbidity` goes from 2 (very clear)
+    /// to 10 (hazy). Below the horizon the sun disappears and the sky stays
+    /// as it is at sunset, since the model doesn't cover twilight.
+    pub fn new(sun: &Vec3, turbidity: f64) -> Self {
+        let sun = sun.unit_vector();
+        let theta_sun = sun.y().clamp(0.0, 1.0).acos();
+
+        let coefficients = PEREZ.map(|channel| channel.map(|[slope, offset]| slope * turbidity + offset));
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_sun);
+        // Zenith luminance in kcd/m².
+        let luminance = ((4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192).max(0.0);
+        let zenith = [
+            0.1 * luminance,
+            cubic(&ZENITH_X, turbidity, theta_sun),
+            cubic(&ZENITH_Y, turbidity, theta_sun),
+        ];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez(&coefficients[i], 1.0, theta_sun));
+
+        // Rayleigh and aerosol extinction along the relative air mass.
+        let sun_radiance = if sun.y() > 0.0 {
+            let degrees = theta_sun.to_degrees();
+            let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - degrees).powf(-1.253));
+            let beta = 0.04608 * turbidity - 0.04586;
+            let [r, g, b] = WAVELENGTHS.map(|l: f64| {
+                (-0.008735 * l.powf(-4.08) * air_mass).exp() * (-beta * l.powf(-1.3) * air_mass).exp()
+            });
+            SUN_LUMINANCE * Color::new(r, g, b)
+        } else {
+            Color::new(0.0, 0.0, 0.0)
+        };
+
+        Self { sun, intensity: 1.0, perez: coefficients, zenith, sun_radiance, cos_sun_radius: SUN_RADIUS.cos() }
+    }
+
+    /// Scales the sky and the sun.
+    pub fn with_intensity(mut self, intensity: f64) -> Self {
+        self.intensity = intensity;
+        self
+    }
+
+    fn sun_visible(&self) -> bool {
+        !self.sun_radiance.near_zero()
+    }
+
+    /// Radiance of the sky, and of the sun's disk, towards `direction`.
+    /// Below the horizon the sky continues as it is at the horizon.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let d = direction.unit_vector();
+        let cos_gamma = d.dot(&self.sun).clamp(-1.0, 1.0);
+        if self.sun_visible() && cos_gamma >= self.cos_sun_radius {
+            return self.intensity * &self.sun_radiance;
+        }
+        let (cos_theta, gamma) = (d.y().max(0.0), cos_gamma.acos());
+        let [luminance, x, y] = [0, 1, 2].map(|i| self.zenith[i] * perez(&self.perez[i], cos_theta, gamma));
+        self.intensity * yxy_to_rgb(luminance, x, y)
+    }
+
+    /// Picks a direction towards the sun's disk; the sky itself is only
+    /// found by scattered rays.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        if !self.sun_visible() {
+            return None;
+        }
+        let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - self.cos_sun_radius);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * rng.gen::<f64>();
+        let direction = Onb::from_w(&self.sun).local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta).unit_vector();
+        Some(LightSample {
+            direction,
+            distance: f64::INFINITY,
+            pdf: 1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius)),
+            radiance: self.intensity * &self.sun_radiance,
+        })
+    }
+
+    /// Solid angle density of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        if self.sun_visible() && direction.unit_vector().dot(&self.sun) >= self.cos_sun_radius {
+            1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Unit direction of something `elevation` degrees above the horizon and
+/// `azimuth` degrees clockwise from north, seen from above.
+pub fn direction(elevation: f64, azimuth: f64) -> Vec3 {
+    let (elevation, azimuth) = (degrees_to_radians(elevation), degrees_to_radians(azimuth));
+    Vec3::new(azimuth.sin() * elevation.cos(), elevation.sin(), -azimuth.cos() * elevation.cos())
+}
+
+/// Day of the year, from 1 on January 1st, or `None` for dates that don't
+/// exist.
+pub fn day_of_year(year: i32, month: u32, day: u32) -> Option<u32> {
+    let leap = (year % 4 == 0 && year % 100 != 0) || year % 400 == 0;
+    let days = [31, if leap { 29 } else { 28 }, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31];
+    let month_days = *days.get((month as usize).checked_sub(1)?)?;
+    if day == 0 || day > month_days {
+        return None;
+    }
+    Some(days[..month as usize - 1].iter().sum::<u32>() + day)
+}
+
+/// Elevation and azimuth of the sun in degrees, seen from `latitude`
+/// (north positive) and `longitude` (east positive) on `day` of the year
+/// at `hours` past midnight UTC, using NOAA's low accuracy equations
+/// (good to a fraction of a degree).
+pub fn sun_position(latitude: f64, longitude: f64, day: u32, hours: f64) -> (f64, f64) {
+    let year = 2.0 * PI / 365.0 * (day as f64 - 1.0 + (hours - 12.0) / 24.0);
+    let (s1, c1, s2, c2, s3, c3) = (year.sin(), year.cos(), (2.0 * year).sin(), (2.0 * year).cos(), (3.0 * year).sin(), (3.0 * year).cos());
+    // Minutes by which solar time runs ahead of mean time.
+    let equation_of_time = 229.18 * (0.000075 + 0.001868 * c1 - 0.032077 * s1 - 0.014615 * c2 - 0.040849 * s2);
+    let declination = 0.006918 - 0.399912 * c1 + 0.070257 * s1 - 0.006758 * c2 + 0.000907 * s2 - 0.002697 * c3 + 0.00148 * s3;
+
+    let solar_minutes = hours * 60.0 + equation_of_time + 4.0 * longitude;
+    let hour_angle = degrees_to_radians(solar_minutes / 4.0 - 180.0);
+    let latitude = degrees_to_radians(latitude);
+
+    let sin_elevation = latitude.sin() * declination.sin() + latitude.cos() * declination.cos() * hour_angle.cos();
+    let elevation = sin_elevation.clamp(-1.0, 1.0).asin();
+    // Measured from the south, towards the west.
+    let from_south = hour_angle.sin().atan2(hour_angle.cos() * latitude.sin() - declination.tan() * latitude.cos());
+    (elevation.to_degrees(), (from_south.to_degrees() + 180.0).rem_euclid(360.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn luminance(c: &Color) -> f64 {
+        0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+    }
+
+    #[test]
+    fn test_sun_position() {
+        assert_eq!(day_of_year(2024, 3, 1), Some(61));
+        assert_eq!(day_of_year(2023, 3, 1), Some(60));
+        assert_eq!(day_of_year(2023, 2, 29), None);
+        assert_eq!(day_of_year(2023, 13, 1), None);
+
+        // Greenwich at noon on the June solstice: due south, 90° minus the
+        // latitude plus the tilt of the earth's axis.
+        let day = day_of_year(2024, 6, 20).unwrap();
+        let (elevation, azimuth) = sun_position(51.48, 0.0, day, 12.0);
+        assert!((elevation - (90.0 - 51.48 + 23.44)).abs() < 0.5, "{}", elevation);
+        assert!((azimuth - 180.0).abs() < 1.0, "{}", azimuth);
+
+        // Rising in the east and setting in the west, in the north towards
+        // the equator in the southern hemisphere.
+        let (morning, east) = sun_position(51.48, 0.0, day, 6.0);
+        let (evening, west) = sun_position(51.48, 0.0, day, 18.0);
+        assert!(morning > 0.0 && evening > 0.0 && east < 90.0 && west > 270.0);
+        let (elevation, azimuth) = sun_position(-33.87, 151.21, day, 2.0);
+        assert!((25.0..35.0).contains(&elevation) && !(10.0..=350.0).contains(&azimuth));
+        let (night, _) = sun_position(51.48, 0.0, day, 0.0);
+        assert!(night < 0.0);
+
+        let south = direction(0.0, 180.0);
+        assert!((south - Vec3::new(0.0, 0.0, 1.0)).near_zero());
+    }
+
+    #[test]
+    fn test_sky() {
+        let sun = direction(30.0, 90.0);
+        let sky = Daylight::new(&sun, 3.0);
+
+        // The zenith is the fitted zenith luminance, around 10 kcd/m² here.
+        let zenith = luminance(&sky.radiance(&Vec3::new(0.0, 1.0, 0.0)));
+        let chi = (4.0 / 9.0 - 3.0 / 120.0) * (PI - 2.0 * PI / 3.0);
+        let expected = 0.1 * ((4.0453 * 3.0 - 4.9710) * chi.tan() - 0.2155 * 3.0 + 2.4192);
+        assert!((zenith - expected).abs() < 0.01 * expected, "{} != {}", zenith, expected);
+
+        // Brighter around the sun than opposite it, and bluer overhead than
+        // at the horizon.
+        let near = sky.radiance(&direction(30.0, 80.0));
+        let far = sky.radiance(&direction(30.0, 270.0));
+        assert!(luminance(&near) > 2.0 * luminance(&far));
+        let up = sky.radiance(&direction(80.0, 270.0));
+        let horizon = sky.radiance(&direction(1.0, 270.0));
+        assert!(up.b() / up.r() > horizon.b() / horizon.r());
+
+        // The sun itself is much brighter, and redder when low.
+        let disk = sky.radiance(&sun);
+        assert!(luminance(&disk) > 1e4);
+        let low = Daylight::new(&direction(3.0, 90.0), 3.0).radiance(&direction(3.0, 90.0));
+        assert!(low.b() / low.r() < disk.b() / disk.r());
+        let night = Daylight::new(&direction(-10.0, 90.0), 3.0);
+        assert!(luminance(&night.radiance(&direction(-10.0, 90.0))) < 10.0);
+        assert!(night.sample(&mut StdRng::seed_from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn test_sample_sun() {
+        let sun = direction(45.0, 200.0);
+        let sky = Daylight::new(&sun, 4.0).with_intensity(0.5);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let sample = sky.sample(&mut rng).unwrap();
+            assert!(sample.direction.dot(&sun) >= sky.cos_sun_radius - 1e-12);
+            assert_eq!(sample.pdf, sky.pdf(&sample.direction));
+            assert_eq!(sample.radiance, sky.radiance(&sample.direction));
+        }
+        assert_eq!(sky.pdf(&Vec3::new(0.0, 1.0, 0.0)), 0.0);
+    }
+}
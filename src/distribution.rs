@@ -0,0 +1,148 @@
+/*
+ * Piecewise constant densities for importance sampling tabulated
+ * functions, such as the brightness of an environment map.
+ */
+
+/// Density over [0, 1) proportional to a function that is constant on
+/// each of `func.len()` equal steps.
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    /// Running integral, normalized to end at one.
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Negative values count as zero. A function that is zero everywhere
+    /// gives the uniform density.
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "distribution needs at least one step");
+        let n = func.len() as f64;
+        let func: Vec<f64> = func.into_iter().map(|f| if f.is_finite() { f.max(0.0) } else { 0.0 }).collect();
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for f in &func {
+            cdf.push(cdf.last().unwrap() + f / n);
+        }
+        let integral = *cdf.last().unwrap();
+        if integral > 0.0 {
+            cdf.iter_mut().for_each(|c| *c /= integral);
+        } else {
+            cdf.iter_mut().enumerate().for_each(|(i, c)| *c = i as f64 / n);
+        }
+        Self { func, cdf, integral }
+    }
+
+    /// Integral of the function over [0, 1).
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    /// Maps a uniform `u` in [0, 1) to a point in [0, 1), returning it
+    /// with its density and the step it falls in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        // Last step whose start is at or below u, skipping empty ones.
+        let i = self.cdf.partition_point(|&c| c <= u).clamp(1, self.count()) - 1;
+        let width = self.cdf[i + 1] - self.cdf[i];
+        let offset = if width > 0.0 { ((u - self.cdf[i]) / width).clamp(0.0, 1.0) } else { 0.0 };
+        let x = ((i as f64 + offset) / self.count() as f64).min(1.0 - f64::EPSILON);
+        (x, self.pdf(x), i)
+    }
+
+    /// Density of `sample` returning `x`.
+    pub fn pdf(&self, x: f64) -> f64 {
+        let i = ((x * self.count() as f64) as usize).min(self.count() - 1);
+        if self.integral > 0.0 {
+            self.func[i] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Density over the unit square proportional to a function tabulated on a
+/// grid, sampled by picking a row from the marginal density and then a
+/// column within it.
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `func` holds `height` rows of `width` values, top row first.
+    pub fn new(func: &[f64], width: usize, height: usize) -> Self {
+        assert_eq!(func.len(), width * height, "distribution needs one value per cell");
+        let rows: Vec<Distribution1D> = func.chunks(width).map(|row| Distribution1D::new(row.to_vec())).collect();
+        let marginal = Distribution1D::new(rows.iter().map(Distribution1D::integral).collect());
+        Self { rows, marginal }
+    }
+
+    /// Maps two uniform numbers to a point `(u, v)` in the unit square,
+    /// `u` across the rows and `v` down, and its density.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (v, _, row) = self.marginal.sample(u2);
+        let (u, _, _) = self.rows[row].sample(u1);
+        ((u, v), self.pdf(u, v))
+    }
+
+    pub fn pdf(&self, u: f64, v: f64) -> f64 {
+        let row = ((v * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        self.marginal.pdf(v) * self.rows[row].pdf(u)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sample_1d() {
+        // Steps of weight 0, 1 and 3: nothing lands in the first.
+        let d = Distribution1D::new(vec![0.0, 1.0, 3.0]);
+        assert!((d.integral() - 4.0 / 3.0).abs() < 1e-12);
+        let (x, pdf, i) = d.sample(0.0);
+        assert_eq!(i, 1);
+        assert!((x - 1.0 / 3.0).abs() < 1e-12);
+        assert!((pdf - 0.75).abs() < 1e-12);
+        let (x, pdf, i) = d.sample(0.625);
+        assert_eq!(i, 2);
+        assert!((x - 5.0 / 6.0).abs() < 1e-12);
+        assert!((pdf - 2.25).abs() < 1e-12);
+        assert!(d.sample(1.0 - 1e-12).0 < 1.0);
+
+        // All zero is uniform.
+        let flat = Distribution1D::new(vec![0.0; 4]);
+        let (x, pdf, _) = flat.sample(0.3);
+        assert!((x - 0.3).abs() < 1e-12 && pdf == 1.0);
+    }
+
+    #[test]
+    fn test_sample_2d() {
+        // A bright cell dominates and the density integrates to one.
+        let mut func = vec![1.0; 12];
+        func[7] = 100.0;
+        let d = Distribution2D::new(&func, 4, 3);
+        let n = 60;
+        let mut integral = 0.0;
+        let mut bright = 0;
+        for a in 0..n {
+            for b in 0..n {
+                let ((u, v), pdf) = d.sample((a as f64 + 0.5) / n as f64, (b as f64 + 0.5) / n as f64);
+                assert!((pdf - d.pdf(u, v)).abs() < 1e-9);
+                if (u * 4.0) as usize == 3 && (v * 3.0) as usize == 1 {
+                    bright += 1;
+                }
+                integral += d.pdf((a as f64 + 0.5) / n as f64, (b as f64 + 0.5) / n as f64) / (n * n) as f64;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-9);
+        let share = bright as f64 / (n * n) as f64;
+        assert!((share - 100.0 / 111.0).abs() < 0.01, "{}", share);
+    }
+}
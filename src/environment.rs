@@ -0,0 +1,180 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::image::Image;
+use crate::light::LightSample;
+use crate::distribution::Distribution2D;
+use crate::util::degrees_to_radians;
+
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Light arriving from infinitely far away, read from an equirectangular
+/// image: longitude across, from -180° at the left edge, and latitude
+/// down, from straight up at the top row. The middle of the image is
+/// towards -z.
+///
+/// Directions are sampled in proportion to the brightness of each pixel,
+/// so small bright features like the sun are found by shadow rays instead
+/// of by chance.
+pub struct Environment {
+    image: Image,
+    /// Turn around the up axis, in radians.
+    rotation: f64,
+    intensity: f64,
+    distribution: Distribution2D,
+}
+
+impl fmt::Debug for Environment {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Environment")
+            .field("width", &self.image.width)
+            .field("height", &self.image.height)
+            .field("rotation", &self.rotation)
+            .field("intensity", &self.intensity)
+            .finish()
+    }
+}
+
+/// Maps are only equal to themselves; comparing their pixels isn't worth it.
+impl PartialEq for Environment {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+fn luminance(c: &Color) -> f64 {
+    0.2126 * c.r() + 0.7152 * c.g() + 0.0722 * c.b()
+}
+
+impl Environment {
+    /// `rotation` turns the map around the up axis, in degrees,
+    /// counterclockwise seen from above. `intensity` scales its radiance.
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> Self {
+        let (width, height) = (image.width, image.height);
+        // Rows near the poles cover less solid angle.
+        let weights: Vec<f64> = image
+            .pixels
+            .iter()
+            .enumerate()
+            .map(|(i, c)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                luminance(c) * theta.sin()
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, width, height);
+        Self { image, rotation: degrees_to_radians(rotation), intensity, distribution }
+    }
+
+    /// Turns `v` around the up axis by `angle`.
+    fn turn(v: &Vec3, angle: f64) -> Vec3 {
+        let (sin, cos) = angle.sin_cos();
+        Vec3::new(cos * v.x() + sin * v.z(), v.y(), cos * v.z() - sin * v.x())
+    }
+
+    /// Image coordinates in [0, 1) of a world direction.
+    fn uv(&self, direction: &Vec3) -> (f64, f64) {
+        let d = Self::turn(&direction.unit_vector(), -self.rotation);
+        let u = 0.5 + d.x().atan2(-d.z()) / (2.0 * PI);
+        let v = d.y().clamp(-1.0, 1.0).acos() / PI;
+        (u.rem_euclid(1.0), v.min(1.0 - f64::EPSILON))
+    }
+
+    fn lookup(&self, u: f64, v: f64) -> Color {
+        let col = ((u * self.image.width as f64) as usize).min(self.image.width - 1);
+        let row = ((v * self.image.height as f64) as usize).min(self.image.height - 1);
+        self.intensity * &self.image.pixels[row * self.image.width + col]
+    }
+
+    /// Radiance arriving from `direction`.
+    pub fn radiance(&self, direction: &Vec3) -> Color {
+        let (u, v) = self.uv(direction);
+        self.lookup(u, v)
+    }
+
+    /// Picks a direction towards the environment, by brightness.
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> Option<LightSample> {
+        let ((u, v), pdf) = self.distribution.sample(rng.gen(), rng.gen());
+        let (theta, phi) = (v * PI, (u - 0.5) * 2.0 * PI);
+        let sin_theta = theta.sin();
+        if pdf <= 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let local = Vec3::new(sin_theta * phi.sin(), theta.cos(), -sin_theta * phi.cos());
+        Some(LightSample {
+            direction: Self::turn(&local, self.rotation),
+            distance: f64::INFINITY,
+            // From the unit square to solid angle.
+            pdf: pdf / (2.0 * PI * PI * sin_theta),
+            radiance: self.lookup(u, v),
+        })
+    }
+
+    /// Solid angle density of `sample` picking `direction`.
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        let (u, v) = self.uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    /// A dim map with one bright pixel in the row above the horizon.
+    fn sun() -> Image {
+        let (width, height) = (16, 8);
+        let mut pixels = vec![Color::new(0.1, 0.1, 0.1); width * height];
+        pixels[3 * width + 8] = Color::new(1000.0, 900.0, 800.0);
+        Image::new(width, height, pixels)
+    }
+
+    #[test]
+    fn test_directions_and_rotation() {
+        let env = Environment::new(sun(), 0.0, 2.0);
+        // The middle of the image is towards -z, a bit above the horizon.
+        let towards = Vec3::new(0.0, (PI * 3.5 / 8.0).cos(), -(PI * 3.5 / 8.0).sin());
+        assert_eq!(env.radiance(&towards), Color::new(2000.0, 1800.0, 1600.0));
+        assert_eq!(env.radiance(&Vec3::new(0.0, 1.0, 0.0)), Color::new(0.2, 0.2, 0.2));
+
+        // Turned a quarter counterclockwise, -z goes to -x.
+        let turned = Environment::new(sun(), 90.0, 2.0);
+        let towards = Vec3::new(towards.z(), towards.y(), 0.0);
+        assert_eq!(turned.radiance(&towards), Color::new(2000.0, 1800.0, 1600.0));
+    }
+
+    #[test]
+    fn test_importance_sampling() {
+        let env = Environment::new(sun(), 30.0, 1.0);
+        let mut rng = StdRng::seed_from_u64(9);
+        // Samples match their density, and estimate the irradiance over
+        // the whole sphere as well as a uniform estimate would.
+        let n = 20000;
+        let mut estimate = 0.0;
+        let mut bright = 0;
+        for _ in 0..n {
+            let sample = env.sample(&mut rng).unwrap();
+            assert!((sample.pdf - env.pdf(&sample.direction)).abs() <= 1e-6 * sample.pdf);
+            assert_eq!(sample.radiance, env.radiance(&sample.direction));
+            estimate += sample.radiance.g() / sample.pdf / n as f64;
+            if sample.radiance.g() > 1.0 {
+                bright += 1;
+            }
+        }
+        // Each pixel covers 2π²/128 sin θ of solid angle.
+        let expected: f64 = (0..8)
+            .map(|row| {
+                let solid_angle = 2.0 * PI * PI / 128.0 * (PI * (row as f64 + 0.5) / 8.0).sin();
+                solid_angle * if row == 3 { 15.0 * 0.1 + 900.0 } else { 16.0 * 0.1 }
+            })
+            .sum();
+        assert!((estimate - expected).abs() < 0.02 * expected, "{} != {}", estimate, expected);
+        assert!(bright as f64 > 0.9 * n as f64);
+    }
+}
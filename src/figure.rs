@@ -1,13 +1,39 @@
 use crate::hitrecord::{HitRecord, FaceNormal};
 use crate::vec3::Vec3;
 use crate::ray::Ray;
-use crate::color::Color;
 use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::mesh::{Triangle, Mesh};
+use crate::instance::Instance;
+use crate::planar::{Plane, Quad, Disk};
+use crate::cuboid::Cuboid;
+use crate::quadric::{Cylinder, Cone, Quadric};
+use crate::torus::Torus;
+use crate::csg::{Csg, CsgOp};
+use crate::medium::{ConstantMedium, GridMedium};
+use crate::matrix::Matrix4;
+use crate::util::clamp;
 
-use std::rc::Rc;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 pub enum Figure {
-    Sphere(Sphere)
+    Sphere(Sphere),
+    MovingSphere(MovingSphere),
+    Triangle(Triangle),
+    Mesh(Mesh),
+    Instance(Instance),
+    Plane(Plane),
+    Quad(Quad),
+    Disk(Disk),
+    Box(Cuboid),
+    Cylinder(Cylinder),
+    Cone(Cone),
+    Torus(Torus),
+    Quadric(Quadric),
+    Csg(Csg),
+    Medium(ConstantMedium),
+    Grid(GridMedium),
 }
 
 impl Figure {
@@ -15,10 +41,121 @@ impl Figure {
         Self::Sphere(Sphere::new(center, radius, mat))
     }
 
+    pub fn moving_sphere(center0: Vec3, center1: Vec3, time0: f64, time1: f64, radius: f64, mat: Material) -> Self {
+        Self::MovingSphere(MovingSphere::new(center0, center1, time0, time1, radius, mat))
+    }
+
+    pub fn triangle(v0: Vec3, v1: Vec3, v2: Vec3, mat: Material) -> Self {
+        Self::Triangle(Triangle::new(v0, v1, v2, mat))
+    }
+
+    pub fn mesh(mesh: Mesh) -> Self {
+        Self::Mesh(mesh)
+    }
+
+    pub fn plane(point: Vec3, normal: Vec3, mat: Material) -> Self {
+        Self::Plane(Plane::new(point, normal, mat))
+    }
+
+    pub fn quad(q: Vec3, u: Vec3, v: Vec3, mat: Material) -> Self {
+        Self::Quad(Quad::new(q, u, v, mat))
+    }
+
+    pub fn disk(center: Vec3, normal: Vec3, radius: f64, mat: Material) -> Self {
+        Self::Disk(Disk::new(center, normal, radius, mat))
+    }
+
+    /// Axis-aligned box between two opposite corners. Named after the
+    /// `Cuboid` it holds, since `box` is a keyword.
+    pub fn cuboid(a: Vec3, b: Vec3, mat: Material) -> Self {
+        Self::Box(Cuboid::new(a, b, mat))
+    }
+
+    pub fn cylinder(base: Vec3, top: Vec3, radius: f64, capped: bool, mat: Material) -> Self {
+        Self::Cylinder(Cylinder::new(base, top, radius, capped, mat))
+    }
+
+    /// Cone or frustum; a zero radius puts the apex at that end.
+    pub fn cone(base: Vec3, top: Vec3, base_radius: f64, top_radius: f64, capped: bool, mat: Material) -> Self {
+        Self::Cone(Cone::new(base, top, base_radius, top_radius, capped, mat))
+    }
+
+    pub fn torus(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64, mat: Material) -> Self {
+        Self::Torus(Torus::new(center, axis, major_radius, minor_radius, mat))
+    }
+
+    /// Zero set of a quadratic polynomial in x, y and z, clipped to `bounds`.
+    pub fn quadric(coefficients: [f64; 10], bounds: Option<Aabb>, mat: Material) -> Self {
+        Self::Quadric(Quadric::new(coefficients, bounds, mat))
+    }
+
+    /// Boolean combination of two closed figures.
+    pub fn csg(op: CsgOp, left: Figure, right: Figure) -> Self {
+        Self::Csg(Csg::new(op, left, right))
+    }
+
+    /// Uniform fog filling a closed `boundary`, scattering with the
+    /// phase function `phase`.
+    pub fn constant_medium(boundary: Figure, density: f64, phase: Material) -> Self {
+        Self::Medium(ConstantMedium::new(boundary, density, phase))
+    }
+
+    /// Places shared geometry with an invertible object-to-world transform.
+    pub fn instance(object: Arc<Figure>, transform: Matrix4) -> Self {
+        Self::Instance(Instance::new(object, transform))
+    }
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
         match self {
-            Figure::Sphere(sphere) => sphere.hit(ray, t_min, t_max)
+            Figure::Sphere(sphere) => sphere.hit(ray, t_min, t_max),
+            Figure::MovingSphere(sphere) => sphere.hit(ray, t_min, t_max),
+            Figure::Triangle(triangle) => triangle.hit(ray, t_min, t_max),
+            Figure::Mesh(mesh) => mesh.hit(ray, t_min, t_max),
+            Figure::Instance(instance) => instance.hit(ray, t_min, t_max),
+            Figure::Plane(plane) => plane.hit(ray, t_min, t_max),
+            Figure::Quad(quad) => quad.hit(ray, t_min, t_max),
+            Figure::Disk(disk) => disk.hit(ray, t_min, t_max),
+            Figure::Box(cuboid) => cuboid.hit(ray, t_min, t_max),
+            Figure::Cylinder(cylinder) => cylinder.hit(ray, t_min, t_max),
+            Figure::Cone(cone) => cone.hit(ray, t_min, t_max),
+            Figure::Torus(torus) => torus.hit(ray, t_min, t_max),
+            Figure::Quadric(quadric) => quadric.hit(ray, t_min, t_max),
+            Figure::Csg(csg) => csg.hit(ray, t_min, t_max),
+            Figure::Medium(medium) => medium.hit(ray, t_min, t_max),
+            Figure::Grid(grid) => grid.hit(ray, t_min, t_max),
+        }
+    }
+
+    /// Fraction of light passing along the ray between `t_min` and
+    /// `t_max`: none through surfaces, an unbiased estimate through media.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        match self {
+            Figure::Medium(medium) => medium.transmittance(ray, t_min, t_max),
+            Figure::Grid(grid) => grid.transmittance(ray, t_min, t_max),
+            _ if self.hit(ray, t_min, t_max).is_some() => 0.0,
+            _ => 1.0,
+        }
+    }
+
+    /// Box enclosing the figure, or `None` if it is unbounded.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        match self {
+            Figure::Sphere(sphere) => Some(sphere.bounding_box()),
+            Figure::MovingSphere(sphere) => Some(sphere.bounding_box()),
+            Figure::Triangle(triangle) => Some(triangle.bounding_box()),
+            Figure::Mesh(mesh) => mesh.bounding_box(),
+            Figure::Instance(instance) => instance.bounding_box(),
+            Figure::Plane(_) => None,
+            Figure::Quad(quad) => Some(quad.bounding_box()),
+            Figure::Disk(disk) => Some(disk.bounding_box()),
+            Figure::Box(cuboid) => Some(cuboid.bounding_box()),
+            Figure::Cylinder(cylinder) => Some(cylinder.bounding_box()),
+            Figure::Cone(cone) => Some(cone.bounding_box()),
+            Figure::Torus(torus) => Some(torus.bounding_box()),
+            Figure::Quadric(quadric) => quadric.bounding_box(),
+            Figure::Csg(csg) => csg.bounding_box(),
+            Figure::Medium(medium) => medium.bounding_box(),
+            Figure::Grid(grid) => Some(grid.bounding_box()),
         }
     }
 }
@@ -37,34 +174,139 @@ impl Sphere {
 }
 
 impl Sphere {
-    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let oc = &ray.origin - &self.center;
-        let a = ray.direction.length_squared();
-        let half_b = oc.dot(&ray.direction);
-        let c = oc.length_squared() - self.radius * self.radius;
-        let discriminant = half_b * half_b - a * c;
-
-        if discriminant < 0.0 {
-            return None;
-        }
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        hit_sphere(&self.center, self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    /// Longitude and latitude of a point on the unit sphere, scaled to
+    /// [0, 1]: u goes around the y axis starting at -x, v from the south
+    /// pole (-y) to the north pole.
+    pub fn uv(p: &Vec3) -> (f64, f64) {
+        let theta = clamp(-p.y(), -1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // Hollow glass uses a negative radius, so size the box by magnitude.
+        let r = self.radius.abs();
+        let rvec = Vec3::new(r, r, r);
+        Aabb::new(&self.center - &rvec, &self.center + &rvec)
+    }
+
+}
+
+fn hit_sphere<'a>(center: &Vec3, radius: f64, material: &'a Material, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'a>> {
+    let oc = &ray.origin - center;
+    let a = ray.direction.length_squared();
+    let half_b = oc.dot(&ray.direction);
+    let c = oc.length_squared() - radius * radius;
+    let discriminant = half_b * half_b - a * c;
 
-        let sqrtd = discriminant.sqrt();
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let sqrtd = discriminant.sqrt();
 
-        // Find the nearest root that lies in the acceptable range.
-        let mut root = (-half_b - sqrtd) / a;
+    // Find the nearest root that lies in the acceptable range.
+    let mut root = (-half_b - sqrtd) / a;
+    if root < t_min || t_max < root {
+        root = (-half_b + sqrtd) / a;
         if root < t_min || t_max < root {
-            root = (-half_b + sqrtd) / a;
-            if root < t_min || t_max < root {
-                return None;
-            }
+            return None;
         }
+    }
 
-        let point = ray.at(root);
-        let outward_normal = (&point - &self.center) / self.radius;
-        match HitRecord::get_face_normal(ray, &outward_normal) {
-            FaceNormal::Front(normal) => Some(HitRecord::new(point, normal, root, true, &self.material)),
-            FaceNormal::Back(normal) => Some(HitRecord::new(point, normal, root, false, &self.material))
-        }
+    let point = ray.at(root);
+    let outward_normal = (&point - center) / radius;
+    let (u, v) = Sphere::uv(&((&point - center) / radius.abs()));
+    let rec = match HitRecord::get_face_normal(ray, &outward_normal) {
+        FaceNormal::Front(normal) => HitRecord::new(point, normal, root, true, material),
+        FaceNormal::Back(normal) => HitRecord::new(point, normal, root, false, material)
+    };
+    Some(rec.with_uv(u, v))
+}
+
+/// A sphere whose center moves linearly from `center0` at `time0` to
+/// `center1` at `time1`, resting at either end outside that interval.
+#[derive(Debug, Clone)]
+pub struct MovingSphere {
+    pub center0: Vec3,
+    pub center1: Vec3,
+    pub time0: f64,
+    pub time1: f64,
+    pub radius: f64,
+    pub material: Material,
+}
+
+impl MovingSphere {
+    pub fn new(center0: Vec3, center1: Vec3, time0: f64, time1: f64, radius: f64, material: Material) -> Self {
+        assert!(time1 > time0, "moving sphere keyframes must be in order");
+        Self { center0, center1, time0, time1, radius, material }
+    }
+
+    pub fn center(&self, time: f64) -> Vec3 {
+        let s = clamp((time - self.time0) / (self.time1 - self.time0), 0.0, 1.0);
+        &self.center0 + (s * (&self.center1 - &self.center0))
     }
 
+    fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        hit_sphere(&self.center(ray.time), self.radius, &self.material, ray, t_min, t_max)
+    }
+
+    /// Covers the sphere at every time, since it never leaves the segment
+    /// between its keyframes.
+    fn bounding_box(&self) -> Aabb {
+        let r = self.radius.abs();
+        let rvec = Vec3::new(r, r, r);
+        let box0 = Aabb::new(&self.center0 - &rvec, &self.center0 + &rvec);
+        let box1 = Aabb::new(&self.center1 - &rvec, &self.center1 + &rvec);
+        box0.surrounding(&box1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    #[test]
+    fn test_sphere_uv() {
+        let close = |(u, v): (f64, f64), (eu, ev): (f64, f64)| (u - eu).abs() < 1e-12 && (v - ev).abs() < 1e-12;
+        assert!(close(Sphere::uv(&Vec3::new(1.0, 0.0, 0.0)), (0.5, 0.5)));
+        assert!(close(Sphere::uv(&Vec3::new(0.0, 1.0, 0.0)), (0.5, 1.0)));
+        assert!(close(Sphere::uv(&Vec3::new(0.0, -1.0, 0.0)), (0.5, 0.0)));
+        assert!(close(Sphere::uv(&Vec3::new(-1.0, 0.0, 0.0)), (0.0, 0.5)));
+        assert!(close(Sphere::uv(&Vec3::new(0.0, 0.0, 1.0)), (0.25, 0.5)));
+        assert!(close(Sphere::uv(&Vec3::new(0.0, 0.0, -1.0)), (0.75, 0.5)));
+
+        // Hollow spheres map the same point to the same coordinates.
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let m = Material::lambertian(Color::new(1.0, 1.0, 1.0));
+        let solid = Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 1.0, m.clone());
+        let hollow = Figure::sphere(Vec3::new(0.0, 0.0, 0.0), -1.0, m);
+        let a = solid.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let b = hollow.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert_eq!((a.u, a.v), (b.u, b.v));
+        assert!(close((a.u, a.v), (0.25, 0.5)));
+    }
+
+    #[test]
+    fn test_moving_sphere() {
+        let m = Material::lambertian(Color::new(1.0, 1.0, 1.0));
+        let sphere = Figure::moving_sphere(Vec3::new(0.0, 0.0, 0.0), Vec3::new(4.0, 0.0, 0.0), 0.0, 1.0, 1.0, m);
+        let down = |x: f64, time: f64| Ray::new(Vec3::new(x, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).with_time(time);
+
+        assert!(sphere.hit(&down(0.0, 0.0), 0.001, f64::INFINITY).is_some());
+        assert!(sphere.hit(&down(4.0, 0.0), 0.001, f64::INFINITY).is_none());
+        assert!(sphere.hit(&down(2.0, 0.5), 0.001, f64::INFINITY).is_some());
+        assert!(sphere.hit(&down(4.0, 1.0), 0.001, f64::INFINITY).is_some());
+        // It rests at the last keyframe, inside its bounding box.
+        assert!(sphere.hit(&down(4.0, 3.0), 0.001, f64::INFINITY).is_some());
+
+        let bounds = sphere.bounding_box().unwrap();
+        assert_eq!(bounds.min, Vec3::new(-1.0, -1.0, -1.0));
+        assert_eq!(bounds.max, Vec3::new(5.0, 1.0, 1.0));
+    }
 }
@@ -0,0 +1,287 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/*
+ * Voxel grids come as text or binary files with the same layout. Text:
+ *
+ *   # comments run to the end of the line
+ *   grid 4 4 2 temperature emission
+ *   0.5 1200 0 0 0
+ *   ...
+ *
+ * The header names the size along x, y and z, then the optional channels.
+ * Each voxel follows with its density, then its temperature in kelvin and
+ * its emitted color if present, x varying fastest and z slowest.
+ *
+ * Binary files start with the bytes `VGRD`, then little-endian u32 sizes
+ * along x, y and z and a u32 with bit 0 set for temperature and bit 1 for
+ * emission, then the same values per voxel as little-endian f32s.
+ */
+
+const MAGIC: &[u8; 4] = b"VGRD";
+const TEMPERATURE: u32 = 1;
+const EMISSION: u32 = 2;
+
+/// Densities on a regular grid spanning the unit cube, with optional
+/// temperature and emission channels. Values are taken at voxel centers
+/// and interpolated trilinearly between them.
+pub struct VoxelGrid {
+    pub size: [usize; 3],
+    density: Vec<f32>,
+    temperature: Option<Vec<f32>>,
+    emission: Option<Vec<[f32; 3]>>,
+    max_density: f64,
+}
+
+impl fmt::Debug for VoxelGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("VoxelGrid")
+            .field("size", &self.size)
+            .field("temperature", &self.temperature.is_some())
+            .field("emission", &self.emission.is_some())
+            .finish()
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+impl VoxelGrid {
+    pub fn new(
+        size: [usize; 3],
+        density: Vec<f32>,
+        temperature: Option<Vec<f32>>,
+        emission: Option<Vec<[f32; 3]>>,
+    ) -> io::Result<VoxelGrid> {
+        let count = size.iter().try_fold(1usize, |n, s| n.checked_mul(*s)).ok_or_else(|| invalid("voxel grid is too large"))?;
+        if count == 0 {
+            return Err(invalid("voxel grid is empty"));
+        }
+        let matches = density.len() == count
+            && temperature.as_ref().is_none_or(|t| t.len() == count)
+            && emission.as_ref().is_none_or(|e| e.len() == count);
+        if !matches {
+            return Err(invalid("voxel grid needs one value per voxel in every channel"));
+        }
+        if density.iter().any(|d| !(d.is_finite() && *d >= 0.0)) {
+            return Err(invalid("voxel densities must be finite and non-negative"));
+        }
+        let max_density = density.iter().fold(0.0f32, |a, b| a.max(*b)) as f64;
+        Ok(VoxelGrid { size, density, temperature, emission, max_density })
+    }
+
+    /// Reads a binary grid if the file starts with `VGRD`, a text one
+    /// otherwise.
+    pub fn load(path: &Path) -> io::Result<VoxelGrid> {
+        let bytes = std::fs::read(path)?;
+        if bytes.starts_with(MAGIC) {
+            decode_binary(&bytes)
+        } else {
+            decode_text(std::str::from_utf8(&bytes).map_err(|_| invalid("voxel grid is neither text nor binary"))?)
+        }
+    }
+
+    /// Upper bound of the density anywhere in the grid.
+    pub fn max_density(&self) -> f64 {
+        self.max_density
+    }
+
+    /// Indices and weights of the eight voxels around `p` in the unit cube.
+    fn corners(&self, p: &Vec3) -> [(usize, f64); 8] {
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut frac = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.size[axis];
+            let x = (p.data[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            lower[axis] = (x.floor() as usize).min(n - 1);
+            upper[axis] = (lower[axis] + 1).min(n - 1);
+            frac[axis] = x - lower[axis] as f64;
+        }
+        std::array::from_fn(|corner| {
+            let pick = |axis: usize| corner >> axis & 1 == 1;
+            let index = |axis: usize| if pick(axis) { upper[axis] } else { lower[axis] };
+            let weight = (0..3).map(|axis| if pick(axis) { frac[axis] } else { 1.0 - frac[axis] }).product();
+            (index(0) + self.size[0] * (index(1) + self.size[1] * index(2)), weight)
+        })
+    }
+
+    /// Density at `p` in the unit cube.
+    pub fn density(&self, p: &Vec3) -> f64 {
+        self.corners(p).iter().map(|(i, w)| w * self.density[*i] as f64).sum()
+    }
+
+    /// Temperature in kelvin at `p`, zero without a temperature channel.
+    pub fn temperature(&self, p: &Vec3) -> f64 {
+        self.temperature
+            .as_ref()
+            .map_or(0.0, |t| self.corners(p).iter().map(|(i, w)| w * t[*i] as f64).sum())
+    }
+
+    /// Emitted color at `p`, black without an emission channel.
+    pub fn emission(&self, p: &Vec3) -> Color {
+        let mut color = Color::new(0.0, 0.0, 0.0);
+        if let Some(emission) = &self.emission {
+            for (i, w) in self.corners(p) {
+                let [r, g, b] = emission[i];
+                color += w * Color::new(r as f64, g as f64, b as f64);
+            }
+        }
+        color
+    }
+}
+
+/// Radiance of a black body at `kelvin` seen through red, green and blue
+/// filters, approximated by Planck's law at 610, 550 and 465 nm. Scaled so
+/// that a 1500 K flame is 1 in green.
+pub fn blackbody(kelvin: f64) -> Color {
+    if kelvin <= 0.0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    // Second radiation constant hc/k in metre kelvin.
+    const C2: f64 = 1.4388e-2;
+    let planck = |nm: f64, kelvin: f64| {
+        let lambda = nm * 1e-9;
+        1.0 / (lambda.powi(5) * ((C2 / (lambda * kelvin)).exp() - 1.0))
+    };
+    let reference = planck(550.0, 1500.0);
+    Color::new(planck(610.0, kelvin), planck(550.0, kelvin), planck(465.0, kelvin)) / reference
+}
+
+fn decode_text(text: &str) -> io::Result<VoxelGrid> {
+    let mut tokens = text
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .flat_map(str::split_whitespace);
+
+    if tokens.next() != Some("grid") {
+        return Err(invalid("voxel grid must start with `grid`"));
+    }
+    let mut size = [0; 3];
+    for n in size.iter_mut() {
+        *n = tokens.next().and_then(|t| t.parse().ok()).ok_or_else(|| invalid("bad voxel grid size"))?;
+    }
+    let mut flags = 0;
+    let mut values = Vec::new();
+    for token in tokens {
+        match token {
+            "temperature" if values.is_empty() => flags |= TEMPERATURE,
+            "emission" if values.is_empty() => flags |= EMISSION,
+            _ => values.push(token.parse::<f32>().map_err(|_| invalid("bad voxel grid value"))?),
+        }
+    }
+    from_values(size, flags, &values)
+}
+
+fn decode_binary(bytes: &[u8]) -> io::Result<VoxelGrid> {
+    if !(bytes.len() - MAGIC.len()).is_multiple_of(4) {
+        return Err(invalid("voxel grid has trailing bytes"));
+    }
+    let words: Vec<u32> = bytes[MAGIC.len()..]
+        .chunks_exact(4)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .collect();
+    if words.len() < 4 {
+        return Err(invalid("truncated voxel grid header"));
+    }
+    let size = [words[0] as usize, words[1] as usize, words[2] as usize];
+    let values: Vec<f32> = words[4..].iter().map(|w| f32::from_bits(*w)).collect();
+    from_values(size, words[3], &values)
+}
+
+/// Splits interleaved per-voxel values into channels.
+fn from_values(size: [usize; 3], flags: u32, values: &[f32]) -> io::Result<VoxelGrid> {
+    let stride = 1 + (flags & TEMPERATURE != 0) as usize + 3 * (flags & EMISSION != 0) as usize;
+    if flags & !(TEMPERATURE | EMISSION) != 0 {
+        return Err(invalid("unknown voxel grid channels"));
+    }
+    let expected = size.iter().try_fold(stride, |n, s| n.checked_mul(*s)).ok_or_else(|| invalid("voxel grid is too large"))?;
+    if values.len() != expected {
+        return Err(invalid("voxel grid has the wrong number of values"));
+    }
+    let voxels = values.chunks(stride);
+    let density = voxels.clone().map(|v| v[0]).collect();
+    let temperature = (flags & TEMPERATURE != 0).then(|| voxels.clone().map(|v| v[1]).collect());
+    let emission = (flags & EMISSION != 0).then(|| voxels.map(|v| [v[stride - 3], v[stride - 2], v[stride - 1]]).collect());
+    VoxelGrid::new(size, density, temperature, emission)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trilinear() {
+        // Two voxels along x, so values blend between their centers.
+        let grid = VoxelGrid::new([2, 1, 1], vec![0.0, 2.0], None, None).unwrap();
+        let at = |x: f64| grid.density(&Vec3::new(x, 0.5, 0.5));
+        assert_eq!(at(0.25), 0.0);
+        assert_eq!(at(0.75), 2.0);
+        assert!((at(0.5) - 1.0).abs() < 1e-12);
+        // Clamped past the outer centers.
+        assert_eq!(at(0.0), 0.0);
+        assert_eq!(at(1.0), 2.0);
+        assert_eq!(grid.max_density(), 2.0);
+        assert_eq!(grid.temperature(&Vec3::new(0.5, 0.5, 0.5)), 0.0);
+    }
+
+    #[test]
+    fn test_text_and_binary_agree() {
+        let text = "# a 2x1x2 grid\ngrid 2 1 2 temperature emission\n\
+                    1 1000 0 0 0\n2 1500 1 0 0\n3 2000 0 1 0\n4 2500 0 0 1 # last voxel\n";
+        let from_text = decode_text(text).unwrap();
+
+        let mut bytes = MAGIC.to_vec();
+        for word in [2u32, 1, 2, TEMPERATURE | EMISSION] {
+            bytes.extend(word.to_le_bytes());
+        }
+        for value in text.lines().skip(2).flat_map(|l| l.split('#').next().unwrap().split_whitespace()) {
+            bytes.extend(value.parse::<f32>().unwrap().to_le_bytes());
+        }
+        let from_binary = decode_binary(&bytes).unwrap();
+
+        for grid in [&from_text, &from_binary] {
+            assert_eq!(grid.size, [2, 1, 2]);
+            let corner = Vec3::new(0.75, 0.5, 0.75);
+            assert_eq!(grid.density(&corner), 4.0);
+            assert_eq!(grid.temperature(&corner), 2500.0);
+            assert_eq!(grid.emission(&Vec3::new(0.75, 0.5, 0.25)), Color::new(1.0, 0.0, 0.0));
+        }
+
+        assert!(decode_text("grid 2 1 1\n1\n").is_err());
+        assert!(decode_text("grid 1 1 1\n-1\n").is_err());
+        assert!(decode_text("voxels 1 1 1\n1\n").is_err());
+
+        // Sizes whose voxel count overflows, a value too many and a
+        // partial value are all rejected.
+        let header = |size: [u32; 3]| {
+            let mut bytes = MAGIC.to_vec();
+            for word in [size[0], size[1], size[2], EMISSION] {
+                bytes.extend(word.to_le_bytes());
+            }
+            bytes
+        };
+        assert!(decode_binary(&header([u32::MAX; 3])).is_err());
+        let mut one = header([1, 1, 1]);
+        one.extend([0u8; 16]);
+        assert!(decode_binary(&one).is_ok());
+        assert!(decode_binary(&[one.clone(), vec![0; 4]].concat()).is_err());
+        assert!(decode_binary(&[one, vec![0; 2]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_blackbody() {
+        let flame = blackbody(1500.0);
+        assert!((flame.g() - 1.0).abs() < 1e-12);
+        // Hotter bodies are brighter and bluer.
+        let hot = blackbody(6000.0);
+        assert!(hot.g() > flame.g());
+        assert!(hot.b() / hot.r() > flame.b() / flame.r());
+        assert_eq!(blackbody(0.0), Color::new(0.0, 0.0, 0.0));
+    }
+}
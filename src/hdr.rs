@@ -0,0 +1,248 @@
+use crate::color::Color;
+use crate::image::Image;
+
+use std::io::{self, Write};
+
+// Scanlines outside this range can't use the run-length encoding.
+const MIN_RLE_WIDTH: usize = 8;
+const MAX_RLE_WIDTH: usize = 0x7fff;
+const MIN_RUN: usize = 4;
+
+/// Shared-exponent RGBE encoding. Negative and non-finite channels are
+/// stored as zero since the format can't represent them.
+pub fn to_rgbe(color: &Color) -> [u8; 4] {
+    let channel = |c: f64| if c.is_finite() && c > 0.0 { c } else { 0.0 };
+    let (r, g, b) = (channel(color.r()), channel(color.g()), channel(color.b()));
+    let v = r.max(g).max(b);
+    if v < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // v = m * 2^e with m in [0.5, 1), as frexp would return.
+    let mut e = v.log2().floor() as i32 + 1;
+    if v / 2f64.powi(e) >= 1.0 {
+        e += 1;
+    }
+    let scale = 256.0 / 2f64.powi(e);
+    [(r * scale) as u8, (g * scale) as u8, (b * scale) as u8, (e + 128) as u8]
+}
+
+/// Inverse of `to_rgbe`, taking each mantissa at the middle of its step.
+pub fn from_rgbe(p: [u8; 4]) -> Color {
+    if p[3] == 0 {
+        return Color::new(0.0, 0.0, 0.0);
+    }
+    let f = 2f64.powi(p[3] as i32 - 136);
+    Color::new((p[0] as f64 + 0.5) * f, (p[1] as f64 + 0.5) * f, (p[2] as f64 + 0.5) * f)
+}
+
+/// Encodes one component of a scanline: runs of repeated bytes as
+/// `128 + count, value` and everything else as `count, bytes...`.
+fn write_rle_component(out: &mut Vec<u8>, data: &[u8]) {
+    let mut i = 0;
+    while i < data.len() {
+        let mut run_start = i;
+        let mut run_len = 0;
+        while run_start < data.len() {
+            run_len = 1;
+            while run_len < 127 && run_start + run_len < data.len() && data[run_start + run_len] == data[run_start] {
+                run_len += 1;
+            }
+            if run_len >= MIN_RUN {
+                break;
+            }
+            run_start += run_len;
+        }
+        if run_len < MIN_RUN {
+            run_start = data.len();
+        }
+
+        while i < run_start {
+            let n = (run_start - i).min(128);
+            out.push(n as u8);
+            out.extend_from_slice(&data[i..i + n]);
+            i += n;
+        }
+        if run_start < data.len() {
+            out.push(128 + run_len as u8);
+            out.push(data[run_start]);
+            i = run_start + run_len;
+        }
+    }
+}
+
+/// Writes a Radiance RGBE (.hdr) file from the linear image, without any
+/// tone mapping, using run-length encoded scanlines where the width allows.
+pub fn write_hdr<W: Write>(writer: &mut W, image: &Image) -> io::Result<()> {
+    write!(writer, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", image.height, image.width)?;
+
+    let rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&image.width);
+    let mut line = Vec::new();
+    for row in image.pixels.chunks(image.width) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+        line.clear();
+        if rle {
+            line.extend_from_slice(&[2, 2, (image.width >> 8) as u8, (image.width & 0xff) as u8]);
+            for component in 0..4 {
+                let data: Vec<u8> = rgbe.iter().map(|p| p[component]).collect();
+                write_rle_component(&mut line, &data);
+            }
+        } else {
+            line.extend(rgbe.iter().flatten());
+        }
+        writer.write_all(&line)?;
+    }
+    Ok(())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Decodes one run-length encoded component of a `width` pixel scanline
+/// into `out`, returning how many bytes of `data` it used.
+fn decode_rle_component(data: &[u8], width: usize, out: &mut Vec<u8>) -> io::Result<usize> {
+    let mut i = 0;
+    let start = out.len();
+    while out.len() - start < width {
+        let count = *data.get(i).ok_or_else(|| invalid("truncated .hdr scanline"))? as usize;
+        let chunk = if count > 128 {
+            let value = *data.get(i + 1).ok_or_else(|| invalid("truncated .hdr scanline"))?;
+            out.extend(std::iter::repeat_n(value, count - 128));
+            2
+        } else {
+            let bytes = data.get(i + 1..i + 1 + count).ok_or_else(|| invalid("truncated .hdr scanline"))?;
+            out.extend_from_slice(bytes);
+            1 + count
+        };
+        if count == 0 || out.len() - start > width {
+            return Err(invalid("bad run in .hdr scanline"));
+        }
+        i += chunk;
+    }
+    Ok(i)
+}
+
+/// Reads a Radiance RGBE (.hdr) file with the usual `-Y height +X width`
+/// orientation, flat or with run-length encoded scanlines.
+pub fn read_hdr(bytes: &[u8]) -> io::Result<Image> {
+    if !(bytes.starts_with(b"#?RADIANCE") || bytes.starts_with(b"#?RGBE")) {
+        return Err(invalid("not a Radiance .hdr file"));
+    }
+    // Header lines run up to an empty one, then comes the resolution.
+    let mut lines = bytes.split(|&b| b == b'\n');
+    let mut pos = 0;
+    for line in lines.by_ref() {
+        pos += line.len() + 1;
+        if line.is_empty() {
+            break;
+        }
+        if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+            return Err(invalid("unsupported .hdr pixel format, expected 32-bit_rle_rgbe"));
+        }
+    }
+    let resolution = lines.next().ok_or_else(|| invalid("missing .hdr resolution"))?;
+    pos += resolution.len() + 1;
+    let resolution = std::str::from_utf8(resolution).map_err(|_| invalid("bad .hdr resolution"))?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", h, "+X", w] => match (h.parse::<usize>(), w.parse::<usize>()) {
+            (Ok(h), Ok(w)) if h > 0 && w > 0 => (h, w),
+            _ => return Err(invalid("bad .hdr resolution")),
+        },
+        _ => return Err(invalid("unsupported .hdr orientation, expected -Y height +X width")),
+    };
+
+    let mut data = bytes.get(pos..).unwrap_or(&[]);
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut components: Vec<Vec<u8>> = (0..4).map(|_| Vec::with_capacity(width)).collect();
+    for _ in 0..height {
+        let rle = (MIN_RLE_WIDTH..=MAX_RLE_WIDTH).contains(&width)
+            && data.len() >= 4
+            && data[..2] == [2, 2]
+            && ((data[2] as usize) << 8 | data[3] as usize) == width;
+        if rle {
+            data = &data[4..];
+            for component in components.iter_mut() {
+                component.clear();
+                let used = decode_rle_component(data, width, component)?;
+                data = &data[used..];
+            }
+            pixels.extend((0..width).map(|x| {
+                from_rgbe([components[0][x], components[1][x], components[2][x], components[3][x]])
+            }));
+        } else {
+            let line = data.get(..4 * width).ok_or_else(|| invalid("truncated .hdr file"))?;
+            pixels.extend(line.chunks(4).map(|p| from_rgbe([p[0], p[1], p[2], p[3]])));
+            data = &data[4 * width..];
+        }
+    }
+    Ok(Image::new(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rgbe_round_trip() {
+        for c in [Color::new(1.0, 0.5, 0.25), Color::new(1000.0, 3.0, 0.0), Color::new(0.001, 0.002, 0.003)] {
+            let decoded = from_rgbe(to_rgbe(&c));
+            let largest = c.r().max(c.g()).max(c.b());
+            for (d, expected) in decoded.iter().zip(c.iter()) {
+                // Eight bits of mantissa relative to the largest channel.
+                assert!((d - expected).abs() <= largest / 128.0);
+            }
+        }
+        assert_eq!(to_rgbe(&Color::new(-1.0, 0.0, f64::NAN)), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_rle_scanlines() {
+        let width = 300;
+        let pixels: Vec<Color> = (0..width * 2)
+            .map(|i| if i % 100 < 50 { Color::new(2.0, 2.0, 2.0) } else { Color::new(i as f64, 0.5, 0.1) })
+            .collect();
+        let image = Image::new(width, 2, pixels);
+
+        let mut bytes = Vec::new();
+        write_hdr(&mut bytes, &image).unwrap();
+        let header = format!("#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 2 +X {}\n", width);
+        assert!(bytes.starts_with(header.as_bytes()));
+
+        let mut data = &bytes[header.len()..];
+        for row in image.pixels.chunks(width) {
+            assert_eq!(&data[..4], &[2, 2, 1, 44]);
+            data = &data[4..];
+            let mut components = vec![Vec::new(); 4];
+            for component in components.iter_mut() {
+                let used = decode_rle_component(data, width, component).unwrap();
+                data = &data[used..];
+            }
+            for (x, color) in row.iter().enumerate() {
+                let rgbe = [components[0][x], components[1][x], components[2][x], components[3][x]];
+                assert_eq!(rgbe, to_rgbe(color));
+            }
+        }
+        assert!(data.is_empty());
+    }
+
+    #[test]
+    fn test_read_hdr() {
+        // Both scanline encodings read back what was written, to within
+        // the precision of RGBE.
+        for width in [5, 300] {
+            let pixels: Vec<Color> = (0..width * 3).map(|i| Color::new(i as f64 * 0.1, 2.0, 1e-3)).collect();
+            let image = Image::new(width, 3, pixels);
+            let mut bytes = Vec::new();
+            write_hdr(&mut bytes, &image).unwrap();
+            let read = read_hdr(&bytes).unwrap();
+            assert_eq!((read.width, read.height), (width, 3));
+            for (r, e) in read.pixels.iter().zip(&image.pixels) {
+                assert_eq!(to_rgbe(r), to_rgbe(e));
+            }
+            assert!(read_hdr(&bytes[..bytes.len() - 3]).is_err());
+        }
+        assert!(read_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\0\0\0\0").is_err());
+        assert!(read_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\0\0\0\0").is_err());
+    }
+}
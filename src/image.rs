@@ -0,0 +1,228 @@
+use crate::color::Color;
+use crate::hdr;
+
+use std::io;
+use std::path::Path;
+
+/// Linear, sample-averaged radiance for every pixel, top row first.
+/// Encoders decide how to tone map and quantize it.
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(pixels.len(), width * height, "image needs one color per pixel");
+        Self { width, height, pixels }
+    }
+
+    /// Gamma corrected channels scaled to `0..=max_value`, row by row.
+    pub fn quantize(&self, max_value: u32) -> Vec<u32> {
+        self.pixels.iter().flat_map(|c| c.quantize(max_value)).collect()
+    }
+
+    /// Reads a PNG, PPM (P3 or P6), Radiance HDR or OpenEXR file picked by
+    /// its extension. PNG and PPM samples are undone with the same gamma 2
+    /// the encoders apply, so rendered images load back as the radiance
+    /// they were written from; HDR and EXR files are linear already.
+    pub fn load(path: &Path) -> io::Result<Image> {
+        let bytes = std::fs::read(path)?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("png") => decode_png(&bytes),
+            Some("ppm") => decode_ppm(&bytes),
+            Some("hdr") => hdr::read_hdr(&bytes),
+            Some("exr") => decode_exr(&bytes),
+            _ => Err(invalid("unsupported image format, expected .png, .ppm, .hdr or .exr")),
+        }
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Builds an image from interleaved gamma encoded samples in `0..=max_value`.
+fn from_samples(width: usize, height: usize, channels: usize, samples: &[u32], max_value: u32) -> io::Result<Image> {
+    if width == 0 || height == 0 {
+        return Err(invalid("image is empty"));
+    }
+    if samples.len() < width * height * channels {
+        return Err(invalid("image data ends early"));
+    }
+    let linear = |s: u32| (s.min(max_value) as f64 / max_value as f64).powi(2);
+    let pixels = samples
+        .chunks(channels)
+        .take(width * height)
+        .map(|p| match channels {
+            1 | 2 => Color::new(linear(p[0]), linear(p[0]), linear(p[0])),
+            _ => Color::new(linear(p[0]), linear(p[1]), linear(p[2])),
+        })
+        .collect();
+    Ok(Image::new(width, height, pixels))
+}
+
+fn decode_png(bytes: &[u8]) -> io::Result<Image> {
+    let mut decoder = png::Decoder::new(bytes);
+    // Palettes and low bit depths expand to 8 bits, 16 bits stay as they are.
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf).map_err(io::Error::other)?;
+    let data = &buf[..info.buffer_size()];
+
+    let channels = info.color_type.samples();
+    let (samples, max_value): (Vec<u32>, u32) = match info.bit_depth {
+        png::BitDepth::Sixteen => (data.chunks(2).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32).collect(), 65535),
+        _ => (data.iter().map(|&b| b as u32).collect(), 255),
+    };
+    from_samples(info.width as usize, info.height as usize, channels, &samples, max_value)
+}
+
+fn decode_ppm(bytes: &[u8]) -> io::Result<Image> {
+    // The header is four whitespace separated tokens, with `#` comments.
+    let mut pos = 0;
+    let mut header = Vec::new();
+    while header.len() < 4 {
+        while pos < bytes.len() && (bytes[pos].is_ascii_whitespace() || bytes[pos] == b'#') {
+            if bytes[pos] == b'#' {
+                while pos < bytes.len() && bytes[pos] != b'\n' {
+                    pos += 1;
+                }
+            } else {
+                pos += 1;
+            }
+        }
+        let start = pos;
+        while pos < bytes.len() && !bytes[pos].is_ascii_whitespace() {
+            pos += 1;
+        }
+        if start == pos {
+            return Err(invalid("truncated PPM header"));
+        }
+        header.push(std::str::from_utf8(&bytes[start..pos]).map_err(|_| invalid("bad PPM header"))?);
+    }
+
+    let number = |s: &str| s.parse::<usize>().map_err(|_| invalid("bad PPM header"));
+    let (width, height, max_value) = (number(header[1])?, number(header[2])?, number(header[3])?);
+    if max_value == 0 || max_value > 65535 {
+        return Err(invalid("PPM max value must be between 1 and 65535"));
+    }
+    let count = width * height * 3;
+
+    let samples: Vec<u32> = match header[0] {
+        "P6" => {
+            // A single whitespace byte separates the header from the data.
+            let data = bytes.get(pos + 1..).unwrap_or(&[]);
+            if max_value < 256 {
+                data.iter().take(count).map(|&b| b as u32).collect()
+            } else {
+                data.chunks_exact(2).take(count).map(|b| u16::from_be_bytes([b[0], b[1]]) as u32).collect()
+            }
+        }
+        "P3" => std::str::from_utf8(&bytes[pos..])
+            .map_err(|_| invalid("bad PPM data"))?
+            .split_ascii_whitespace()
+            .take(count)
+            .map(|s| s.parse::<u32>().map_err(|_| invalid("bad PPM sample")))
+            .collect::<io::Result<_>>()?,
+        _ => return Err(invalid("not a P3 or P6 PPM file")),
+    };
+    from_samples(width, height, 3, &samples, max_value as u32)
+}
+
+/// Reads the RGB channels of the first layer; missing ones are black.
+fn decode_exr(bytes: &[u8]) -> io::Result<Image> {
+    use exr::prelude::{read, ReadChannels, ReadLayers, Vec2};
+
+    let image = read()
+        .no_deep_data()
+        .largest_resolution_level()
+        .rgba_channels(
+            |resolution: Vec2<usize>, _| (resolution.width(), vec![Color::new(0.0, 0.0, 0.0); resolution.area()]),
+            |(width, pixels): &mut (usize, Vec<Color>), position: Vec2<usize>, (r, g, b, _): (f32, f32, f32, f32)| {
+                pixels[position.y() * *width + position.x()] = Color::new(r as f64, g as f64, b as f64);
+            },
+        )
+        .first_valid_layer()
+        .all_attributes()
+        .from_buffered(io::Cursor::new(bytes))
+    .map_err(io::Error::other)?;
+    let (width, pixels) = image.layer_data.channel_data.pixels;
+    if width == 0 || pixels.is_empty() {
+        return Err(invalid("image is empty"));
+    }
+    Ok(Image::new(width, pixels.len() / width, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::{encode, BitDepth, Format, Precision};
+
+    #[test]
+    fn test_decode_round_trip() {
+        let pixels = (0..12).map(|i| Color::new(i as f64 / 11.0, 0.25, 1.0)).collect();
+        let image = Image::new(4, 3, pixels);
+
+        for format in [
+            Format::Png(BitDepth::Eight),
+            Format::Png(BitDepth::Sixteen),
+            Format::Ppm(BitDepth::Eight),
+            Format::Ppm(BitDepth::Sixteen),
+            Format::PpmAscii(BitDepth::Eight),
+        ] {
+            let mut bytes = Vec::new();
+            encode(&mut bytes, &image, format).unwrap();
+            let decoded = match format {
+                Format::Png(_) => decode_png(&bytes),
+                _ => decode_ppm(&bytes),
+            }
+            .unwrap();
+
+            assert_eq!((decoded.width, decoded.height), (4, 3));
+            for (d, e) in decoded.pixels.iter().zip(&image.pixels) {
+                for (d, e) in d.iter().zip(e.iter()) {
+                    // One 8 bit step of the gamma encoded value.
+                    assert!((d.sqrt() - e.sqrt()).abs() <= 1.0 / 255.0, "{:?}: {} != {}", format, d, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_linear_formats() {
+        // HDR and EXR keep radiance above one, without gamma.
+        let pixels = (0..12).map(|i| Color::new(i as f64 * 10.0, 0.25, 1.0)).collect();
+        let image = Image::new(4, 3, pixels);
+        for format in [Format::Exr(Precision::Float), Format::Hdr] {
+            let mut bytes = Vec::new();
+            encode(&mut bytes, &image, format).unwrap();
+            let decoded = match format {
+                Format::Hdr => hdr::read_hdr(&bytes),
+                _ => decode_exr(&bytes),
+            }
+            .unwrap();
+            assert_eq!((decoded.width, decoded.height), (4, 3));
+            for (d, e) in decoded.pixels.iter().zip(&image.pixels) {
+                let largest = e.iter().fold(0.0f64, |a, b| a.max(*b));
+                for (d, e) in d.iter().zip(e.iter()) {
+                    assert!((d - e).abs() <= largest / 128.0, "{:?}: {} != {}", format, d, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_ppm_comments() {
+        let image = decode_ppm(b"P3\n# made by hand\n1 1 # one pixel\n255\n255 0 255\n").unwrap();
+        assert_eq!(image.pixels[0], Color::new(1.0, 0.0, 1.0));
+        assert!(decode_ppm(b"P6\n2 2\n255\n\x00\x00").is_err());
+        assert!(decode_ppm(b"P5\n1 1\n255\n\x00").is_err());
+    }
+}
@@ -0,0 +1,108 @@
+use crate::hitrecord::HitRecord;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::aabb::Aabb;
+use crate::figure::Figure;
+use crate::matrix::Matrix4;
+
+use std::sync::Arc;
+
+/// Shared geometry placed in the world by an affine transform. Rays are
+/// taken into object space instead of moving the geometry, so any number
+/// of instances can refer to one mesh.
+pub struct Instance {
+    pub object: Arc<Figure>,
+    pub object_to_world: Matrix4,
+    pub world_to_object: Matrix4,
+}
+
+impl Instance {
+    pub fn new(object: Arc<Figure>, object_to_world: Matrix4) -> Self {
+        let world_to_object = object_to_world
+            .inverse()
+            .expect("instance transform must be invertible");
+        Self { object, object_to_world, world_to_object }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        // The direction isn't normalized, so t means the same in both spaces.
+        let local = Ray::new(
+            self.world_to_object.transform_point(&ray.origin),
+            self.world_to_object.transform_vector(&ray.direction),
+        )
+        .with_time(ray.time);
+
+        let mut rec = self.object.hit(&local, t_min, t_max)?;
+        rec.p = self.object_to_world.transform_point(&rec.p);
+        rec.normal = self.world_to_object.transform_normal(&rec.normal).unit_vector();
+        Some(rec)
+    }
+
+    /// Box around the transformed corners of the object's box.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        let local = self.object.bounding_box()?;
+        let mut bounds = Aabb::empty();
+        for i in 0..8 {
+            let corner = Vec3::new(
+                if i & 1 == 0 { local.min.x() } else { local.max.x() },
+                if i & 2 == 0 { local.min.y() } else { local.max.y() },
+                if i & 4 == 0 { local.min.z() } else { local.max.z() },
+            );
+            bounds = bounds.include(&self.object_to_world.transform_point(&corner));
+        }
+        Some(bounds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+
+    fn ellipsoid() -> Figure {
+        let sphere = Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 1.0, Material::lambertian(Color::new(1.0, 1.0, 1.0)));
+        let transform = Matrix4::translation(&Vec3::new(0.0, 1.0, 0.0)) * Matrix4::scaling(&Vec3::new(2.0, 1.0, 1.0));
+        Figure::instance(Arc::new(sphere), transform)
+    }
+
+    #[test]
+    fn test_non_uniform_scale() {
+        let figure = ellipsoid();
+
+        let ray = Ray::new(Vec3::new(-10.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = figure.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 8.0).abs() < 1e-12);
+        assert!((&rec.p - Vec3::new(-2.0, 1.0, 0.0)).near_zero());
+        assert!((&rec.normal - Vec3::new(-1.0, 0.0, 0.0)).near_zero());
+        assert!(rec.front_face);
+
+        // Off-axis the normal follows the ellipsoid's gradient (x / 4, y, z),
+        // not the direction from the center.
+        let ray = Ray::new(Vec3::new(2.0f64.sqrt(), 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = figure.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let local = Vec3::new(2.0f64.sqrt(), 0.5f64.sqrt(), 0.0);
+        assert!((&rec.p - (&local + Vec3::new(0.0, 1.0, 0.0))).near_zero());
+        let expected = Vec3::new(local.x() / 4.0, local.y(), 0.0).unit_vector();
+        assert!((&rec.normal - &expected).length() < 1e-9);
+    }
+
+    #[test]
+    fn test_bounding_box() {
+        let bounds = ellipsoid().bounding_box().unwrap();
+        assert_eq!(bounds.min, Vec3::new(-2.0, 0.0, -1.0));
+        assert_eq!(bounds.max, Vec3::new(2.0, 2.0, 1.0));
+
+        let triangle = Figure::triangle(
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Material::lambertian(Color::new(1.0, 1.0, 1.0)),
+        );
+        let rotated = Figure::instance(Arc::new(triangle), Matrix4::rotation(&Vec3::new(0.0, 0.0, 1.0), 45.0));
+        let bounds = rotated.bounding_box().unwrap();
+        let h = 0.5f64.sqrt();
+        assert!((bounds.min - Vec3::new(-h, 0.0, 0.0)).near_zero());
+        assert!((bounds.max - Vec3::new(h, 2.0 * h, 0.0)).near_zero());
+    }
+}
@@ -0,0 +1,306 @@
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::world::World;
+use crate::hitrecord::HitRecord;
+use crate::background::Background;
+
+use rand::Rng;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/*
+ * Path tracing with next-event estimation: at every diffuse hit one light
+ * is sampled directly with a shadow ray, and the scattered ray continues
+ * as before. Both can find the same light, so each estimate is weighted
+ * with the power heuristic against the density the other strategy would
+ * have picked its direction with (multiple importance sampling).
+ * A sampled background counts as one more light, and point, spot and
+ * directional lights are only ever found by shadow rays.
+ */
+
+/// How paths end, besides escaping the scene or being absorbed.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Termination {
+    /// Follow every path to the maximum depth.
+    Depth,
+    /// Russian roulette: past a minimum number of bounces, continue each
+    /// path with a probability that follows its throughput and weight the
+    /// survivors up to match, so dim paths end early without darkening
+    /// the image. The maximum depth still applies.
+    Roulette,
+}
+
+/// Parses `depth` or `roulette`.
+impl FromStr for Termination {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "depth" => Ok(Termination::Depth),
+            "roulette" => Ok(Termination::Roulette),
+            _ => Err(format!("invalid termination `{}`, expected depth or roulette", s)),
+        }
+    }
+}
+
+/// Limits on the number of bounces of a path.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathLength {
+    pub max_depth: u32,
+    pub termination: Termination,
+    /// Bounces before Russian roulette starts.
+    pub min_bounces: u32,
+}
+
+/// Survival probabilities are capped so that even paths in a white room
+/// end eventually.
+const MAX_SURVIVAL: f64 = 0.95;
+
+pub fn ray_color<R: Rng>(r: &Ray, world: &World, background: &Background, rng: &mut R, length: &PathLength) -> Color {
+    let mut color = Color::new(0.0, 0.0, 0.0);
+    let mut throughput = Color::new(1.0, 1.0, 1.0);
+    let mut ray = Ray::new(r.origin.clone(), r.direction.clone()).with_time(r.time);
+    // The density the material sampled `ray` with, or `None` for camera
+    // rays and specular bounces that light sampling can't reproduce.
+    let mut bsdf_pdf: Option<f64> = None;
+
+    for bounce in 0..length.max_depth {
+        let (figure, rec) = match world.hit(&ray, 0.001, f64::INFINITY) {
+            Some(hit) => hit,
+            None => {
+                let mut escaped = background.color(&ray);
+                if let Some(pdf) = bsdf_pdf.filter(|_| background.is_sampled()) {
+                    let light_pdf = background.pdf(&ray.direction) / light_count(world, background) as f64;
+                    escaped = power_heuristic(pdf, light_pdf) * escaped;
+                }
+                color += &throughput * escaped;
+                break;
+            }
+        };
+
+        let mut emitted = rec.material.emission(&rec);
+        if let Some(pdf) = bsdf_pdf {
+            if !emitted.near_zero() {
+                let light_pdf = world.light_pdf(figure, &ray, rec.t) / light_count(world, background) as f64;
+                emitted = power_heuristic(pdf, light_pdf) * emitted;
+            }
+        }
+        color += &throughput * emitted;
+
+        let (attenuation, scattered) = match rec.material.scatter(&ray, &rec, rng) {
+            Some(scatter) => scatter,
+            None => break,
+        };
+
+        if rec.material.is_specular() {
+            bsdf_pdf = None;
+        } else {
+            // Lights found by the next bounce are cut off at the last one, so
+            // only sample them directly while that bounce could still reach
+            // them.
+            if bounce + 1 < length.max_depth {
+                color += &throughput * sample_light(&ray, &rec, world, background, rng);
+            }
+            bsdf_pdf = Some(rec.material.pdf(&ray, &rec, &scattered.direction));
+        }
+        throughput = throughput * attenuation;
+        ray = scattered;
+
+        if length.termination == Termination::Roulette && bounce + 1 >= length.min_bounces {
+            let survival = throughput.iter().fold(0.0, |m: f64, &c| m.max(c)).min(MAX_SURVIVAL);
+            if rng.gen::<f64>() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
+    }
+    color
+}
+
+/// Number of lights to choose from: the world's, and the background if it
+/// can be sampled.
+fn light_count(world: &World, background: &Background) -> usize {
+    world.lights().len() + background.is_sampled() as usize
+}
+
+/// Light arriving directly from one uniformly chosen light, reflected
+/// towards `r`.
+fn sample_light<R: Rng>(r: &Ray, rec: &HitRecord, world: &World, background: &Background, rng: &mut R) -> Color {
+    let black = Color::new(0.0, 0.0, 0.0);
+    let lights = world.lights();
+    let count = light_count(world, background);
+    if count == 0 {
+        return black;
+    }
+
+    let index = rng.gen_range(0..count);
+    let (sample, delta) = match lights.get(index) {
+        Some(light) => (light.sample(&rec.p, rng), light.is_delta()),
+        None => (background.sample(rng), false),
+    };
+    let sample = match sample {
+        Some(sample) => sample,
+        None => return black,
+    };
+    let f = rec.material.eval(r, rec, &sample.direction);
+    if f.near_zero() {
+        return black;
+    }
+
+    let shadow = Ray::new(rec.p.clone(), sample.direction.clone()).with_time(r.time);
+    let transmittance = world.transmittance(&shadow, 0.001, sample.distance - 0.001);
+    if transmittance == 0.0 {
+        return black;
+    }
+
+    let light_pdf = sample.pdf / count as f64;
+    // Scattered rays never hit a point or direction, so light sampling is
+    // the only way to find delta lights.
+    let weight = if delta { 1.0 } else { power_heuristic(light_pdf, rec.material.pdf(r, rec, &sample.direction)) };
+    (transmittance * weight / light_pdf) * (f * sample.radiance)
+}
+
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0.0 {
+        a / (a + b)
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use crate::figure::Figure;
+    use crate::material::Material;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use crate::environment::Environment;
+    use crate::light::Light;
+    use crate::image::Image;
+    use std::f64::consts::PI;
+    use std::sync::Arc;
+
+    const TWO_BOUNCES: PathLength = PathLength { max_depth: 2, termination: Termination::Depth, min_bounces: 0 };
+
+    fn floor_under_light(material: Material) -> World {
+        let mut world = World::new();
+        let floor = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+        let corners = [
+            Vec3::new(-100.0, 0.0, -100.0),
+            Vec3::new(100.0, 0.0, -100.0),
+            Vec3::new(100.0, 0.0, 100.0),
+            Vec3::new(-100.0, 0.0, 100.0),
+        ];
+        world.add(Figure::triangle(corners[0].clone(), corners[2].clone(), corners[1].clone(), floor.clone()));
+        world.add(Figure::triangle(corners[0].clone(), corners[3].clone(), corners[2].clone(), floor));
+        world.add(Figure::sphere(Vec3::new(0.0, 3.0, 0.0), 0.5, material));
+        world
+    }
+
+    #[test]
+    fn test_direct_light_from_sphere() {
+        // A sphere seen under a cone of half angle theta lights a facing
+        // Lambertian surface to albedo * Le * sin^2(theta).
+        let world = floor_under_light(Material::diffuse_light(Color::new(1.0, 1.0, 1.0), 10.0));
+        let expected = 0.5 * 10.0 * (0.5f64 / 3.0).powi(2);
+
+        let mut rng = StdRng::seed_from_u64(11);
+        let ray = Ray::new(Vec3::new(1.0, 1.0, 0.0), Vec3::new(-1.0, -1.0, 0.0));
+        let black = Background::Constant(Color::new(0.0, 0.0, 0.0));
+        let n = 20000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            sum += ray_color(&ray, &world, &black, &mut rng, &TWO_BOUNCES).r();
+        }
+        let estimate = sum / n as f64;
+        assert!((estimate - expected).abs() < 0.02 * expected, "{} != {}", estimate, expected);
+    }
+
+    #[test]
+    fn test_environment_light() {
+        // A white furnace: a Lambertian floor under a uniform sky reflects
+        // albedo * Le, whether the light is found by shadow rays or bounces.
+        let mut world = World::new();
+        let floor = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+        world.add(Figure::plane(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), floor));
+        let mut pixels = vec![Color::new(2.0, 2.0, 2.0); 32 * 16];
+        pixels[3 * 32 + 5] = Color::new(50.0, 50.0, 50.0);
+        let image = Image::new(32, 16, pixels);
+        let sky = Background::Environment(Arc::new(Environment::new(image, 0.0, 1.0)));
+        assert_eq!(sky.color(&Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0))), Color::new(2.0, 2.0, 2.0));
+
+        // The bright pixel adds its solid angle times cos θ / π.
+        let (theta, cell) = (PI * 3.5 / 16.0, 2.0 * PI * PI / (32.0 * 16.0));
+        let expected = 0.5 * (2.0 + 48.0 * cell * theta.sin() * theta.cos() / PI);
+
+        let mut rng = StdRng::seed_from_u64(5);
+        let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.2));
+        let n = 20000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            sum += ray_color(&ray, &world, &sky, &mut rng, &TWO_BOUNCES).r();
+        }
+        let estimate = sum / n as f64;
+        assert!((estimate - expected).abs() < 0.02 * expected, "{} != {}", estimate, expected);
+    }
+
+    #[test]
+    fn test_punctual_lights() {
+        // A point light at height h lights a Lambertian floor at horizontal
+        // distance x to albedo / π * I cos θ / d², with no noise at all.
+        let mut world = World::new();
+        let floor = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+        world.add(Figure::plane(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), floor));
+        world.add_light(Light::Point { position: Vec3::new(0.0, 2.0, 0.0), intensity: Color::new(8.0, 8.0, 8.0) });
+        let black = Background::Constant(Color::new(0.0, 0.0, 0.0));
+        let mut rng = StdRng::seed_from_u64(1);
+        let ray = Ray::new(Vec3::new(1.0, 1.0, 0.0), Vec3::new(0.5, -1.0, 0.0));
+        let d2: f64 = 1.5 * 1.5 + 4.0;
+        let expected = 0.5 / PI * 8.0 * (2.0 / d2.sqrt()) / d2;
+        let color = ray_color(&ray, &world, &black, &mut rng, &TWO_BOUNCES);
+        assert!((color.r() - expected).abs() < 1e-9, "{} != {}", color.r(), expected);
+
+        // A directional light shining straight down, blocked by a sphere.
+        let mut world = World::new();
+        let floor = Material::lambertian(Color::new(0.5, 0.5, 0.5));
+        world.add(Figure::plane(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), floor.clone()));
+        world.add(Figure::sphere(Vec3::new(0.0, 3.0, 0.0), 1.0, floor));
+        world.add_light(Light::Directional { direction: Vec3::new(0.0, -1.0, 0.0), irradiance: Color::new(2.0, 2.0, 2.0) });
+        let lit = ray_color(&Ray::new(Vec3::new(3.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), &world, &black, &mut rng, &TWO_BOUNCES);
+        assert!((lit.r() - 0.5 / PI * 2.0).abs() < 1e-9);
+        let shadow = ray_color(&Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0)), &world, &black, &mut rng, &TWO_BOUNCES);
+        assert_eq!(shadow.r(), 0.0);
+    }
+
+    #[test]
+    fn test_russian_roulette() {
+        // Between two grey planes lit by a point light, most of the light
+        // has bounced a few times. Roulette ends paths early but must
+        // average to the same as following them all to the maximum depth.
+        let mut world = World::new();
+        let grey = Material::lambertian(Color::new(0.7, 0.7, 0.7));
+        world.add(Figure::plane(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), grey.clone()));
+        world.add(Figure::plane(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0), grey));
+        world.add_light(Light::Point { position: Vec3::new(0.0, 0.5, 0.0), intensity: Color::new(1.0, 1.0, 1.0) });
+        let black = Background::Constant(Color::new(0.0, 0.0, 0.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.5, -1.0, 0.0));
+
+        let estimate = |length: PathLength, seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let n = 6000;
+            (0..n).map(|_| ray_color(&ray, &world, &black, &mut rng, &length).r()).sum::<f64>() / n as f64
+        };
+        let direct = estimate(TWO_BOUNCES, 1);
+        let depth = estimate(PathLength { max_depth: 12, termination: Termination::Depth, min_bounces: 0 }, 2);
+        let roulette = estimate(PathLength { max_depth: 12, termination: Termination::Roulette, min_bounces: 1 }, 3);
+        assert!(depth > 1.2 * direct, "{} vs {}", depth, direct);
+        assert!((roulette - depth).abs() < 0.03 * depth, "{} != {}", roulette, depth);
+
+        assert_eq!("roulette".parse(), Ok(Termination::Roulette));
+        assert!("forever".parse::<Termination>().is_err());
+    }
+}
@@ -0,0 +1,334 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::onb::Onb;
+use crate::mesh::intersect_triangle;
+use crate::planar::{intersect_quad, intersect_disk};
+use crate::util::degrees_to_radians;
+
+use rand::Rng;
+use std::f64::consts::PI;
+
+/// Something that can be sampled directly for next-event estimation:
+/// copies of the emissive figures in a `World`, and punctual lights that
+/// have no surface at all.
+#[derive(Debug, Clone)]
+pub enum Light {
+    Sphere { center: Vec3, radius: f64, radiance: Color },
+    Triangle { p0: Vec3, p1: Vec3, p2: Vec3, radiance: Color },
+    Quad { q: Vec3, u: Vec3, v: Vec3, radiance: Color },
+    Disk { center: Vec3, normal: Vec3, radius: f64, radiance: Color },
+    /// Radiant `intensity` in every direction, falling off with the
+    /// square of the distance.
+    Point { position: Vec3, intensity: Color },
+    /// A point light shining along the unit `direction`, at full
+    /// intensity within the inner cone and fading out towards the outer.
+    Spot { position: Vec3, direction: Vec3, intensity: Color, cos_inner: f64, cos_outer: f64 },
+    /// Parallel light travelling along the unit `direction` from
+    /// infinitely far away, like the sun.
+    Directional { direction: Vec3, irradiance: Color },
+}
+
+/// A direction towards a point on a light.
+pub struct LightSample {
+    /// Unit direction from the shading point to the light.
+    pub direction: Vec3,
+    pub distance: f64,
+    /// Solid angle density of `direction` when sampling this light. Delta
+    /// lights have only the one direction; their density is one and their
+    /// `radiance` is the irradiance they give facing them.
+    pub pdf: f64,
+    pub radiance: Color,
+}
+
+impl Light {
+    /// A spot light at `position` pointing at `target`. The angles are
+    /// measured from the axis to the edge of each cone, in degrees.
+    pub fn spot(position: Vec3, target: &Vec3, intensity: Color, inner: f64, outer: f64) -> Light {
+        let direction = (target - &position).unit_vector();
+        let (cos_inner, cos_outer) = (degrees_to_radians(inner).cos(), degrees_to_radians(outer).cos());
+        Light::Spot { position, direction, intensity, cos_inner, cos_outer }
+    }
+
+    /// Whether the light is a single point or direction, which scattered
+    /// rays can never hit.
+    pub fn is_delta(&self) -> bool {
+        matches!(self, Light::Point { .. } | Light::Spot { .. } | Light::Directional { .. })
+    }
+
+    pub fn sample<R: Rng>(&self, origin: &Vec3, rng: &mut R) -> Option<LightSample> {
+        match self {
+            Light::Sphere { center, radius, radiance } => {
+                let to_center = center - origin;
+                let d2 = to_center.length_squared();
+                let r2 = radius * radius;
+
+                if d2 <= r2 {
+                    // Inside the sphere every direction reaches it; sample its area.
+                    let point = center + &(radius.abs() * Vec3::rand_unit_vector(rng));
+                    let to_light = point - origin;
+                    let distance = to_light.length();
+                    if distance <= 0.0 {
+                        return None;
+                    }
+                    let direction = &to_light / distance;
+                    let ray = Ray::new(origin.clone(), direction.clone());
+                    let pdf = self.pdf(&ray, distance);
+                    return (pdf > 0.0).then(|| LightSample { direction, distance, pdf, radiance: radiance.clone() });
+                }
+
+                // Uniformly sample the cone of directions the sphere subtends.
+                let cos_theta_max = (1.0 - r2 / d2).max(0.0).sqrt();
+                let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_theta_max);
+                let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                let direction = Onb::from_w(&to_center)
+                    .local(phi.cos() * sin_theta, phi.sin() * sin_theta, cos_theta)
+                    .unit_vector();
+
+                let ray = Ray::new(origin.clone(), direction.clone());
+                let distance = self.intersect(&ray, 0.0, f64::INFINITY)?;
+                Some(LightSample {
+                    direction,
+                    distance,
+                    pdf: 1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+                    radiance: radiance.clone(),
+                })
+            }
+            Light::Triangle { p0, p1, p2, radiance } => {
+                let su = rng.gen::<f64>().sqrt();
+                let b0 = 1.0 - su;
+                let b1 = rng.gen::<f64>() * su;
+                let point = &(b0 * p0) + &(b1 * p1) + ((1.0 - b0 - b1) * p2);
+                let normal = (p1 - p0).cross(&(p2 - p0));
+                let area = 0.5 * normal.length();
+                area_sample(origin, point, &normal, area, radiance)
+            }
+            Light::Quad { q, u, v, radiance } => {
+                let point = q + &(rng.gen::<f64>() * u) + (rng.gen::<f64>() * v);
+                let normal = u.cross(v);
+                area_sample(origin, point, &normal, normal.length(), radiance)
+            }
+            Light::Disk { center, normal, radius, radiance } => {
+                let r = radius * rng.gen::<f64>().sqrt();
+                let phi = 2.0 * PI * rng.gen::<f64>();
+                let point = center + &Onb::from_w(normal).local(r * phi.cos(), r * phi.sin(), 0.0);
+                area_sample(origin, point, normal, PI * radius * radius, radiance)
+            }
+            Light::Point { position, intensity } => point_sample(origin, position, intensity.clone()),
+            Light::Spot { position, direction, intensity, cos_inner, cos_outer } => {
+                let cosine = (origin - position).unit_vector().dot(direction);
+                let falloff = smoothstep(*cos_outer, *cos_inner, cosine);
+                if falloff <= 0.0 {
+                    return None;
+                }
+                point_sample(origin, position, falloff * intensity)
+            }
+            Light::Directional { direction, irradiance } => Some(LightSample {
+                direction: -direction,
+                distance: f64::INFINITY,
+                pdf: 1.0,
+                radiance: irradiance.clone(),
+            }),
+        }
+    }
+
+    /// Closest `t` in the range where `ray` reaches the light.
+    pub fn intersect(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<f64> {
+        match self {
+            Light::Sphere { center, radius, .. } => {
+                let oc = &ray.origin - center;
+                let a = ray.direction.length_squared();
+                let half_b = oc.dot(&ray.direction);
+                let c = oc.length_squared() - radius * radius;
+                let discriminant = half_b * half_b - a * c;
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let sqrtd = discriminant.sqrt();
+                [(-half_b - sqrtd) / a, (-half_b + sqrtd) / a]
+                    .into_iter()
+                    .find(|&t| t_min <= t && t <= t_max)
+            }
+            Light::Triangle { p0, p1, p2, .. } => {
+                intersect_triangle(ray, p0, p1, p2, t_min, t_max).map(|(t, _)| t)
+            }
+            Light::Quad { q, u, v, .. } => intersect_quad(ray, q, u, v, t_min, t_max).map(|(t, _, _)| t),
+            Light::Disk { center, normal, radius, .. } => intersect_disk(ray, center, normal, *radius, t_min, t_max),
+            Light::Point { .. } | Light::Spot { .. } | Light::Directional { .. } => None,
+        }
+    }
+
+    /// Solid angle density with which `sample` picks the direction of
+    /// `ray`, given that the ray reaches this light at `t`.
+    pub fn pdf(&self, ray: &Ray, t: f64) -> f64 {
+        match self {
+            Light::Sphere { center, radius, .. } => {
+                let d2 = (center - &ray.origin).length_squared();
+                let r2 = radius * radius;
+                if d2 > r2 {
+                    let cos_theta_max = (1.0 - r2 / d2).max(0.0).sqrt();
+                    return 1.0 / (2.0 * PI * (1.0 - cos_theta_max));
+                }
+                let point = ray.at(t);
+                let distance = t * ray.direction.length();
+                let normal = (&point - center).unit_vector();
+                let cosine = normal.dot(&ray.direction.unit_vector()).abs();
+                if cosine < 1e-8 {
+                    return 0.0;
+                }
+                distance * distance / (cosine * 4.0 * PI * r2)
+            }
+            Light::Triangle { p0, p1, p2, .. } => {
+                let normal = (p1 - p0).cross(&(p2 - p0));
+                area_pdf(ray, t, &normal, 0.5 * normal.length())
+            }
+            Light::Quad { u, v, .. } => {
+                let normal = u.cross(v);
+                area_pdf(ray, t, &normal, normal.length())
+            }
+            Light::Disk { normal, radius, .. } => area_pdf(ray, t, normal, PI * radius * radius),
+            Light::Point { .. } | Light::Spot { .. } | Light::Directional { .. } => 0.0,
+        }
+    }
+}
+
+/// Samples a point picked uniformly on a flat light of the given area,
+/// converting the density to solid angle. Lights emit from both sides.
+fn area_sample(origin: &Vec3, point: Vec3, normal: &Vec3, area: f64, radiance: &Color) -> Option<LightSample> {
+    let to_light = point - origin;
+    let distance = to_light.length();
+    if distance <= 0.0 || area <= 0.0 {
+        return None;
+    }
+    let direction = &to_light / distance;
+    let cosine = normal.unit_vector().dot(&direction).abs();
+    if cosine < 1e-8 {
+        return None;
+    }
+    Some(LightSample {
+        direction,
+        distance,
+        pdf: distance * distance / (cosine * area),
+        radiance: radiance.clone(),
+    })
+}
+
+/// The only direction towards a point light, with the intensity turned
+/// into irradiance at `origin`.
+fn point_sample(origin: &Vec3, position: &Vec3, intensity: Color) -> Option<LightSample> {
+    let to_light = position - origin;
+    let distance = to_light.length();
+    if distance <= 0.0 {
+        return None;
+    }
+    Some(LightSample {
+        direction: &to_light / distance,
+        distance,
+        pdf: 1.0,
+        radiance: intensity / (distance * distance),
+    })
+}
+
+/// Smooth step from 0 at `edge0` to 1 at `edge1`.
+fn smoothstep(edge0: f64, edge1: f64, x: f64) -> f64 {
+    if edge1 <= edge0 {
+        return if x >= edge0 { 1.0 } else { 0.0 };
+    }
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn area_pdf(ray: &Ray, t: f64, normal: &Vec3, area: f64) -> f64 {
+    let cosine = normal.unit_vector().dot(&ray.direction.unit_vector()).abs();
+    if cosine < 1e-8 || area <= 0.0 {
+        return 0.0;
+    }
+    let distance = t * ray.direction.length();
+    distance * distance / (cosine * area)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn assert_pdf_matches_sample(light: &Light, origin: &Vec3) {
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..100 {
+            let sample = light.sample(origin, &mut rng).unwrap();
+            let ray = Ray::new(origin.clone(), sample.direction.clone());
+            let t = light.intersect(&ray, 0.0, f64::INFINITY).unwrap();
+            assert!((t - sample.distance).abs() < 1e-9);
+            assert!((light.pdf(&ray, t) - sample.pdf).abs() <= 1e-9 * sample.pdf);
+        }
+    }
+
+    #[test]
+    fn test_sphere_sample_pdf() {
+        let light = Light::Sphere { center: Vec3::new(0.0, 2.0, 0.0), radius: 0.5, radiance: Color::new(1.0, 1.0, 1.0) };
+        assert_pdf_matches_sample(&light, &Vec3::new(0.0, 0.0, 0.0));
+        // From inside, the sphere is sampled by area.
+        assert_pdf_matches_sample(&light, &Vec3::new(0.1, 2.1, 0.0));
+    }
+
+    #[test]
+    fn test_triangle_sample_pdf() {
+        let light = Light::Triangle {
+            p0: Vec3::new(-1.0, 2.0, -1.0),
+            p1: Vec3::new(1.0, 2.0, -1.0),
+            p2: Vec3::new(0.0, 2.0, 1.0),
+            radiance: Color::new(1.0, 1.0, 1.0),
+        };
+        assert_pdf_matches_sample(&light, &Vec3::new(0.3, 0.0, 0.2));
+    }
+
+    #[test]
+    fn test_quad_and_disk_sample_pdf() {
+        let quad = Light::Quad {
+            q: Vec3::new(-1.0, 2.0, -1.0),
+            u: Vec3::new(2.0, 0.0, 0.0),
+            v: Vec3::new(0.5, 0.0, 2.0),
+            radiance: Color::new(1.0, 1.0, 1.0),
+        };
+        assert_pdf_matches_sample(&quad, &Vec3::new(0.3, 0.0, 0.2));
+        let disk = Light::Disk {
+            center: Vec3::new(0.0, 2.0, 0.0),
+            normal: Vec3::new(0.0, -1.0, 1.0).unit_vector(),
+            radius: 0.5,
+            radiance: Color::new(1.0, 1.0, 1.0),
+        };
+        assert_pdf_matches_sample(&disk, &Vec3::new(0.3, 0.0, 0.2));
+    }
+
+    #[test]
+    fn test_punctual_lights() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let white = Color::new(4.0, 4.0, 4.0);
+        let point = Light::Point { position: Vec3::new(0.0, 2.0, 0.0), intensity: white.clone() };
+        assert!(point.is_delta());
+        let sample = point.sample(&Vec3::new(0.0, 0.0, 0.0), &mut rng).unwrap();
+        assert_eq!((sample.distance, sample.pdf), (2.0, 1.0));
+        assert_eq!(sample.radiance, Color::new(1.0, 1.0, 1.0));
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), sample.direction);
+        assert_eq!(point.intersect(&ray, 0.0, f64::INFINITY), None);
+
+        // Full intensity inside the inner cone, half way between the cone
+        // angles' cosines, and nothing outside the outer one.
+        let spot = Light::spot(Vec3::new(0.0, 1.0, 0.0), &Vec3::new(0.0, 0.0, 0.0), white, 20.0, 40.0);
+        let at = |angle: f64| {
+            let x = degrees_to_radians(angle).tan();
+            spot.sample(&Vec3::new(x, 0.0, 0.0), &mut StdRng::seed_from_u64(0)).map(|s| s.radiance.r() * (1.0 + x * x))
+        };
+        assert!((at(10.0).unwrap() - 4.0).abs() < 1e-9);
+        let middle = (degrees_to_radians(20.0).cos() + degrees_to_radians(40.0).cos()) / 2.0;
+        assert!((at(middle.acos().to_degrees()).unwrap() - 2.0).abs() < 1e-9);
+        assert!(at(45.0).is_none());
+
+        let sun = Light::Directional { direction: Vec3::new(0.0, -1.0, 0.0), irradiance: Color::new(3.0, 3.0, 3.0) };
+        let sample = sun.sample(&Vec3::new(5.0, 0.0, 5.0), &mut rng).unwrap();
+        assert_eq!(sample.direction, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(sample.distance, f64::INFINITY);
+    }
+}
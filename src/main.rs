@@ -8,30 +8,67 @@ mod camera;
 mod util;
 mod material;
 mod figure;
-
-use std::fs::File;
-use std::io::{BufWriter, Write};
+mod aabb;
+mod bvh;
+mod mesh;
+mod obj;
+mod scene;
+mod image;
+mod output;
+mod hdr;
+mod background;
+mod onb;
+mod light;
+mod integrator;
+mod perlin;
+mod texture;
+mod matrix;
+mod instance;
+mod planar;
+mod cuboid;
+mod poly;
+mod quadric;
+mod torus;
+mod csg;
+mod medium;
+mod grid;
+mod microfacet;
+mod distribution;
+mod environment;
+mod daylight;
 
 use clap::Parser;
 use vec3::Vec3;
 use color::Color;
-use ray::Ray;
 use world::World;
-use figure::{Figure, Sphere};
+use figure::Figure;
 use camera::Camera;
-use material::{Material, Lambertian, Metal, Dielectric};
+use background::Background;
+use image::Image;
+use output::Format;
+use material::Material;
+use integrator::{ray_color, PathLength, Termination};
+use environment::Environment;
 
 use rayon::prelude::*;
-use std::rc::Rc;
 use rand::{Rng, self};
+use std::sync::Arc;
 
 /// Simple rust ray tracer
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-    /// Recursion depth limit for rays
+    /// Most bounces a path can take [default: 10 with depth termination, 1000 with roulette]
     #[arg(short, long)]
-    max_depth: Option<u8>,
+    max_depth: Option<u32>,
+
+    /// How paths end: depth (at the maximum depth, the default) or roulette (randomly by throughput)
+    #[arg(long)]
+    termination: Option<Termination>,
+
+    /// Bounces before Russian roulette may end a path [default: 3]
+    #[arg(long)]
+    min_bounces: Option<u32>,
 
     /// Width of the output image
     #[arg(short, long)]
@@ -45,41 +82,93 @@ struct Args {
     #[arg(short, long)]
     samples: Option<usize>,
 
-    /// Output file name
+    /// Output file name; the extension (.png, .ppm, .exr or .hdr) picks the format
     #[arg(short, long)]
     file: std::path::PathBuf,
+
+    /// Bits per channel: 8 (default) or 16 for .png and .ppm, 16 (default) or 32 for .exr
+    #[arg(long)]
+    bit_depth: Option<u8>,
+
+    /// Write plain text (P3) instead of binary (P6) PPM files
+    #[arg(long)]
+    ascii: bool,
+
+    /// What escaping rays see: sky, black or an r,g,b color
+    #[arg(long)]
+    background: Option<Background>,
+
+    /// Equirectangular .hdr or .exr map to light the scene with, instead of the background
+    #[arg(long)]
+    environment: Option<std::path::PathBuf>,
+
+    /// Turn of the environment map around the up axis, in degrees
+    #[arg(long, default_value_t = 0.0)]
+    environment_rotation: f64,
+
+    /// Brightness multiplier for the environment map
+    #[arg(long, default_value_t = 1.0, value_parser = non_negative)]
+    environment_intensity: f64,
+
+    /// Scene description file; renders the random spheres scene if omitted
+    #[arg(long)]
+    scene: Option<std::path::PathBuf>,
+
+    /// Lens diameter for depth of field; 0 is a pinhole camera
+    #[arg(long, value_parser = non_negative)]
+    aperture: Option<f64>,
+
+    /// Distance to the plane in focus; defaults to the distance to the look-at point
+    #[arg(long, value_parser = positive)]
+    focus_dist: Option<f64>,
+
+    /// Shutter open and close times as OPEN,CLOSE, for motion blur
+    #[arg(long, value_parser = shutter)]
+    shutter: Option<(f64, f64)>,
 }
 
-//TODO: Change rand unit vector to random in hemisphere!!!
-//TODO: Fuzz is the min between 1 and the fuzz
-fn ray_color<R: Rng>(r: &Ray, world: &World, rng: &mut R, depth: u8) -> Color {
-    if depth <= 0 {
-        return Color::new(0.0, 0.0, 0.0);
+fn non_negative(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x >= 0.0 => Ok(x),
+        _ => Err(format!("`{}` is not a non-negative number", s)),
     }
+}
 
-    if let Some(rec) = world.hit(r, 0.001, std::f64::INFINITY) {
-        if let Some((attenuation, scattered)) = rec.material.scatter(r, &rec, rng) {
-            return attenuation * ray_color(&scattered, world, rng, depth - 1);
-        } else {
-            return Color::new(0.0, 0.0, 0.0);
-        }
+fn shutter(s: &str) -> Result<(f64, f64), String> {
+    let times = s
+        .split(',')
+        .map(|t| t.trim().parse::<f64>())
+        .collect::<Result<Vec<f64>, _>>()
+        .map_err(|e| e.to_string())?;
+    match times[..] {
+        [open, close] if open <= close => Ok((open, close)),
+        [_, _] => Err("the shutter closes before it opens".to_string()),
+        _ => Err("expected OPEN,CLOSE".to_string()),
     }
+}
 
-    let unit_direction = r.direction.unit_vector();
-    let t = 0.5f64 * (unit_direction.y() + 1.0f64);
-    let white = Color::new_color(1.0, 1.0, 1.0);
-    let col = Color::new_color(0.5, 0.7, 1.0);
-    return ((1.0f64 - t) * &white) + (t * &col);
+fn positive(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x > 0.0 => Ok(x),
+        _ => Err(format!("`{}` is not a positive number", s)),
+    }
 }
 
-fn create_final_world<R: Rng>(rng: &mut R, aspect_ratio: f64) -> (World, Camera) {
+fn create_final_world<R: Rng>(rng: &mut R, aspect_ratio: f64, aperture: Option<f64>, focus_dist: Option<f64>, shutter: Option<(f64, f64)>) -> (World, Camera) {
     let mut world = World::new();
+    let lookfrom = Vec3::new(13.0, 2.0, 3.0);
+    let lookat = Vec3::new(0.0, 0.0, 0.0);
+    let focus_dist = focus_dist.unwrap_or_else(|| (&lookfrom - &lookat).length());
     let camera = Camera::new(
-        Vec3::new(13.0, 2.0, 3.0),  // lookfrom
-        Vec3::new(0.0, 0.0, 0.0), // lookat
+        lookfrom,
+        lookat,
         Vec3::new(0.0, 1.0, 0.0),  // up
         20.0,                      // fov
-        aspect_ratio);
+        aspect_ratio,
+        aperture.unwrap_or(0.0),
+        focus_dist);
+    let (open, close) = shutter.unwrap_or((0.0, 0.0));
+    let camera = camera.with_shutter(open, close);
 
     for a in -11..11 {
         for b in -11..11 {
@@ -97,7 +186,7 @@ fn create_final_world<R: Rng>(rng: &mut R, aspect_ratio: f64) -> (World, Camera)
                 } else if choose_mat < 0.95 {
                     let albedo = Color::rand_range(rng, 0.5, 1.0);
                     let fuzz = rng.gen_range(0.0..=0.5);
-                    let sphere_mat = Material::metal(albedo, fuzz);
+                    let sphere_mat = Material::conductor_albedo(&albedo, fuzz);
                     let sphere = Figure::sphere(center, 0.2, sphere_mat); 
                     world.add(sphere);
                 } else {
@@ -115,7 +204,7 @@ fn create_final_world<R: Rng>(rng: &mut R, aspect_ratio: f64) -> (World, Camera)
     world.add(sphere1);
     
     let alb2 = Color::new(0.7, 0.6, 0.5);
-    let mat2 = Material::metal(alb2, 0.0); 
+    let mat2 = Material::conductor_albedo(&alb2, 0.0); 
     let sphere2 = Figure::sphere(Vec3::new(4.0, 1.0, 0.0), 1.0, mat2);
     world.add(sphere2);
 
@@ -125,61 +214,8 @@ fn create_final_world<R: Rng>(rng: &mut R, aspect_ratio: f64) -> (World, Camera)
 
     let ground_alb = Color::new(0.5, 0.5, 0.5);
     let ground_mat = Material::lambertian(ground_alb);
-    let sphere = Figure::sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0, ground_mat);
-    world.add(sphere);
-    (world, camera)
-}
-
-fn create_world_with_three_spheres() -> (World, Camera) {
-
-    let mat_ground = Material::lambertian(Color::new_color(0.8, 0.8, 0.0));
-    let mat_center = Material::lambertian(Color::new_color(0.1, 0.2, 0.5));
-    let mat_left_1 = Material::dielectric(1.5);
-    let mat_left_2 = Material::dielectric(1.5);
-    let mat_right = Material::metal(Color::new_color(0.8, 0.6, 0.2), 0.0);
-
-    let mut world = World::new();
-    let sphere_ground = Figure::sphere(Vec3::new(0.0, -100.5, -1.0), 100.0, mat_ground);
-    let sphere1 = Figure::sphere(Vec3::new(0.0, 0.0, -1.0), 0.5, mat_center);
-    let sphere2_1 = Figure::sphere(Vec3::new(-1.0, 0.0, -1.0), 0.5, mat_left_1);
-    let sphere2_2 = Figure::sphere(Vec3::new(-1.0, 0.0, -1.0), -0.4, mat_left_2);
-    let sphere3 = Figure::sphere(Vec3::new(1.0, 0.0, -1.0), 0.5, mat_right);
-
-    let camera = Camera::new(
-        Vec3::new(-2.0, 2.0, 1.0),  // lookfrom
-        Vec3::new(0.0, 0.0, -1.0), // lookat
-        Vec3::new(0.0, 1.0, 0.0),  // up
-        20.0,                      // fov
-        16.0 / 9.0);
-
-    world.add(sphere_ground);
-    world.add(sphere1);
-    world.add(sphere2_1);
-    world.add(sphere2_2);
-    world.add(sphere3);
-    (world, camera)
-}
-
-fn create_two_spheres_world() -> (World, Camera) {
-
-    let mat_left = Material::lambertian(Color::new_color(0.0, 0.0, 1.0));
-    let mat_right = Material::lambertian(Color::new_color(1.0, 0.0, 0.0));
-
-    let r = (std::f64::consts::PI / 4.0).cos();
-    let mut world = World::new();
-    let sphere_left = Figure::sphere(Vec3::new(-r, 0.0, -1.0), r, mat_left);
-    let sphere_right = Figure::sphere(Vec3::new(r, 0.0, -1.0), r, mat_right);
-
-    world.add(sphere_left);
-    world.add(sphere_right);
-
-    let camera = Camera::new(
-        Vec3::new(0.0, 0.0, 0.0), // lookfrom
-        Vec3::new(0.0, 0.0, -1.0), // lookat
-        Vec3::new(0.0, 1.0, 0.0),  // up
-        90.0,                      // fov
-        16.0 / 9.0);
-
+    let ground = Figure::plane(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), ground_mat);
+    world.add(ground);
     (world, camera)
 }
 
@@ -193,38 +229,86 @@ fn create_two_spheres_world() -> (World, Camera) {
 fn main() {
     let args = Args::parse();
 
+    // Reject unusable output paths before spending time on the render.
+    let format = Format::from_path(&args.file, args.bit_depth, args.ascii)
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+    let scene = args.scene.as_ref().map(|path| {
+        scene::load_scene(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        })
+    });
+    // Command line arguments win over the scene's render settings.
+    let settings = scene.as_ref().map(|s| &s.settings);
+
     let image_width = args
         .width
+        .or(settings.and_then(|s| s.width))
         .unwrap_or(200);
     let image_height = args
         .height
+        .or(settings.and_then(|s| s.height))
         .unwrap_or(200);
+    let termination = args
+        .termination
+        .or(settings.and_then(|s| s.termination))
+        .unwrap_or(Termination::Depth);
     let max_depth = args
         .max_depth
-        .unwrap_or(10);
+        .or(settings.and_then(|s| s.max_depth))
+        .unwrap_or(match termination {
+            Termination::Depth => 10,
+            Termination::Roulette => 1000,
+        });
+    let min_bounces = args
+        .min_bounces
+        .or(settings.and_then(|s| s.min_bounces))
+        .unwrap_or(3);
+    let length = PathLength { max_depth, termination, min_bounces };
     let samples_per_pixel = args
         .samples
+        .or(settings.and_then(|s| s.samples))
         .unwrap_or(50);
+    let environment = args.environment.as_ref().map(|path| {
+        let image = Image::load(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1);
+        });
+        Background::Environment(Arc::new(Environment::new(image, args.environment_rotation, args.environment_intensity)))
+    });
+    let background = environment
+        .or(args.background.clone())
+        .or(settings.and_then(|s| s.background.clone()))
+        .unwrap_or(Background::Sky);
 
     let file_name = args.file;
 
     let aspect_ratio = image_width as f64 / image_height as f64;
 
 
-    let mut first_rng = rand::thread_rng();
-    let (world, camera) = create_final_world(&mut first_rng, aspect_ratio);
-
-    let file = File::create(file_name).unwrap();
-    let mut writer = BufWriter::new(&file);
-
-    writeln!(&mut writer, "P3\n{} {}\n255", image_width, image_height);
+    let (world, camera) = match scene {
+        Some(scene) => {
+            let camera = scene.camera(aspect_ratio, args.aperture, args.focus_dist, args.shutter);
+            (scene.world, camera)
+        }
+        None => {
+            let mut first_rng = rand::thread_rng();
+            create_final_world(&mut first_rng, aspect_ratio, args.aperture, args.focus_dist, args.shutter)
+        }
+    };
+    world.build_bvh();
 
-    let res = (0..image_height)
+    // Rows are rendered bottom-up by v but stored top row first.
+    let rows = (0..image_height)
         .into_par_iter()
+        .rev()
         .map(|row| {
-            let percent = (image_height as f64 - row as f64) / (image_height as f64) * 100.0;
             let mut rng = rand::thread_rng();
-            return (0..image_width).map(|col| {
+            (0..image_width).map(|col| {
                 let mut pixel_color = Color::new_color(0.0, 0.0, 0.0);
                 for _ in 0..samples_per_pixel {
 
@@ -234,14 +318,18 @@ fn main() {
                     let u = (col as f64 + ru) / (image_width-1) as f64;
                     let v = (row as f64 + rv)/ (image_height-1) as f64;
 
-                    let ray = camera.get_ray(u, v);
-                    pixel_color += ray_color(&ray, &world, &mut rng, max_depth);
+                    let ray = camera.get_ray(u, v, &mut rng);
+                    pixel_color += ray_color(&ray, &world, &background, &mut rng, &length);
                 }
-                return format!("{}\n", pixel_color.ppm_color(samples_per_pixel as f64));
-            }).collect::<String>()
+                let samples = samples_per_pixel as f64;
+                Color::new_color(pixel_color.r() / samples, pixel_color.g() / samples, pixel_color.b() / samples)
+            }).collect::<Vec<Color>>()
         })
-        .rev()
-        .collect::<String>();
+        .collect::<Vec<Vec<Color>>>();
 
-    writeln!(&mut writer, "{}", res);
+    let image = Image::new(image_width, image_height, rows.concat());
+    if let Err(err) = output::write_image(&file_name, &image, format) {
+        eprintln!("{}: {}", file_name.display(), err);
+        std::process::exit(1);
+    }
 }
@@ -2,113 +2,844 @@ use crate::vec3::Vec3;
 use crate::color::Color;
 use crate::ray::Ray;
 use crate::hitrecord::HitRecord;
-use crate::util::reflectance;
+use crate::texture::Texture;
+use crate::onb::Onb;
+use crate::aabb::Aabb;
+use crate::grid::{VoxelGrid, blackbody};
+use crate::microfacet::{self, Ggx};
+use crate::util;
 
 use rand::Rng;
+use serde::Deserialize;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Lambertian {
-    pub albedo: Color,
+    pub albedo: Texture,
 }
 
 impl Lambertian {
-    pub fn new(albedo: Color) -> Lambertian {
+    pub fn new(albedo: Texture) -> Lambertian {
         Lambertian { albedo }
     }
 
+    /// Lambert's BRDF, reflecting the same radiance in every direction.
+    pub fn brdf(&self, rec: &HitRecord) -> Color {
+        self.albedo.value(rec.u, rec.v, &rec.p) / PI
+    }
+
+    /// Scatters with density cos θ / π: the normal plus a uniformly random
+    /// unit vector points at a uniform point on the unit sphere touching
+    /// the surface, which is seen under that distribution. The density
+    /// cancels the BRDF and its cosine, so the attenuation is the albedo.
     pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
-        let target = &rec.p + &rec.normal + Vec3::rand_in_unit_sphere(rng);
-        let scattered = Ray::new(rec.p.clone(), target - &rec.p);
-        Some((self.albedo.clone(), scattered))
+        let mut direction = &rec.normal + Vec3::rand_unit_vector(rng);
+        // The random vector can cancel the normal, leaving no direction.
+        if direction.near_zero() {
+            direction = rec.normal.clone();
+        }
+        let scattered = Ray::new(rec.p.clone(), direction).with_time(ray_in.time);
+        Some((self.albedo.value(rec.u, rec.v, &rec.p), scattered))
+    }
+
+    /// BRDF times the cosine to the normal.
+    pub fn eval(&self, rec: &HitRecord, direction: &Vec3) -> Color {
+        let cosine = rec.normal.dot(&direction.unit_vector()).max(0.0);
+        cosine * self.brdf(rec)
+    }
+
+    pub fn pdf(&self, rec: &HitRecord, direction: &Vec3) -> f64 {
+        rec.normal.dot(&direction.unit_vector()).max(0.0) / PI
+    }
+}
+
+/// Basis around the normal at a hit, with the directions towards the
+/// viewer and towards `direction` in it. Microfacet materials work there.
+fn shading_frame(ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Onb, Vec3, Vec3) {
+    let basis = Onb::from_w(&rec.normal);
+    let local = |d: &Vec3| {
+        let d = d.unit_vector();
+        Vec3::new(d.dot(&basis.u), d.dot(&basis.v), d.dot(&basis.w))
+    };
+    let (wo, wi) = (local(&-(ray_in.direction.clone())), local(direction));
+    (basis, wo, wi)
+}
+
+/// Measured metals, with their complex indices of refraction at roughly
+/// 650, 550 and 450 nm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConductorPreset {
+    Gold,
+    Silver,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+}
+
+impl ConductorPreset {
+    /// Real and imaginary parts of the index of refraction.
+    pub fn ior(&self) -> (Color, Color) {
+        let (eta, k) = match self {
+            ConductorPreset::Gold => ([0.143, 0.374, 1.442], [3.983, 2.385, 1.603]),
+            ConductorPreset::Silver => ([0.155, 0.117, 0.138], [4.828, 3.122, 2.147]),
+            ConductorPreset::Copper => ([0.200, 0.924, 1.102], [3.912, 2.452, 2.142]),
+            ConductorPreset::Aluminium => ([1.657, 0.880, 0.521], [9.224, 6.270, 4.837]),
+        };
+        (Color::new(eta[0], eta[1], eta[2]), Color::new(k[0], k[1], k[2]))
     }
 }
 
+/// Metal with a GGX microfacet surface and the Fresnel reflectance of its
+/// complex index of refraction. Light that bounces between microfacets
+/// more than once comes back through a diffuse-like lobe (Kulla and Conty
+/// 2017), so rough metal doesn't darken.
 #[derive(Debug, Clone)]
-pub struct Metal {
-    pub albedo: Color,
-    pub fuzz: f64,
+pub struct Conductor {
+    pub eta: Color,
+    pub k: Color,
+    pub roughness: f64,
+    /// Texture the reflected light is multiplied by, for patterned metal.
+    pub tint: Option<Texture>,
+    /// Tint of the light leaving after several bounces.
+    multiple: Color,
 }
 
-impl Metal {
-    pub fn new(albedo: Color, fuzz: f64) -> Metal {
-        Metal { albedo, fuzz }
+impl Conductor {
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Conductor {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let f_avg = microfacet::average_fresnel(|mu| microfacet::fresnel_conductor(mu, &eta, &k));
+        let e_avg = microfacet::average_albedo(roughness);
+        // Each further bounce keeps f_avg of the light and escapes with
+        // e_avg; summing the series gives the tint.
+        let tint = |f: f64| f * f * e_avg / (1.0 - f * (1.0 - e_avg));
+        let multiple = Color::new(tint(f_avg.r()), tint(f_avg.g()), tint(f_avg.b()));
+        Conductor { eta, k, roughness, tint: None, multiple }
+    }
+
+    pub fn with_tint(mut self, tint: Texture) -> Conductor {
+        self.tint = Some(tint);
+        self
+    }
+
+    fn tint(&self, rec: &HitRecord) -> Color {
+        match &self.tint {
+            Some(tint) => tint.value(rec.u, rec.v, &rec.p),
+            None => Color::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    /// Metal reflecting `albedo` head on, with the same tint towards
+    /// grazing angles: the index of refraction is fitted per channel as in
+    /// Gulbrandsen 2014.
+    pub fn from_albedo(albedo: &Color, roughness: f64) -> Conductor {
+        let fit = |r: f64| {
+            let r = r.clamp(0.0, 0.99);
+            let n = r * (1.0 - r) / (1.0 + r) + (1.0 - r) * (1.0 + r.sqrt()) / (1.0 - r.sqrt());
+            let k = (((n + 1.0) * (n + 1.0) * r - (n - 1.0) * (n - 1.0)) / (1.0 - r)).max(0.0).sqrt();
+            (n, k)
+        };
+        let (r, g, b) = (fit(albedo.r()), fit(albedo.g()), fit(albedo.b()));
+        Conductor::new(Color::new(r.0, g.0, b.0), Color::new(r.1, g.1, b.1), roughness)
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.roughness == 0.0
+    }
+
+    /// Chance of sampling the single-bounce lobe rather than the diffuse one.
+    fn single_probability(&self, cos_o: f64) -> f64 {
+        microfacet::directional_albedo(self.roughness, cos_o)
     }
 
     pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
-        let reflected = ray_in.direction.unit_vector().reflect(&rec.normal);
-        let scattered = Ray::new(rec.p.clone(), reflected + (self.fuzz * Vec3::rand_in_unit_sphere(rng)));
-        if scattered.direction.dot(&rec.normal) > 0.0 {
-            Some((self.albedo.clone(), scattered))
+        if self.is_smooth() {
+            let unit_direction = ray_in.direction.unit_vector();
+            let cos_theta = -unit_direction.dot(&rec.normal);
+            let reflected = unit_direction.reflect(&rec.normal);
+            let scattered = Ray::new(rec.p.clone(), reflected).with_time(ray_in.time);
+            let fresnel = microfacet::fresnel_conductor(cos_theta, &self.eta, &self.k);
+            return Some((fresnel * self.tint(rec), scattered));
+        }
+
+        let (basis, wo, _) = shading_frame(ray_in, rec, &rec.normal);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let wi = if rng.gen::<f64>() < self.single_probability(wo.z()) {
+            let ggx = Ggx::new(microfacet::alpha(self.roughness));
+            let h = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+            (2.0 * wo.dot(&h)) * &h - &wo
         } else {
-            None
+            let (r1, r2): (f64, f64) = (rng.gen(), rng.gen());
+            let phi = 2.0 * PI * r1;
+            Vec3::new(phi.cos() * r2.sqrt(), phi.sin() * r2.sqrt(), (1.0 - r2).sqrt())
+        };
+        if wi.z() <= 0.0 {
+            return None;
+        }
+        let direction = basis.local(wi.x(), wi.y(), wi.z());
+        let attenuation = self.eval(ray_in, rec, &direction) / self.pdf(ray_in, rec, &direction);
+        Some((attenuation, Ray::new(rec.p.clone(), direction).with_time(ray_in.time)))
+    }
+
+    pub fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (_, wo, wi) = shading_frame(ray_in, rec, direction);
+        if self.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        let ggx = Ggx::new(microfacet::alpha(self.roughness));
+        let h = (&wo + &wi).unit_vector();
+        let fresnel = microfacet::fresnel_conductor(wo.dot(&h), &self.eta, &self.k);
+        let single = (ggx.d(&h) * ggx.g2(&wo, &wi) / (4.0 * wo.z())) * fresnel;
+
+        let albedo = |cos: f64| microfacet::directional_albedo(self.roughness, cos);
+        let e_avg = microfacet::average_albedo(self.roughness);
+        let multiple = ((1.0 - albedo(wo.z())) * (1.0 - albedo(wi.z())) * wi.z() / (PI * (1.0 - e_avg)).max(1e-6))
+            * &self.multiple;
+        (single + multiple) * self.tint(rec)
+    }
+
+    pub fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (_, wo, wi) = shading_frame(ray_in, rec, direction);
+        if self.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
         }
+        let ggx = Ggx::new(microfacet::alpha(self.roughness));
+        let h = (&wo + &wi).unit_vector();
+        // Reflecting about h halves the solid angle: dω_i = 4 |wo·h| dω_h.
+        let single = ggx.visible_pdf(&wo, &h) / (4.0 * wo.dot(&h));
+        let p = self.single_probability(wo.z());
+        p * single + (1.0 - p) * wi.z() / PI
     }
 }
 
+/// How a dielectric splits light between reflection and refraction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fresnel {
+    /// Schlick's approximation, as in the book.
+    #[default]
+    Schlick,
+    /// The Fresnel equations for unpolarized light.
+    Exact,
+}
+
+/// Glass, water and the like. Smooth by default; rough ones scatter
+/// through GGX microfacets (Walter et al. 2007) and are frosted.
 #[derive(Debug, Clone)]
 pub struct Dielectric {
     pub ref_idx: f64,
+    pub roughness: f64,
+    pub fresnel: Fresnel,
 }
 
 impl Dielectric {
     pub fn new(ref_idx: f64) -> Dielectric {
-        Dielectric { ref_idx }
+        Dielectric { ref_idx, roughness: 0.0, fresnel: Fresnel::default() }
     }
 
-    pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
-        let attenuation = Color::new(1.0, 1.0, 1.0);
-        let refraction_ratio = if rec.front_face {
+    pub fn with_fresnel(mut self, fresnel: Fresnel) -> Dielectric {
+        self.fresnel = fresnel;
+        self
+    }
+
+    /// Roughness from 0, perfectly smooth, to 1.
+    pub fn with_roughness(mut self, roughness: f64) -> Dielectric {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.roughness == 0.0
+    }
+
+    /// Index of refraction past the surface over the one in front of it.
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ref_idx
+        } else {
             1.0 / self.ref_idx
+        }
+    }
+
+    /// Share of the light arriving at `cos_theta` from the normal that is
+    /// reflected, one under total internal reflection. `eta` is as in
+    /// `eta()`.
+    fn reflectance(&self, cos_theta: f64, eta: f64) -> f64 {
+        match self.fresnel {
+            Fresnel::Exact => microfacet::fresnel_dielectric(cos_theta, eta),
+            Fresnel::Schlick if eta * eta < 1.0 - cos_theta * cos_theta => 1.0,
+            Fresnel::Schlick => util::reflectance(cos_theta, eta),
+        }
+    }
+
+    pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
+        if self.is_smooth() {
+            return self.scatter_smooth(ray_in, rec, rng);
+        }
+        let (basis, wo, _) = shading_frame(ray_in, rec, &rec.normal);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(rec);
+        let ggx = Ggx::new(microfacet::alpha(self.roughness));
+        let h = ggx.sample_visible(&wo, rng.gen(), rng.gen());
+        let cos_o = wo.dot(&h);
+        // Fresnel picks between reflecting off the microfacet and
+        // refracting through it; it is one under total internal reflection.
+        let reflect = rng.gen::<f64>() < self.reflectance(cos_o, eta);
+        let wi = if reflect {
+            (2.0 * cos_o) * &h - &wo
         } else {
-            self.ref_idx
+            (-&wo).refract(&h, 1.0 / eta)
         };
+        // Steep microfacets can send light to the wrong side.
+        if (wi.z() > 0.0) != reflect {
+            return None;
+        }
+        let direction = basis.local(wi.x(), wi.y(), wi.z());
+        let pdf = self.pdf(ray_in, rec, &direction);
+        if pdf <= 0.0 {
+            return None;
+        }
+        let attenuation = self.eval(ray_in, rec, &direction) / pdf;
+        Some((attenuation, Ray::new(rec.p.clone(), direction).with_time(ray_in.time)))
+    }
+
+    /// Microfacet normal that reflects or refracts `wo` into `wi`, facing
+    /// the viewer's side, or `None` if no microfacet can.
+    fn half_vector(&self, eta: f64, wo: &Vec3, wi: &Vec3) -> Option<Vec3> {
+        let reflected = wi.z() > 0.0;
+        let h = if reflected { wo + wi } else { wo + (eta * wi) };
+        if h.near_zero() {
+            return None;
+        }
+        let h = h.unit_vector();
+        let h = if h.z() < 0.0 { -h } else { h };
+        // Refraction must cross the microfacet, reflection stay in front.
+        let crosses = wi.dot(&h) < 0.0;
+        (wo.dot(&h) > 0.0 && crosses != reflected).then_some(h)
+    }
+
+    /// Scale from the density of microfacet normals to the density of
+    /// directions they send light into.
+    fn jacobian(eta: f64, wo: &Vec3, wi: &Vec3, h: &Vec3) -> f64 {
+        if wi.z() > 0.0 {
+            1.0 / (4.0 * wo.dot(h))
+        } else {
+            let denom = wo.dot(h) + eta * wi.dot(h);
+            eta * eta * wi.dot(h).abs() / (denom * denom)
+        }
+    }
+
+    pub fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (_, wo, wi) = shading_frame(ray_in, rec, direction);
+        let eta = self.eta(rec);
+        let h = match self.half_vector(eta, &wo, &wi) {
+            Some(h) if !self.is_smooth() && wo.z() > 0.0 => h,
+            _ => return Color::new(0.0, 0.0, 0.0),
+        };
+        let ggx = Ggx::new(microfacet::alpha(self.roughness));
+        let fresnel = self.reflectance(wo.dot(&h), eta);
+        let share = if wi.z() > 0.0 { fresnel } else { 1.0 - fresnel };
+        // Like the smooth case, radiance isn't rescaled by eta² on the way
+        // through, so entering and leaving a solid cancel out.
+        let value = share * ggx.d(&h) * ggx.g2(&wo, &wi) * wo.dot(&h) / wo.z() * Self::jacobian(eta, &wo, &wi, &h);
+        Color::new(value, value, value)
+    }
+
+    pub fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (_, wo, wi) = shading_frame(ray_in, rec, direction);
+        let eta = self.eta(rec);
+        let h = match self.half_vector(eta, &wo, &wi) {
+            Some(h) if !self.is_smooth() && wo.z() > 0.0 => h,
+            _ => return 0.0,
+        };
+        let ggx = Ggx::new(microfacet::alpha(self.roughness));
+        let fresnel = self.reflectance(wo.dot(&h), eta);
+        let share = if wi.z() > 0.0 { fresnel } else { 1.0 - fresnel };
+        share * ggx.visible_pdf(&wo, &h) * Self::jacobian(eta, &wo, &wi, &h)
+    }
+
+    fn scatter_smooth<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
+        let attenuation = Color::new(1.0, 1.0, 1.0);
+        let eta = self.eta(rec);
 
         let unit_direction = ray_in.direction.unit_vector();
         let munit_direction = -(unit_direction.clone());
         let cos_theta = munit_direction.dot(&rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let cannot_refract = refraction_ratio * sin_theta > 1.0;
-        let direction = if cannot_refract {
+        let direction = if self.reflectance(cos_theta, eta) > rng.gen::<f64>() {
             unit_direction.reflect(&rec.normal)
         } else {
-            unit_direction.refract(&rec.normal, refraction_ratio)
+            unit_direction.refract(&rec.normal, 1.0 / eta)
         };
 
-        let ray = Ray::new(rec.p.clone(), direction);
-        return Some((attenuation, ray))
+        let ray = Ray::new(rec.p.clone(), direction).with_time(ray_in.time);
+        Some((attenuation, ray))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffuseLight {
+    pub color: Color,
+    pub intensity: f64,
+}
+
+impl DiffuseLight {
+    pub fn new(color: Color, intensity: f64) -> DiffuseLight {
+        DiffuseLight { color, intensity }
+    }
+
+    /// Emits the same radiance in every direction, from both sides.
+    pub fn emitted(&self) -> Color {
+        self.intensity * &self.color
+    }
+}
+
+/// Henyey-Greenstein phase function: the density of scattering by an angle
+/// with cosine `cos_theta` from the direction of travel. `g` is the mean
+/// cosine, positive for forward scattering and zero for isotropic.
+fn henyey_greenstein(cos_theta: f64, g: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+}
+
+/// Samples a direction around `forward` from the Henyey-Greenstein
+/// distribution by inverting its CDF.
+fn sample_henyey_greenstein<R: Rng>(forward: &Vec3, g: f64, rng: &mut R) -> Vec3 {
+    let xi: f64 = rng.gen();
+    let cos_theta = if g.abs() < 1e-3 {
+        1.0 - 2.0 * xi
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * xi);
+        (1.0 + g * g - s * s) / (2.0 * g)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng.gen::<f64>();
+    Onb::from_w(forward).local(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+}
+
+/// Phase function of a participating medium: scatters light the same
+/// way in every direction, wherever the medium puts the hit.
+#[derive(Debug, Clone)]
+pub struct Isotropic {
+    pub albedo: Texture,
+}
+
+impl Isotropic {
+    pub fn new(albedo: Texture) -> Isotropic {
+        Isotropic { albedo }
+    }
+
+    pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
+        let scattered = Ray::new(rec.p.clone(), Vec3::rand_unit_vector(rng)).with_time(ray_in.time);
+        Some((self.albedo.value(rec.u, rec.v, &rec.p), scattered))
+    }
+
+    pub fn eval(&self, rec: &HitRecord) -> Color {
+        (1.0 / (4.0 * PI)) * self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+}
+
+/// Anisotropic phase function for haze and smoke, which mostly scatter
+/// light forward (`g` > 0), or for backscattering media (`g` < 0).
+#[derive(Debug, Clone)]
+pub struct HenyeyGreenstein {
+    pub albedo: Texture,
+    pub g: f64,
+}
+
+impl HenyeyGreenstein {
+    pub fn new(albedo: Texture, g: f64) -> HenyeyGreenstein {
+        assert!(g.abs() < 1.0, "Henyey-Greenstein asymmetry must be between -1 and 1");
+        HenyeyGreenstein { albedo, g }
+    }
+
+    pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
+        let direction = sample_henyey_greenstein(&ray_in.direction.unit_vector(), self.g, rng);
+        let scattered = Ray::new(rec.p.clone(), direction).with_time(ray_in.time);
+        Some((self.albedo.value(rec.u, rec.v, &rec.p), scattered))
+    }
+
+    pub fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.pdf(ray_in, direction) * self.albedo.value(rec.u, rec.v, &rec.p)
+    }
+
+    pub fn pdf(&self, ray_in: &Ray, direction: &Vec3) -> f64 {
+        let cos_theta = ray_in.direction.unit_vector().dot(&direction.unit_vector());
+        henyey_greenstein(cos_theta, self.g)
+    }
+}
+
+/// Phase function of a voxel grid medium, glowing where the grid's
+/// emission and temperature channels say so.
+#[derive(Debug, Clone)]
+pub struct Volume {
+    pub phase: Box<Material>,
+    pub grid: Arc<VoxelGrid>,
+    /// Box the grid is stretched over.
+    pub bounds: Aabb,
+    pub emission_scale: f64,
+    pub temperature_scale: f64,
+}
+
+impl Volume {
+    /// Light emitted at a collision. Media only emit what they absorb, so
+    /// a phase function with a white albedo doesn't glow at all.
+    pub fn glow(&self, rec: &HitRecord) -> Color {
+        let p = self.bounds.relative(&rec.p);
+        let mut radiance = self.emission_scale * self.grid.emission(&p);
+        if self.temperature_scale > 0.0 {
+            radiance += self.temperature_scale * blackbody(self.grid.temperature(&p));
+        }
+        let albedo = match self.phase.as_ref() {
+            Material::Isotropic(i) => i.albedo.value(rec.u, rec.v, &rec.p),
+            Material::HenyeyGreenstein(h) => h.albedo.value(rec.u, rec.v, &rec.p),
+            _ => Color::new(1.0, 1.0, 1.0),
+        };
+        (Color::new(1.0, 1.0, 1.0) - albedo) * radiance
     }
 }
 
 #[derive(Debug, Clone)]
 pub enum Material {
     Lambertian(Lambertian),
-    Metal(Metal),
+    Conductor(Conductor),
     Dielectric(Dielectric),
+    DiffuseLight(DiffuseLight),
+    Isotropic(Isotropic),
+    HenyeyGreenstein(HenyeyGreenstein),
+    Volume(Volume),
 }
 
 impl Material {
     pub fn scatter<R: Rng>(&self, ray_in: &Ray, rec: &HitRecord, rng: &mut R) -> Option<(Color, Ray)> {
         match self {
             Material::Lambertian(l) => l.scatter(ray_in, rec, rng),
-            Material::Metal(m) => m.scatter(ray_in, rec, rng),
+            Material::Conductor(c) => c.scatter(ray_in, rec, rng),
             Material::Dielectric(d) => d.scatter(ray_in, rec, rng),
+            Material::DiffuseLight(_) => None,
+            Material::Isotropic(i) => i.scatter(ray_in, rec, rng),
+            Material::HenyeyGreenstein(h) => h.scatter(ray_in, rec, rng),
+            Material::Volume(v) => v.phase.scatter(ray_in, rec, rng),
+        }
+    }
+
+    /// Materials whose scattered direction can't be sampled any other way,
+    /// which are skipped when sampling lights directly.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Conductor(c) => c.is_smooth(),
+            Material::Dielectric(d) => d.is_smooth(),
+            _ => false,
+        }
+    }
+
+    /// Scattering function times the cosine towards `direction`, the
+    /// light arriving from there is weighted by. Zero for specular materials;
+    /// phase functions scatter within a volume, so they have no cosine.
+    pub fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        match self {
+            Material::Lambertian(l) => l.eval(rec, direction),
+            Material::Conductor(c) => c.eval(ray_in, rec, direction),
+            Material::Dielectric(d) => d.eval(ray_in, rec, direction),
+            Material::Isotropic(i) => i.eval(rec),
+            Material::HenyeyGreenstein(h) => h.eval(ray_in, rec, direction),
+            Material::Volume(v) => v.phase.eval(ray_in, rec, direction),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Solid angle density with which `scatter` picks `direction`.
+    pub fn pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        match self {
+            Material::Lambertian(l) => l.pdf(rec, direction),
+            Material::Conductor(c) => c.pdf(ray_in, rec, direction),
+            Material::Dielectric(d) => d.pdf(ray_in, rec, direction),
+            Material::Isotropic(_) => 1.0 / (4.0 * PI),
+            Material::HenyeyGreenstein(h) => h.pdf(ray_in, direction),
+            Material::Volume(v) => v.phase.pdf(ray_in, rec, direction),
+            _ => 0.0,
+        }
+    }
+
+    /// Radiance leaving the surface on its own, black for anything but lights.
+    pub fn emitted(&self) -> Color {
+        match self {
+            Material::DiffuseLight(l) => l.emitted(),
+            _ => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    /// Light added at a hit: a light's own radiance, or for glowing media
+    /// the emission at the collision point.
+    pub fn emission(&self, rec: &HitRecord) -> Color {
+        match self {
+            Material::Volume(v) => v.glow(rec),
+            _ => self.emitted(),
+        }
+    }
+
+    /// `albedo` is a `Texture` or a plain `Color`.
+    pub fn lambertian(albedo: impl Into<Texture>) -> Material {
+        Material::Lambertian(Lambertian::new(albedo.into()))
+    }
+
+    /// Metal of a given color, with the fuzz as its roughness. Textured
+    /// ones are a white metal tinted by the texture.
+    pub fn metal(albedo: impl Into<Texture>, fuzz: f64) -> Material {
+        match albedo.into() {
+            Texture::Solid(color) => Material::conductor_albedo(&color, fuzz),
+            albedo => {
+                let white = Conductor::from_albedo(&Color::new(1.0, 1.0, 1.0), fuzz);
+                Material::Conductor(white.with_tint(albedo))
+            }
         }
     }
 
-    pub fn lambertian(albedo: Color) -> Material {
-        return Material::Lambertian(Lambertian::new(albedo));
+    /// Metal reflecting `albedo` head on.
+    pub fn conductor_albedo(albedo: &Color, roughness: f64) -> Material {
+        Material::Conductor(Conductor::from_albedo(albedo, roughness))
     }
 
-    pub fn metal(albedo: Color, fuzz: f64) -> Material {
-        return Material::Metal(Metal::new(albedo, fuzz));
+    /// Metal with the complex index of refraction `eta + i k`, from a
+    /// mirror at roughness 0 to fully rough at 1.
+    pub fn conductor(eta: Color, k: Color, roughness: f64) -> Material {
+        Material::Conductor(Conductor::new(eta, k, roughness))
+    }
+
+    pub fn conductor_preset(preset: ConductorPreset, roughness: f64) -> Material {
+        let (eta, k) = preset.ior();
+        Material::conductor(eta, k, roughness)
     }
 
     pub fn dielectric(ref_idx: f64) -> Material {
-        return Material::Dielectric(Dielectric::new(ref_idx));
+        Material::Dielectric(Dielectric::new(ref_idx))
+    }
+
+    /// Frosted glass; roughness 0 is the same as `dielectric`.
+    pub fn rough_dielectric(ref_idx: f64, roughness: f64) -> Material {
+        Material::Dielectric(Dielectric::new(ref_idx).with_roughness(roughness))
     }
 
+    pub fn diffuse_light(color: Color, intensity: f64) -> Material {
+        Material::DiffuseLight(DiffuseLight::new(color, intensity))
+    }
 
+    /// Phase function for a medium that scatters evenly in all directions.
+    pub fn isotropic(albedo: impl Into<Texture>) -> Material {
+        Material::Isotropic(Isotropic::new(albedo.into()))
+    }
 
+    /// Phase function with asymmetry `g` in (-1, 1).
+    pub fn henyey_greenstein(albedo: impl Into<Texture>, g: f64) -> Material {
+        Material::HenyeyGreenstein(HenyeyGreenstein::new(albedo.into(), g))
+    }
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_henyey_greenstein() {
+        let mut rng = StdRng::seed_from_u64(11);
+        let forward = Vec3::new(0.0, 0.0, 1.0);
+        for g in [-0.7, 0.0, 0.3, 0.9] {
+            // Sampled directions have the mean cosine g ...
+            let n = 20000;
+            let mean = (0..n).map(|_| sample_henyey_greenstein(&forward, g, &mut rng).z()).sum::<f64>() / n as f64;
+            assert!((mean - g).abs() < 0.02, "g = {}: mean cosine {}", g, mean);
+
+            // ... and the density integrates to one over the sphere.
+            let steps = 10000;
+            let integral: f64 = (0..steps)
+                .map(|i| {
+                    let cos_theta = -1.0 + 2.0 * (i as f64 + 0.5) / steps as f64;
+                    2.0 * PI * henyey_greenstein(cos_theta, g) * 2.0 / steps as f64
+                })
+                .sum();
+            assert!((integral - 1.0).abs() < 1e-3, "g = {}: integral {}", g, integral);
+        }
+    }
+
+    #[test]
+    fn test_lambertian_sampling() {
+        // Cosine weighted directions have P(cos θ < c) = c² and a uniform
+        // azimuth; check both with a chi-squared test over ten bins each.
+        let material = Material::lambertian(Color::new(0.8, 0.6, 0.4));
+        let normal = Vec3::new(1.0, 2.0, -2.0).unit_vector();
+        let rec = HitRecord::new(Vec3::new(0.0, 0.0, 0.0), normal.clone(), 1.0, true, &material);
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let basis = Onb::from_w(&normal);
+        let mut rng = StdRng::seed_from_u64(17);
+        let (n, bins) = (50000, 10);
+        let (mut elevation, mut azimuth) = (vec![0.0; bins], vec![0.0; bins]);
+        for _ in 0..n {
+            let (attenuation, scattered) = material.scatter(&ray, &rec, &mut rng).unwrap();
+            assert_eq!(attenuation, Color::new(0.8, 0.6, 0.4));
+            let d = scattered.direction.unit_vector();
+            let cos_theta = d.dot(&normal);
+            assert!(cos_theta >= 0.0);
+            let expected = material.eval(&ray, &rec, &d) / material.pdf(&ray, &rec, &d);
+            assert!(cos_theta < 1e-6 || (&attenuation - &expected).length() < 1e-9);
+
+            elevation[((cos_theta * cos_theta * bins as f64) as usize).min(bins - 1)] += 1.0;
+            let phi = d.dot(&basis.v).atan2(d.dot(&basis.u)) + PI;
+            azimuth[((phi / (2.0 * PI) * bins as f64) as usize).min(bins - 1)] += 1.0;
+        }
+        // The 0.1% critical value of chi-squared with nine degrees of freedom.
+        let expected = n as f64 / bins as f64;
+        for counts in [elevation, azimuth] {
+            let chi2: f64 = counts.iter().map(|c| (c - expected).powi(2) / expected).sum();
+            assert!(chi2 < 27.88, "{:?}: chi-squared {}", counts, chi2);
+        }
+    }
+
+    /// Mean attenuation of light scattered off a flat surface from a ray
+    /// arriving at `cos_theta` to the normal.
+    fn reflected(material: &Material, cos_theta: f64, rng: &mut StdRng) -> Color {
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+        let ray = Ray::new(Vec3::new(-sin_theta, cos_theta, 0.0), Vec3::new(sin_theta, -cos_theta, 0.0));
+        let rec = HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, true, material);
+        let n = 4000;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            if let Some((attenuation, scattered)) = material.scatter(&ray, &rec, rng) {
+                assert!(scattered.direction.y() > 0.0);
+                // Sampling and evaluating agree, so light sampling and
+                // scattering can be weighed against each other.
+                let expected = material.eval(&ray, &rec, &scattered.direction)
+                    / material.pdf(&ray, &rec, &scattered.direction);
+                assert!((&attenuation - &expected).length() < 1e-9);
+                sum += attenuation;
+            }
+        }
+        sum / n as f64
+    }
+
+    #[test]
+    fn test_conductor_keeps_energy() {
+        // With an enormous extinction coefficient everything reflects, so
+        // no roughness may lose light between the microfacets.
+        let mut rng = StdRng::seed_from_u64(5);
+        let mirror = |roughness| Material::conductor(Color::new(1.0, 1.0, 1.0), Color::new(1e4, 1e4, 1e4), roughness);
+        for roughness in [0.2, 0.6, 1.0] {
+            for cos_theta in [0.2, 0.7, 1.0] {
+                let albedo = reflected(&mirror(roughness), cos_theta, &mut rng).g();
+                assert!((albedo - 1.0).abs() < 0.03, "roughness {} at {}: {}", roughness, cos_theta, albedo);
+            }
+        }
+
+        // Gold stays gold, and its smooth reflection is specular.
+        let gold = Material::conductor_preset(ConductorPreset::Gold, 0.5);
+        let color = reflected(&gold, 0.8, &mut rng);
+        assert!(color.r() > color.b());
+        assert!(Material::conductor_preset(ConductorPreset::Gold, 0.0).is_specular());
+        assert!(!gold.is_specular());
+    }
+
+    #[test]
+    fn test_metal_albedo() {
+        // Plain colored metals are conductors reflecting their albedo head
+        // on; textured ones reflect the texture at the hit.
+        let albedo = Color::new(0.9, 0.6, 0.0);
+        let metal = Material::metal(albedo.clone(), 0.0);
+        let Material::Conductor(c) = &metal else { panic!("expected a conductor") };
+        let head_on = microfacet::fresnel_conductor(1.0, &c.eta, &c.k);
+        assert!((&head_on - &albedo).length() < 1e-9, "{:?}", head_on);
+        assert!(metal.is_specular());
+        let checker = Texture::checker(1.0, Texture::Solid(albedo.clone()), Texture::Solid(Color::new(0.5, 0.5, 0.5)));
+        let metal = Material::metal(checker.clone(), 0.0);
+        let Material::Conductor(c) = &metal else { panic!("expected a conductor") };
+        assert!(c.tint.is_some());
+        let rec = HitRecord::new(Vec3::new(0.5, 0.5, 0.5), Vec3::new(0.0, 1.0, 0.0), 1.0, true, &metal);
+        let ray = Ray::new(Vec3::new(0.5, 1.5, 0.5), Vec3::new(0.0, -1.0, 0.0));
+        let (attenuation, _) = metal.scatter(&ray, &rec, &mut StdRng::seed_from_u64(1)).unwrap();
+        // White fits to a head-on reflectance of 0.99.
+        let expected = 0.99 * checker.value(rec.u, rec.v, &rec.p);
+        assert!((&attenuation - &expected).length() < 1e-9, "{:?}", attenuation);
+    }
+
+    #[test]
+    fn test_dielectric_fresnel() {
+        // Share of rays smooth glass reflects at `cos_theta` from the normal.
+        let reflected = |fresnel: Fresnel, cos_theta: f64| {
+            let mut rng = StdRng::seed_from_u64(3);
+            let glass = Material::Dielectric(Dielectric::new(1.5).with_fresnel(fresnel));
+            let rec = HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, true, &glass);
+            let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+            let ray = Ray::new(Vec3::new(-sin_theta, cos_theta, 0.0), Vec3::new(sin_theta, -cos_theta, 0.0));
+            let n = 10000;
+            let count = (0..n).filter(|_| glass.scatter(&ray, &rec, &mut rng).unwrap().1.direction.y() > 0.0).count();
+            count as f64 / n as f64
+        };
+        for fresnel in [Fresnel::Schlick, Fresnel::Exact] {
+            // About 4% head on, most of it at grazing angles: the bright rims
+            // of glass spheres.
+            assert!((reflected(fresnel, 1.0) - 0.04).abs() < 0.01);
+            assert!(reflected(fresnel, 0.02) > 0.8);
+        }
+        // In between each follows its own formula.
+        for cos_theta in [0.9, 0.7, 0.5, 0.3, 0.1] {
+            let exact = microfacet::fresnel_dielectric(cos_theta, 1.5);
+            let schlick = util::reflectance(cos_theta, 1.5);
+            assert!((reflected(Fresnel::Exact, cos_theta) - exact).abs() < 0.015, "{}", cos_theta);
+            assert!((reflected(Fresnel::Schlick, cos_theta) - schlick).abs() < 0.015, "{}", cos_theta);
+        }
+    }
+
+    #[test]
+    fn test_rough_dielectric() {
+        let mut rng = StdRng::seed_from_u64(8);
+        let down = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        let hit = |material| HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, true, material);
+
+        // Roughness 0 is the smooth dielectric: head on, rays reflect
+        // straight back or pass straight through.
+        let smooth = Material::rough_dielectric(1.5, 0.0);
+        assert!(smooth.is_specular());
+        for _ in 0..100 {
+            let (_, ray) = smooth.scatter(&down, &hit(&smooth), &mut rng).unwrap();
+            assert_eq!(ray.direction.y().abs(), 1.0);
+        }
+
+        // Slightly frosted glass reflects about 4% head on, and what goes
+        // through stays close to straight.
+        let mut rng = StdRng::seed_from_u64(8);
+        let frosted = Material::rough_dielectric(1.5, 0.1);
+        let rec = hit(&frosted);
+        let n = 4000;
+        let mut reflected = 0;
+        for _ in 0..n {
+            let (attenuation, ray) = frosted.scatter(&down, &rec, &mut rng).unwrap();
+            let expected = frosted.eval(&down, &rec, &ray.direction) / frosted.pdf(&down, &rec, &ray.direction);
+            assert!((&attenuation - &expected).length() < 1e-9);
+            // Masking is all a single bounce loses.
+            assert!(attenuation.g() <= 1.0 + 1e-9);
+            if ray.direction.y() > 0.0 {
+                reflected += 1;
+            } else {
+                assert!(ray.direction.unit_vector().y() < -0.95);
+            }
+        }
+        let share = reflected as f64 / n as f64;
+        assert!((share - 0.04).abs() < 0.015, "{}", share);
+
+        // From inside, past the critical angle, nothing gets out.
+        let rough = Material::rough_dielectric(1.5, 0.05);
+        let inside = HitRecord::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, false, &rough);
+        let steep = Ray::new(Vec3::new(-0.9, 0.3, 0.0), Vec3::new(0.9, -0.3, 0.0));
+        for _ in 0..100 {
+            if let Some((_, ray)) = rough.scatter(&steep, &inside, &mut rng) {
+                assert!(ray.direction.y() > 0.0);
+            }
+        }
+    }
+}
@@ -0,0 +1,171 @@
+use crate::vec3::Vec3;
+use crate::util::degrees_to_radians;
+
+use std::ops::Mul;
+
+/// Row-major 4x4 affine transform acting on column vectors, so `a * b`
+/// applies `b` first.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Matrix4 {
+    pub data: [[f64; 4]; 4],
+}
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        Self { data: std::array::from_fn(|i| std::array::from_fn(|j| if i == j { 1.0 } else { 0.0 })) }
+    }
+
+    pub fn translation(offset: &Vec3) -> Self {
+        let mut m = Self::identity();
+        for (i, o) in offset.iter().enumerate() {
+            m.data[i][3] = *o;
+        }
+        m
+    }
+
+    pub fn scaling(factors: &Vec3) -> Self {
+        let mut m = Self::identity();
+        for (i, f) in factors.iter().enumerate() {
+            m.data[i][i] = *f;
+        }
+        m
+    }
+
+    /// Counter-clockwise rotation by `degrees` around `axis`, looking down
+    /// the axis towards the origin.
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Self {
+        let a = axis.unit_vector();
+        let (x, y, z) = (a.x(), a.y(), a.z());
+        let theta = degrees_to_radians(degrees);
+        let (s, c) = theta.sin_cos();
+        let t = 1.0 - c;
+        Self {
+            data: [
+                [t * x * x + c, t * x * y - s * z, t * x * z + s * y, 0.0],
+                [t * x * y + s * z, t * y * y + c, t * y * z - s * x, 0.0],
+                [t * x * z - s * y, t * y * z + s * x, t * z * z + c, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+            ],
+        }
+    }
+
+    /// Gauss-Jordan elimination with partial pivoting; `None` when the
+    /// matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.data;
+        let mut inv = Self::identity().data;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inv.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for j in 0..4 {
+                a[col][j] *= scale;
+                inv[col][j] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for j in 0..4 {
+                        a[row][j] -= factor * a[col][j];
+                        inv[row][j] -= factor * inv[col][j];
+                    }
+                }
+            }
+        }
+        Some(Self { data: inv })
+    }
+
+    pub fn transform_point(&self, p: &Vec3) -> Vec3 {
+        let m = &self.data;
+        let row = |i: usize| m[i][0] * p.x() + m[i][1] * p.y() + m[i][2] * p.z() + m[i][3];
+        let w = row(3);
+        let point = Vec3::new(row(0), row(1), row(2));
+        if w == 1.0 {
+            point
+        } else {
+            point / w
+        }
+    }
+
+    /// Ignores the translation.
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let m = &self.data;
+        let row = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+        Vec3::new(row(0), row(1), row(2))
+    }
+
+    /// Normals transform with the inverse transpose, so call this on the
+    /// inverse of the matrix the surface went through. Not normalized.
+    pub fn transform_normal(&self, n: &Vec3) -> Vec3 {
+        let m = &self.data;
+        let column = |j: usize| m[0][j] * n.x() + m[1][j] * n.y() + m[2][j] * n.z();
+        Vec3::new(column(0), column(1), column(2))
+    }
+}
+
+impl Mul<&Matrix4> for &Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: &Matrix4) -> Matrix4 {
+        Matrix4 {
+            data: std::array::from_fn(|i| {
+                std::array::from_fn(|j| (0..4).map(|k| self.data[i][k] * other.data[k][j]).sum())
+            }),
+        }
+    }
+}
+
+impl Mul<Matrix4> for Matrix4 {
+    type Output = Matrix4;
+
+    fn mul(self, other: Matrix4) -> Matrix4 {
+        &self * &other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Matrix4, b: &Matrix4) {
+        for (ra, rb) in a.data.iter().zip(&b.data) {
+            for (x, y) in ra.iter().zip(rb) {
+                assert!((x - y).abs() < 1e-12, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn test_inverse() {
+        let m = Matrix4::translation(&Vec3::new(1.0, -2.0, 3.0))
+            * Matrix4::rotation(&Vec3::new(1.0, 1.0, 0.0), 30.0)
+            * Matrix4::scaling(&Vec3::new(2.0, 0.5, 3.0));
+        let inverse = m.inverse().unwrap();
+        assert_close(&(&m * &inverse), &Matrix4::identity());
+        assert_close(&(&inverse * &m), &Matrix4::identity());
+        assert!(Matrix4::scaling(&Vec3::new(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn test_transforms() {
+        let rotate = Matrix4::rotation(&Vec3::new(0.0, 0.0, 1.0), 90.0);
+        let p = rotate.transform_point(&Vec3::new(1.0, 0.0, 0.0));
+        assert!((p - Vec3::new(0.0, 1.0, 0.0)).near_zero());
+
+        let m = Matrix4::translation(&Vec3::new(1.0, 2.0, 3.0)) * Matrix4::scaling(&Vec3::new(2.0, 2.0, 2.0));
+        assert_eq!(m.transform_point(&Vec3::new(1.0, 1.0, 1.0)), Vec3::new(3.0, 4.0, 5.0));
+        assert_eq!(m.transform_vector(&Vec3::new(1.0, 1.0, 1.0)), Vec3::new(2.0, 2.0, 2.0));
+
+        // Squashing a 45 degree plane along y tilts its normal towards y.
+        let squash = Matrix4::scaling(&Vec3::new(1.0, 0.5, 1.0));
+        let n = squash.inverse().unwrap().transform_normal(&Vec3::new(1.0, 1.0, 0.0));
+        let tangent = squash.transform_vector(&Vec3::new(1.0, -1.0, 0.0));
+        assert_eq!(n.dot(&tangent), 0.0);
+        assert_eq!(n, Vec3::new(1.0, 2.0, 0.0));
+    }
+}
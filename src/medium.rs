@@ -0,0 +1,290 @@
+use crate::hitrecord::HitRecord;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::{Material, Volume};
+use crate::aabb::Aabb;
+use crate::figure::Figure;
+use crate::csg::inside_spans;
+use crate::grid::VoxelGrid;
+use crate::cuboid::Cuboid;
+
+use rand::Rng;
+use std::sync::Arc;
+
+/// Fog or smoke of uniform density filling a closed boundary figure.
+/// Rays passing through scatter after an exponentially distributed free
+/// flight, off the phase function in `phase`, or pass on untouched.
+/// The boundary itself is invisible.
+pub struct ConstantMedium {
+    pub boundary: Box<Figure>,
+    /// Extinction coefficient, the chance of scattering per unit length.
+    pub density: f64,
+    pub phase: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Figure, density: f64, phase: Material) -> Self {
+        assert!(density > 0.0, "medium density must be positive");
+        Self { boundary: Box::new(boundary), density, phase }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.hit_with(ray, t_min, t_max, &mut rand::thread_rng())
+    }
+
+    fn hit_with<R: Rng>(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut R) -> Option<HitRecord<'_>> {
+        let speed = ray.direction.length();
+        // Distance to the scattering event, walked through the stretches
+        // inside the boundary; the distribution is memoryless, so one
+        // sample serves all of them.
+        let mut flight = -(1.0 - rng.gen::<f64>()).ln() / self.density;
+        for (enter, exit) in inside_spans(&self.boundary, ray, t_min, t_max) {
+            let length = (exit - enter) * speed;
+            if flight < length {
+                let t = enter + flight / speed;
+                // There is no surface, so any normal will do.
+                let rec = HitRecord::new(ray.at(t), Vec3::new(1.0, 0.0, 0.0), t, true, &self.phase);
+                return Some(rec);
+            }
+            flight -= length;
+        }
+        None
+    }
+
+    /// Exact fraction of light crossing the medium along the ray.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let inside: f64 = inside_spans(&self.boundary, ray, t_min, t_max).iter().map(|(a, b)| b - a).sum();
+        (-self.density * inside * ray.direction.length()).exp()
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.boundary.bounding_box()
+    }
+}
+
+/// Medium whose density comes from a voxel grid stretched over an
+/// axis-aligned box figure, such as simulated smoke or fire. Collisions are
+/// found by delta tracking against the grid's largest density, which is
+/// unbiased however the density varies.
+pub struct GridMedium {
+    pub boundary: Cuboid,
+    // The box's extent, which the grid's unit cube maps onto.
+    bounds: Aabb,
+    pub grid: Arc<VoxelGrid>,
+    /// Extinction coefficient for a grid value of one.
+    pub density_scale: f64,
+    pub material: Material,
+}
+
+impl GridMedium {
+    /// `phase` is the phase function scattering light off the medium.
+    pub fn new(min: Vec3, max: Vec3, grid: Arc<VoxelGrid>, density_scale: f64, phase: Material) -> Self {
+        let boundary = Cuboid::new(min, max, phase.clone());
+        let bounds = boundary.bounding_box();
+        let volume = Volume {
+            phase: Box::new(phase),
+            grid: grid.clone(),
+            bounds: bounds.clone(),
+            emission_scale: 0.0,
+            temperature_scale: 0.0,
+        };
+        Self { boundary, bounds, grid, density_scale, material: Material::Volume(volume) }
+    }
+
+    fn volume(&mut self) -> &mut Volume {
+        match &mut self.material {
+            Material::Volume(volume) => volume,
+            _ => unreachable!("grid media always scatter with a volume"),
+        }
+    }
+
+    /// Glows with the grid's emission channel times `scale`.
+    pub fn with_emission(mut self, scale: f64) -> Self {
+        self.volume().emission_scale = scale;
+        self
+    }
+
+    /// Glows like a black body at the grid's temperature, times `scale`.
+    pub fn with_temperature(mut self, scale: f64) -> Self {
+        self.volume().temperature_scale = scale;
+        self
+    }
+
+    fn extinction(&self, p: &Vec3) -> f64 {
+        self.density_scale * self.grid.density(&self.bounds.relative(p))
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.hit_with(ray, t_min, t_max, &mut rand::thread_rng())
+    }
+
+    /// Steps through tentative collisions as if the whole box had the
+    /// largest density; each is real with the chance the actual density
+    /// is of that.
+    fn hit_with<R: Rng>(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut R) -> Option<HitRecord<'_>> {
+        let majorant = self.density_scale * self.grid.max_density();
+        if majorant <= 0.0 {
+            return None;
+        }
+        let speed = ray.direction.length();
+        let (enter, exit) = self.boundary.span(ray, t_min, t_max)?;
+        let mut t = enter;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * speed);
+            if t >= exit {
+                return None;
+            }
+            let p = ray.at(t);
+            if rng.gen::<f64>() * majorant < self.extinction(&p) {
+                return Some(HitRecord::new(p, Vec3::new(1.0, 0.0, 0.0), t, true, &self.material));
+            }
+        }
+    }
+
+    /// Ratio tracking: the same tentative collisions, each scaling the
+    /// estimate by the chance of being a null one. Unbiased, and unlike
+    /// a delta tracked shadow ray it rarely drops to zero.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        self.transmittance_with(ray, t_min, t_max, &mut rand::thread_rng())
+    }
+
+    fn transmittance_with<R: Rng>(&self, ray: &Ray, t_min: f64, t_max: f64, rng: &mut R) -> f64 {
+        let majorant = self.density_scale * self.grid.max_density();
+        let speed = ray.direction.length();
+        let (enter, exit) = match self.boundary.span(ray, t_min, t_max) {
+            Some(span) if majorant > 0.0 => span,
+            _ => return 1.0,
+        };
+        let mut transmittance = 1.0;
+        let mut t = enter;
+        loop {
+            t -= (1.0 - rng.gen::<f64>()).ln() / (majorant * speed);
+            if t >= exit {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.extinction(&ray.at(t)) / majorant;
+        }
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        self.bounds.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn fog(density: f64) -> ConstantMedium {
+        let glass = Material::dielectric(1.5);
+        let boundary = Figure::cuboid(Vec3::new(0.0, -1.0, -1.0), Vec3::new(2.0, 1.0, 1.0), glass);
+        ConstantMedium::new(boundary, density, Material::isotropic(Color::new(1.0, 1.0, 1.0)))
+    }
+
+    #[test]
+    fn test_transmittance() {
+        // A fraction exp(-density * length) of the rays crosses unscattered.
+        let medium = fog(0.5);
+        let mut rng = StdRng::seed_from_u64(7);
+        let ray = Ray::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0));
+        let n = 20000;
+        let mut passed = 0;
+        for _ in 0..n {
+            match medium.hit_with(&ray, 0.001, f64::INFINITY, &mut rng) {
+                Some(rec) => {
+                    assert!((0.0..=2.0).contains(&rec.p.x()));
+                    assert!(matches!(rec.material, Material::Isotropic(_)));
+                }
+                None => passed += 1,
+            }
+        }
+        let expected = (-0.5f64 * 2.0).exp();
+        assert!((passed as f64 / n as f64 - expected).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_exact_transmittance() {
+        let medium = fog(0.5);
+        let ray = Ray::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((medium.transmittance(&ray, 0.001, f64::INFINITY) - (-1.0f64).exp()).abs() < 1e-12);
+        assert_eq!(medium.transmittance(&ray, 0.001, 0.5), 1.0);
+    }
+
+    #[test]
+    fn test_inside_origin_and_range() {
+        // Starting inside, only the rest of the box counts.
+        let medium = fog(1.0);
+        let mut rng = StdRng::seed_from_u64(3);
+        let ray = Ray::new(Vec3::new(1.5, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let n = 20000;
+        let passed = (0..n).filter(|_| medium.hit_with(&ray, 0.001, f64::INFINITY, &mut rng).is_none()).count();
+        assert!((passed as f64 / n as f64 - (-0.5f64).exp()).abs() < 0.01);
+
+        // Shadow rays that stop short of the boundary only see their part.
+        let short = Ray::new(Vec3::new(-1.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let passed = (0..n).filter(|_| medium.hit_with(&short, 0.001, 1.5, &mut rng).is_none()).count();
+        assert!((passed as f64 / n as f64 - (-0.5f64).exp()).abs() < 0.01);
+
+        // Starting inside with a range ending short of the boundary, the
+        // whole range is in the fog.
+        let passed = (0..n).filter(|_| medium.hit_with(&ray, 0.001, 0.4, &mut rng).is_none()).count();
+        assert!((passed as f64 / n as f64 - (-0.399f64).exp()).abs() < 0.01);
+        assert!((medium.transmittance(&ray, 0.001, 0.4) - (-0.399f64).exp()).abs() < 1e-12);
+    }
+
+    /// Density rising linearly along x from 0 to 2, over the box [0, 2]³.
+    fn ramp() -> GridMedium {
+        let grid = VoxelGrid::new([2, 1, 1], vec![0.0, 2.0], None, Some(vec![[0.0; 3], [1.0, 0.5, 0.0]])).unwrap();
+        let phase = Material::isotropic(Color::new(0.5, 0.5, 0.5));
+        GridMedium::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 2.0, 2.0), Arc::new(grid), 1.0, phase)
+    }
+
+    #[test]
+    fn test_delta_and_ratio_tracking() {
+        // Through the middle the density is 0 up to x = 0.5, ramps to 2 at
+        // x = 1.5 and stays there, so its integral over [0, 2] is 2.
+        let medium = ramp();
+        let expected = (-2.0f64).exp();
+        let ray = Ray::new(Vec3::new(-1.0, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        let mut rng = StdRng::seed_from_u64(5);
+        let n = 20000;
+
+        let passed = (0..n).filter(|_| medium.hit_with(&ray, 0.001, f64::INFINITY, &mut rng).is_none()).count();
+        assert!((passed as f64 / n as f64 - expected).abs() < 0.01);
+
+        let ratio = (0..n).map(|_| medium.transmittance_with(&ray, 0.001, f64::INFINITY, &mut rng)).sum::<f64>() / n as f64;
+        assert!((ratio - expected).abs() < 0.01);
+
+        // Nothing scatters where the density is zero.
+        for _ in 0..1000 {
+            if let Some(rec) = medium.hit_with(&ray, 0.001, f64::INFINITY, &mut rng) {
+                assert!(rec.p.x() > 0.5);
+            }
+        }
+
+        // Starting inside, the thinner half is behind the origin.
+        let inside = Ray::new(Vec3::new(1.5, 1.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        let ratio = (0..n).map(|_| medium.transmittance_with(&inside, 0.001, f64::INFINITY, &mut rng)).sum::<f64>() / n as f64;
+        assert!((ratio - (-1.0f64).exp()).abs() < 0.01);
+
+        // A short range from inside only crosses its own stretch, where the
+        // density is 2 throughout.
+        let ratio = (0..n).map(|_| medium.transmittance_with(&inside, 0.001, 0.25, &mut rng)).sum::<f64>() / n as f64;
+        assert!((ratio - (-2.0f64 * 0.249).exp()).abs() < 0.01);
+        let passed = (0..n).filter(|_| medium.hit_with(&inside, 0.001, 0.25, &mut rng).is_none()).count();
+        assert!((passed as f64 / n as f64 - (-2.0f64 * 0.249).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_emission() {
+        let medium = ramp().with_emission(4.0);
+        let ray = Ray::new(Vec3::new(1.75, 1.0, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let mut rng = StdRng::seed_from_u64(9);
+        let rec = medium.hit_with(&ray, 0.001, f64::INFINITY, &mut rng).unwrap();
+        // Half the light is absorbed, so half the emission shows.
+        assert_eq!(rec.material.emission(&rec), Color::new(2.0, 1.0, 0.0));
+    }
+}
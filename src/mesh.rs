@@ -0,0 +1,264 @@
+use crate::hitrecord::{HitRecord, FaceNormal};
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::bvh::Bvh;
+
+/// Watertight ray/triangle intersection (Woop, Benthin and Wald 2013).
+/// Rays through a shared edge or vertex always hit at least one of the
+/// triangles that share it. Returns `t` and the barycentric weights of
+/// `p0`, `p1` and `p2`.
+pub fn intersect_triangle(ray: &Ray, p0: &Vec3, p1: &Vec3, p2: &Vec3, t_min: f64, t_max: f64) -> Option<(f64, [f64; 3])> {
+    let dir = &ray.direction.data;
+
+    // Permute axes so the dominant direction component becomes z.
+    let kz = (0..3)
+        .max_by(|&a, &b| dir[a].abs().total_cmp(&dir[b].abs()))
+        .unwrap();
+    let mut kx = (kz + 1) % 3;
+    let mut ky = (kx + 1) % 3;
+    if dir[kz] < 0.0 {
+        std::mem::swap(&mut kx, &mut ky);
+    }
+
+    // Shear so the ray points down +z from the origin.
+    let sx = dir[kx] / dir[kz];
+    let sy = dir[ky] / dir[kz];
+    let sz = 1.0 / dir[kz];
+
+    let a = p0 - &ray.origin;
+    let b = p1 - &ray.origin;
+    let c = p2 - &ray.origin;
+
+    let ax = a.data[kx] - sx * a.data[kz];
+    let ay = a.data[ky] - sy * a.data[kz];
+    let bx = b.data[kx] - sx * b.data[kz];
+    let by = b.data[ky] - sy * b.data[kz];
+    let cx = c.data[kx] - sx * c.data[kz];
+    let cy = c.data[ky] - sy * c.data[kz];
+
+    let u = cx * by - cy * bx;
+    let v = ax * cy - ay * cx;
+    let w = bx * ay - by * ax;
+
+    if (u < 0.0 || v < 0.0 || w < 0.0) && (u > 0.0 || v > 0.0 || w > 0.0) {
+        return None;
+    }
+
+    let det = u + v + w;
+    if det == 0.0 {
+        return None;
+    }
+
+    let az = sz * a.data[kz];
+    let bz = sz * b.data[kz];
+    let cz = sz * c.data[kz];
+    let t = (u * az + v * bz + w * cz) / det;
+    if t < t_min || t_max < t {
+        return None;
+    }
+
+    Some((t, [u / det, v / det, w / det]))
+}
+
+fn triangle_bounding_box(p0: &Vec3, p1: &Vec3, p2: &Vec3) -> Aabb {
+    Aabb::new(p0.clone(), p0.clone()).include(p1).include(p2)
+}
+
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    pub v0: Vec3,
+    pub v1: Vec3,
+    pub v2: Vec3,
+    pub material: Material,
+}
+
+impl Triangle {
+    /// Vertices in counter-clockwise order when seen from the front.
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, material: Material) -> Self {
+        Self { v0, v1, v2, material }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, b) = intersect_triangle(ray, &self.v0, &self.v1, &self.v2, t_min, t_max)?;
+        let point = &(b[0] * &self.v0) + &(b[1] * &self.v1) + (b[2] * &self.v2);
+        let outward_normal = (&self.v1 - &self.v0).cross(&(&self.v2 - &self.v0)).unit_vector();
+        let rec = match HitRecord::get_face_normal(ray, &outward_normal) {
+            FaceNormal::Front(normal) => HitRecord::new(point, normal, t, true, &self.material),
+            FaceNormal::Back(normal) => HitRecord::new(point, normal, t, false, &self.material),
+        };
+        Some(rec.with_uv(b[1], b[2]))
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        triangle_bounding_box(&self.v0, &self.v1, &self.v2)
+    }
+}
+
+/// Indexed triangle mesh. Every attribute array is indexed by the same
+/// vertex index, and the whole mesh shares one material.
+pub struct Mesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Option<Vec<Vec3>>,
+    pub uvs: Option<Vec<[f64; 2]>>,
+    pub indices: Vec<[usize; 3]>,
+    pub material: Material,
+    bvh: Bvh,
+}
+
+impl Mesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        indices: Vec<[usize; 3]>,
+        normals: Option<Vec<Vec3>>,
+        uvs: Option<Vec<[f64; 2]>>,
+        material: Material,
+    ) -> Self {
+        assert!(
+            indices.iter().flatten().all(|&i| i < positions.len()),
+            "mesh index out of bounds"
+        );
+        assert!(
+            normals.as_ref().is_none_or(|n| n.len() == positions.len()),
+            "mesh needs one normal per vertex"
+        );
+        assert!(
+            uvs.as_ref().is_none_or(|uv| uv.len() == positions.len()),
+            "mesh needs one uv per vertex"
+        );
+
+        let boxes = indices
+            .iter()
+            .enumerate()
+            .map(|(i, [a, b, c])| (i, triangle_bounding_box(&positions[*a], &positions[*b], &positions[*c])))
+            .collect();
+        let bvh = Bvh::new(boxes);
+
+        Self { positions, normals, uvs, indices, material, bvh }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        self.bvh
+            .hit(ray, t_min, t_max, |i, t_max| self.hit_triangle(i, ray, t_min, t_max))
+            .map(|(_, rec)| rec)
+    }
+
+    /// Index of the triangle the ray hits first.
+    pub fn hit_index(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<usize> {
+        self.bvh
+            .hit(ray, t_min, t_max, |i, t_max| self.hit_triangle(i, ray, t_min, t_max))
+            .map(|(i, _)| i)
+    }
+
+    /// `None` for a mesh without triangles.
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.bvh.bounding_box().cloned()
+    }
+
+    fn hit_triangle(&self, index: usize, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let [i0, i1, i2] = self.indices[index];
+        let (p0, p1, p2) = (&self.positions[i0], &self.positions[i1], &self.positions[i2]);
+        let (t, b) = intersect_triangle(ray, p0, p1, p2, t_min, t_max)?;
+
+        let point = &(b[0] * p0) + &(b[1] * p1) + (b[2] * p2);
+        let geometric = (p1 - p0).cross(&(p2 - p0)).unit_vector();
+
+        // Shading normals are kept on the geometric side of the surface so
+        // front_face stays consistent with what the ray actually crossed.
+        let outward_normal = match &self.normals {
+            Some(n) => {
+                let shading = &(b[0] * &n[i0]) + &(b[1] * &n[i1]) + (b[2] * &n[i2]);
+                if shading.near_zero() {
+                    geometric.clone()
+                } else if shading.dot(&geometric) < 0.0 {
+                    -shading.unit_vector()
+                } else {
+                    shading.unit_vector()
+                }
+            }
+            None => geometric.clone(),
+        };
+
+        let (u, v) = match &self.uvs {
+            Some(uv) => (
+                b[0] * uv[i0][0] + b[1] * uv[i1][0] + b[2] * uv[i2][0],
+                b[0] * uv[i0][1] + b[1] * uv[i1][1] + b[2] * uv[i2][1],
+            ),
+            None => (b[1], b[2]),
+        };
+
+        let front_face = ray.direction.dot(&geometric) < 0.0;
+        let normal = if front_face { outward_normal } else { -outward_normal };
+        Some(HitRecord::new(point, normal, t, front_face, &self.material).with_uv(u, v))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn quad_mesh(normals: Option<Vec<Vec3>>) -> Mesh {
+        let positions = vec![
+            Vec3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(1.0, 1.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+        ];
+        let indices = vec![[0, 1, 2], [0, 2, 3]];
+        Mesh::new(positions, indices, normals, None, Material::lambertian(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    #[test]
+    fn test_shared_edge_is_watertight() {
+        let mesh = quad_mesh(None);
+        let mut rng = StdRng::seed_from_u64(3);
+        for _ in 0..1000 {
+            // Points on the diagonal shared by both triangles, seen from random origins.
+            let s = rng.gen_range(0.0..=1.0);
+            let target = Vec3::new(s, s, 0.0);
+            let origin = Vec3::new(rng.gen_range(-2.0..2.0), rng.gen_range(-2.0..2.0), 3.0);
+            let ray = Ray::new(origin.clone(), target - &origin);
+            assert!(mesh.hit(&ray, 0.0, f64::INFINITY).is_some());
+        }
+    }
+
+    #[test]
+    fn test_interpolated_normal() {
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let tilted = Vec3::new(1.0, 0.0, 1.0).unit_vector();
+        let mesh = quad_mesh(Some(vec![n.clone(), tilted.clone(), tilted, n]));
+
+        let ray = Ray::new(Vec3::new(0.75, 0.25, 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let rec = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!(rec.front_face);
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(rec.normal.x() > 0.0 && rec.normal.z() > 0.0);
+        assert!((rec.normal.length() - 1.0).abs() < 1e-12);
+
+        // From behind, the shading normal flips with the face.
+        let ray = Ray::new(Vec3::new(0.75, 0.25, -1.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = mesh.hit(&ray, 0.0, f64::INFINITY).unwrap();
+        assert!(!rec.front_face);
+        assert!(rec.normal.z() < 0.0);
+    }
+
+    #[test]
+    fn test_triangle_miss_and_range() {
+        let tri = Triangle::new(
+            Vec3::new(0.0, 0.0, -1.0),
+            Vec3::new(1.0, 0.0, -1.0),
+            Vec3::new(0.0, 1.0, -1.0),
+            Material::lambertian(Color::new(0.5, 0.5, 0.5)),
+        );
+        let origin = Vec3::new(0.0, 0.0, 0.0);
+        let inside = Ray::new(origin.clone(), Vec3::new(0.2, 0.2, -1.0));
+        let outside = Ray::new(origin.clone(), Vec3::new(0.8, 0.8, -1.0));
+        assert!(tri.hit(&inside, 0.0, f64::INFINITY).is_some());
+        assert!(tri.hit(&inside, 0.0, 0.5).is_none());
+        assert!(tri.hit(&outside, 0.0, f64::INFINITY).is_none());
+    }
+}
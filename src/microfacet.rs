@@ -0,0 +1,280 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+
+use std::f64::consts::PI;
+use std::sync::OnceLock;
+
+/*
+ * GGX (Trowbridge-Reitz) microfacets. Directions are in the shading frame,
+ * with the macro surface normal along +z, and point away from the surface.
+ */
+
+/// Width of the distribution for a perceptual roughness in [0, 1].
+pub fn alpha(roughness: f64) -> f64 {
+    (roughness * roughness).max(1e-4)
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    pub alpha: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha: f64) -> Self {
+        Self { alpha }
+    }
+
+    /// Density of microfacet normals `h` per unit projected area.
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let a2 = self.alpha * self.alpha;
+        let t = h.z() * h.z() * (a2 - 1.0) + 1.0;
+        a2 / (PI * t * t)
+    }
+
+    /// Smith's auxiliary function, the shadowed share of microfacet area
+    /// seen from `w`.
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 >= 1.0 {
+            return 0.0;
+        }
+        let tan2 = (1.0 - cos2) / cos2;
+        0.5 * (-1.0 + (1.0 + self.alpha * self.alpha * tan2).sqrt())
+    }
+
+    /// Fraction of microfacets visible from `w`.
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction visible from both directions, height-correlated.
+    pub fn g2(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Samples a microfacet normal in proportion to how much of it `wo`
+    /// sees (Heitz 2018), from two uniform numbers.
+    pub fn sample_visible(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let a = self.alpha;
+        // Stretch to the hemisphere configuration.
+        let vh = Vec3::new(a * wo.x(), a * wo.y(), wo.z()).unit_vector();
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+
+        // A point on the disk, squeezed towards the visible half.
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+        let up = (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+        let nh = (p1 * &t1) + (p2 * &t2) + (up * &vh);
+
+        // Unstretch.
+        Vec3::new(a * nh.x(), a * nh.y(), nh.z().max(0.0)).unit_vector()
+    }
+
+    /// Density of `sample_visible` returning `h`, per solid angle.
+    pub fn visible_pdf(&self, wo: &Vec3, h: &Vec3) -> f64 {
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z()
+    }
+}
+
+/// Fresnel reflectance of a conductor with complex index of refraction
+/// `eta + i k`, for light arriving at `cos_theta` from the normal.
+pub fn fresnel_conductor(cos_theta: f64, eta: &Color, k: &Color) -> Color {
+    let c = cos_theta.clamp(0.0, 1.0);
+    let (c2, s2) = (c * c, 1.0 - c * c);
+    let channel = |eta: f64, k: f64| {
+        let t = eta * eta - k * k - s2;
+        let a2b2 = (t * t + 4.0 * eta * eta * k * k).sqrt();
+        let a = (0.5 * (a2b2 + t)).max(0.0).sqrt();
+        let rs = (a2b2 + c2 - 2.0 * a * c) / (a2b2 + c2 + 2.0 * a * c);
+        let rp = rs * (c2 * a2b2 + s2 * s2 - 2.0 * a * c * s2) / (c2 * a2b2 + s2 * s2 + 2.0 * a * c * s2);
+        0.5 * (rs + rp)
+    };
+    Color::new(
+        channel(eta.r(), k.r()),
+        channel(eta.g(), k.g()),
+        channel(eta.b(), k.b()),
+    )
+}
+
+/// Fresnel reflectance of a dielectric interface for light arriving at
+/// `cos_theta` from the normal, where `eta` is the index of refraction on
+/// the far side over the one on the near side. One under total internal
+/// reflection.
+pub fn fresnel_dielectric(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// Cosine-weighted average of `fresnel` over the hemisphere.
+pub fn average_fresnel(fresnel: impl Fn(f64) -> Color) -> Color {
+    let steps = 64;
+    let mut sum = Color::new(0.0, 0.0, 0.0);
+    for i in 0..steps {
+        let mu = (i as f64 + 0.5) / steps as f64;
+        sum += (2.0 * mu / steps as f64) * fresnel(mu);
+    }
+    sum
+}
+
+// Grid of the albedo table, in roughness and in the cosine to the normal.
+const TABLE_SIZE: usize = 32;
+
+struct AlbedoTable {
+    /// Directional albedo by roughness, then cosine.
+    directional: Vec<f64>,
+    /// Its cosine-weighted average over the hemisphere, by roughness.
+    average: Vec<f64>,
+}
+
+/// Share of the light a GGX surface with perfect Fresnel reflects after a
+/// single bounce off its microfacets; the rest bounces between them.
+/// Tabulated once with stratified visible normal samples.
+fn albedo_table() -> &'static AlbedoTable {
+    static TABLE: OnceLock<AlbedoTable> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        let strata = 32;
+        let mut directional = Vec::with_capacity(TABLE_SIZE * TABLE_SIZE);
+        for i in 0..TABLE_SIZE {
+            let ggx = Ggx::new(alpha(i as f64 / (TABLE_SIZE - 1) as f64));
+            for j in 0..TABLE_SIZE {
+                let mu = (j as f64 / (TABLE_SIZE - 1) as f64).max(1e-3);
+                let wo = Vec3::new((1.0 - mu * mu).sqrt(), 0.0, mu);
+                let mut sum = 0.0;
+                for a in 0..strata {
+                    for b in 0..strata {
+                        let u1 = (a as f64 + 0.5) / strata as f64;
+                        let u2 = (b as f64 + 0.5) / strata as f64;
+                        let h = ggx.sample_visible(&wo, u1, u2);
+                        let wi = (2.0 * wo.dot(&h)) * &h - &wo;
+                        if wi.z() > 0.0 {
+                            sum += ggx.g2(&wo, &wi) / ggx.g1(&wo);
+                        }
+                    }
+                }
+                directional.push(sum / (strata * strata) as f64);
+            }
+        }
+        let average = directional
+            .chunks(TABLE_SIZE)
+            .map(|row| {
+                // Trapezoids over the cosine grid of 2 ∫ E(μ) μ dμ.
+                let step = 1.0 / (TABLE_SIZE - 1) as f64;
+                row.windows(2)
+                    .enumerate()
+                    .map(|(j, e)| step * (e[0] * j as f64 * step + e[1] * (j + 1) as f64 * step))
+                    .sum()
+            })
+            .collect();
+        AlbedoTable { directional, average }
+    })
+}
+
+/// Position of `x` in [0, 1] on the table grid: a cell and the weight of
+/// its upper end.
+fn cell(x: f64) -> (usize, f64) {
+    let x = x.clamp(0.0, 1.0) * (TABLE_SIZE - 1) as f64;
+    let i = (x.floor() as usize).min(TABLE_SIZE - 2);
+    (i, x - i as f64)
+}
+
+/// Single-scattering albedo of a white GGX mirror seen at `cos_theta`.
+pub fn directional_albedo(roughness: f64, cos_theta: f64) -> f64 {
+    let table = &albedo_table().directional;
+    let (i, s) = cell(roughness);
+    let (j, t) = cell(cos_theta);
+    let at = |i: usize, j: usize| table[i * TABLE_SIZE + j];
+    let lower = (1.0 - t) * at(i, j) + t * at(i, j + 1);
+    let upper = (1.0 - t) * at(i + 1, j) + t * at(i + 1, j + 1);
+    (1.0 - s) * lower + s * upper
+}
+
+/// `directional_albedo` averaged over the hemisphere.
+pub fn average_albedo(roughness: f64) -> f64 {
+    let table = &albedo_table().average;
+    let (i, s) = cell(roughness);
+    (1.0 - s) * table[i] + s * table[i + 1]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_visible_normals() {
+        // Sampled normals face the viewer and the density integrates to one.
+        let ggx = Ggx::new(0.4);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        let n = 256;
+        let mut integral = 0.0;
+        for a in 0..n {
+            for b in 0..n {
+                let h = ggx.sample_visible(&wo, (a as f64 + 0.5) / n as f64, (b as f64 + 0.5) / n as f64);
+                assert!(h.z() >= 0.0 && wo.dot(&h) >= -1e-12);
+                assert!((h.length() - 1.0).abs() < 1e-9);
+            }
+        }
+        // Midpoint rule over the hemisphere in (cos θ, φ).
+        for a in 0..n {
+            for b in 0..n {
+                let cos = (a as f64 + 0.5) / n as f64;
+                let phi = 2.0 * PI * (b as f64 + 0.5) / n as f64;
+                let sin = (1.0 - cos * cos).sqrt();
+                let h = Vec3::new(sin * phi.cos(), sin * phi.sin(), cos);
+                integral += ggx.visible_pdf(&wo, &h) * 2.0 * PI / (n * n) as f64;
+            }
+        }
+        assert!((integral - 1.0).abs() < 1e-2, "{}", integral);
+    }
+
+    #[test]
+    fn test_fresnel_conductor() {
+        // A dielectric-like index with no absorption matches Schlick's R0
+        // at normal incidence, and everything reflects at grazing angles.
+        let eta = Color::new(1.5, 1.5, 1.5);
+        let k = Color::new(0.0, 0.0, 0.0);
+        let r0 = fresnel_conductor(1.0, &eta, &k);
+        assert!((r0.r() - 0.04).abs() < 1e-9);
+        assert!((fresnel_conductor(0.0, &eta, &k).g() - 1.0).abs() < 1e-9);
+
+        // Dielectrics agree with conductors that don't absorb, and reflect
+        // everything past the critical angle.
+        for cos in [0.1, 0.5, 0.9] {
+            assert!((fresnel_dielectric(cos, 1.5) - fresnel_conductor(cos, &eta, &k).r()).abs() < 1e-9);
+        }
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+
+        let gold = fresnel_conductor(1.0, &Color::new(0.143, 0.374, 1.442), &Color::new(3.983, 2.385, 1.603));
+        assert!(gold.r() > gold.g() && gold.g() > gold.b());
+    }
+
+    #[test]
+    fn test_albedo_table() {
+        // Smooth mirrors lose nothing and rougher ones lose more, matching
+        // a direct quadrature of the single-bounce reflectance.
+        assert!((directional_albedo(0.0, 0.5) - 1.0).abs() < 1e-3);
+        assert!((directional_albedo(0.5, 1.0) - 0.916).abs() < 5e-3);
+        assert!((directional_albedo(1.0, 1.0) - 0.307).abs() < 5e-3);
+        assert!((directional_albedo(1.0, 0.3) - 0.560).abs() < 5e-3);
+        assert!(average_albedo(1.0) < average_albedo(0.5));
+        assert!((average_albedo(0.0) - 1.0).abs() < 1e-2);
+    }
+}
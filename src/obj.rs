@@ -0,0 +1,406 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::material::Material;
+use crate::figure::Figure;
+use crate::mesh::Mesh;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io(PathBuf, std::io::Error),
+    Parse { file: PathBuf, line: usize, message: String },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io(file, err) => write!(f, "{}: {}", file.display(), err),
+            ObjError::Parse { file, line, message } => write!(f, "{}:{}: {}", file.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// The subset of an MTL material the renderer understands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MtlMaterial {
+    pub diffuse: Color,
+    pub specular: Color,
+    pub shininess: f64,
+    pub dissolve: f64,
+    pub ior: f64,
+    pub illum: Option<u32>,
+    pub emission: Color,
+}
+
+impl Default for MtlMaterial {
+    fn default() -> Self {
+        Self {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0.0, 0.0, 0.0),
+            shininess: 0.0,
+            dissolve: 1.0,
+            ior: 1.5,
+            illum: None,
+            emission: Color::new(0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl MtlMaterial {
+    /// Emissive materials become lights, transparent materials (dissolve
+    /// below one or a glass illumination model) become dielectrics,
+    /// materials whose specular color outweighs their diffuse color become
+    /// metals, and everything else is lambertian.
+    pub fn to_material(&self) -> Material {
+        if !self.emission.near_zero() {
+            return Material::diffuse_light(self.emission.clone(), 1.0);
+        }
+
+        let glass = matches!(self.illum, Some(4) | Some(6) | Some(7) | Some(9));
+        if self.dissolve < 1.0 || glass {
+            let ior = if self.ior > 0.0 { self.ior } else { 1.5 };
+            return Material::dielectric(ior);
+        }
+
+        let strength = |c: &Color| c.r().max(c.g()).max(c.b());
+        let mirror = matches!(self.illum, Some(3) | Some(5));
+        if mirror || strength(&self.specular) > strength(&self.diffuse) {
+            // Map the Phong exponent onto a roughness.
+            let fuzz = (2.0 / (self.shininess.max(0.0) + 2.0)).sqrt().min(1.0);
+            return Material::metal(self.specular.clone(), fuzz);
+        }
+
+        Material::lambertian(self.diffuse.clone())
+    }
+}
+
+struct LineParser<'a> {
+    file: &'a Path,
+    line: usize,
+}
+
+impl LineParser<'_> {
+    fn error(&self, message: String) -> ObjError {
+        ObjError::Parse { file: self.file.to_path_buf(), line: self.line, message }
+    }
+
+    fn floats(&self, keyword: &str, args: &[&str], min: usize, max: usize) -> Result<Vec<f64>, ObjError> {
+        if args.len() < min || args.len() > max {
+            let expected = if min == max { format!("{}", min) } else { format!("{} to {}", min, max) };
+            return Err(self.error(format!("`{}` expects {} numbers, found {}", keyword, expected, args.len())));
+        }
+        args.iter()
+            .map(|a| a.parse::<f64>().map_err(|_| self.error(format!("`{}`: invalid number `{}`", keyword, a))))
+            .collect()
+    }
+
+    fn float(&self, keyword: &str, args: &[&str]) -> Result<f64, ObjError> {
+        Ok(self.floats(keyword, args, 1, 1)?[0])
+    }
+
+    fn color(&self, keyword: &str, args: &[&str]) -> Result<Color, ObjError> {
+        // A single value is shorthand for a grey.
+        let v = self.floats(keyword, args, 1, 3)?;
+        match v.len() {
+            1 => Ok(Color::new(v[0], v[0], v[0])),
+            3 => Ok(Color::new(v[0], v[1], v[2])),
+            _ => Err(self.error(format!("`{}` expects 1 or 3 numbers, found 2", keyword))),
+        }
+    }
+
+    /// Resolves a 1-based (or negative, relative) OBJ index.
+    fn index(&self, raw: &str, count: usize, what: &str) -> Result<usize, ObjError> {
+        let i: i64 = raw
+            .parse()
+            .map_err(|_| self.error(format!("invalid {} index `{}`", what, raw)))?;
+        let resolved = if i > 0 { i - 1 } else { count as i64 + i };
+        if i == 0 || resolved < 0 || resolved >= count as i64 {
+            return Err(self.error(format!("{} index {} out of range ({} defined)", what, i, count)));
+        }
+        Ok(resolved as usize)
+    }
+}
+
+fn statements(source: &str) -> impl Iterator<Item = (usize, &str, Vec<&str>)> {
+    source.lines().enumerate().filter_map(|(n, line)| {
+        let line = line.split('#').next().unwrap_or("");
+        let mut words = line.split_whitespace();
+        let keyword = words.next()?;
+        Some((n + 1, keyword, words.collect()))
+    })
+}
+
+/// Parses MTL source into materials by name.
+pub fn parse_mtl(source: &str, file: &Path) -> Result<HashMap<String, MtlMaterial>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlMaterial)> = None;
+
+    for (line, keyword, args) in statements(source) {
+        let p = LineParser { file, line };
+
+        if keyword == "newmtl" {
+            if args.len() != 1 {
+                return Err(p.error("`newmtl` expects a single name".to_string()));
+            }
+            if let Some((name, mat)) = current.take() {
+                materials.insert(name, mat);
+            }
+            current = Some((args[0].to_string(), MtlMaterial::default()));
+            continue;
+        }
+
+        let mat = match current.as_mut() {
+            Some((_, mat)) => mat,
+            None => return Err(p.error(format!("`{}` before any `newmtl`", keyword))),
+        };
+
+        match keyword {
+            "Kd" => mat.diffuse = p.color(keyword, &args)?,
+            "Ks" => mat.specular = p.color(keyword, &args)?,
+            "Ke" => mat.emission = p.color(keyword, &args)?,
+            "Ns" => mat.shininess = p.float(keyword, &args)?,
+            "Ni" => mat.ior = p.float(keyword, &args)?,
+            "d" => mat.dissolve = p.float(keyword, &args)?,
+            "Tr" => mat.dissolve = 1.0 - p.float(keyword, &args)?,
+            "illum" => {
+                let model = args
+                    .first()
+                    .filter(|_| args.len() == 1)
+                    .and_then(|a| a.parse().ok())
+                    .ok_or_else(|| p.error("`illum` expects a single integer".to_string()))?;
+                mat.illum = Some(model);
+            }
+            // Ambient color and texture maps have no counterpart yet.
+            _ => {}
+        }
+    }
+
+    if let Some((name, mat)) = current {
+        materials.insert(name, mat);
+    }
+    Ok(materials)
+}
+
+#[derive(Default)]
+struct Group {
+    positions: Vec<Vec3>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<[f64; 2]>>,
+    indices: Vec<[usize; 3]>,
+    // (position, uv, normal) in the file's numbering -> vertex in this group.
+    vertices: HashMap<(usize, Option<usize>, Option<usize>), usize>,
+}
+
+impl Group {
+    fn into_mesh(self, material: Material) -> Mesh {
+        let normals = self.normals.into_iter().collect::<Option<Vec<_>>>();
+        let uvs = if self.uvs.iter().any(|uv| uv.is_some()) {
+            Some(self.uvs.into_iter().map(|uv| uv.unwrap_or([0.0, 0.0])).collect())
+        } else {
+            None
+        };
+        Mesh::new(self.positions, self.indices, normals, uvs, material)
+    }
+}
+
+/// Parses OBJ source into one mesh per material. `mtllib` paths are
+/// resolved against `dir`.
+pub fn parse_obj(source: &str, file: &Path, dir: &Path) -> Result<Vec<Figure>, ObjError> {
+    let mut positions: Vec<Vec3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut uvs: Vec<[f64; 2]> = Vec::new();
+
+    let mut library: HashMap<String, MtlMaterial> = HashMap::new();
+    // Groups keep the order in which their material was first used.
+    let mut groups: Vec<(Option<String>, Group)> = Vec::new();
+    let mut current = None;
+
+    for (line, keyword, args) in statements(source) {
+        let p = LineParser { file, line };
+        match keyword {
+            "v" => {
+                let v = p.floats(keyword, &args, 3, 4)?;
+                positions.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vn" => {
+                let v = p.floats(keyword, &args, 3, 3)?;
+                normals.push(Vec3::new(v[0], v[1], v[2]));
+            }
+            "vt" => {
+                let v = p.floats(keyword, &args, 1, 3)?;
+                uvs.push([v[0], v.get(1).copied().unwrap_or(0.0)]);
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(p.error("`mtllib` expects a file name".to_string()));
+                }
+                for name in &args {
+                    let path = dir.join(name);
+                    let source = std::fs::read_to_string(&path).map_err(|e| ObjError::Io(path.clone(), e))?;
+                    library.extend(parse_mtl(&source, &path)?);
+                }
+            }
+            "usemtl" => {
+                if args.len() != 1 {
+                    return Err(p.error("`usemtl` expects a single name".to_string()));
+                }
+                if !library.contains_key(args[0]) {
+                    return Err(p.error(format!("unknown material `{}`", args[0])));
+                }
+                current = Some(args[0].to_string());
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(p.error(format!("face needs at least 3 vertices, found {}", args.len())));
+                }
+
+                let group_index = match groups.iter().position(|(name, _)| *name == current) {
+                    Some(i) => i,
+                    None => {
+                        groups.push((current.clone(), Group::default()));
+                        groups.len() - 1
+                    }
+                };
+                let group = &mut groups[group_index].1;
+
+                let mut face = Vec::with_capacity(args.len());
+                for vertex in &args {
+                    let mut parts = vertex.split('/');
+                    let v = p.index(parts.next().unwrap_or(""), positions.len(), "vertex")?;
+                    let vt = match parts.next() {
+                        None | Some("") => None,
+                        Some(raw) => Some(p.index(raw, uvs.len(), "texture")?),
+                    };
+                    let vn = match parts.next() {
+                        None | Some("") => None,
+                        Some(raw) => Some(p.index(raw, normals.len(), "normal")?),
+                    };
+                    if parts.next().is_some() {
+                        return Err(p.error(format!("malformed face vertex `{}`", vertex)));
+                    }
+
+                    let next = group.positions.len();
+                    let index = *group.vertices.entry((v, vt, vn)).or_insert(next);
+                    if index == next {
+                        group.positions.push(positions[v].clone());
+                        group.uvs.push(vt.map(|i| uvs[i]));
+                        group.normals.push(vn.map(|i| normals[i].clone()));
+                    }
+                    face.push(index);
+                }
+
+                // Fan triangulation; OBJ polygons are expected to be convex.
+                for i in 1..face.len() - 1 {
+                    group.indices.push([face[0], face[i], face[i + 1]]);
+                }
+            }
+            // Groups, objects, smoothing and line elements don't affect the render.
+            _ => {}
+        }
+    }
+
+    Ok(groups
+        .into_iter()
+        .map(|(name, group)| {
+            let material = name
+                .and_then(|n| library.get(&n))
+                .cloned()
+                .unwrap_or_default()
+                .to_material();
+            Figure::mesh(group.into_mesh(material))
+        })
+        .collect())
+}
+
+/// Loads an OBJ file and the MTL libraries it references.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Figure>, ObjError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| ObjError::Io(path.to_path_buf(), e))?;
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    parse_obj(&source, path, dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(source: &str) -> Result<Vec<Figure>, ObjError> {
+        parse_obj(source, Path::new("test.obj"), Path::new("."))
+    }
+
+    fn line_of(err: ObjError) -> usize {
+        match err {
+            ObjError::Parse { line, .. } => line,
+            ObjError::Io(..) => panic!("unexpected io error"),
+        }
+    }
+
+    #[test]
+    fn test_quad_is_triangulated_and_deduplicated() {
+        let source = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nf 1//1 2//1 3//1 4//1\n";
+        let figures = parse(source).unwrap();
+        assert_eq!(figures.len(), 1);
+        match &figures[0] {
+            Figure::Mesh(mesh) => {
+                assert_eq!(mesh.indices, vec![[0, 1, 2], [0, 2, 3]]);
+                assert_eq!(mesh.positions.len(), 4);
+                assert!(mesh.normals.is_some());
+                assert!(mesh.uvs.is_none());
+            }
+            _ => panic!("expected a mesh"),
+        }
+    }
+
+    #[test]
+    fn test_negative_indices() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3 -2 -1\n";
+        assert_eq!(parse(source).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_errors_report_line() {
+        assert_eq!(line_of(parse("v 0 0 0\nv 1 x 0\n").err().unwrap()), 2);
+        assert_eq!(line_of(parse("v 0 0 0\n\n# c\nf 1 2 3\n").err().unwrap()), 4);
+        assert_eq!(line_of(parse("v 0 0 0\nf 1 1\n").err().unwrap()), 2);
+        assert_eq!(line_of(parse("usemtl missing\n").err().unwrap()), 1);
+    }
+
+    #[test]
+    fn test_mtl_mapping() {
+        let source = "\
+newmtl red
+Kd 0.8 0.1 0.1
+newmtl gold
+Kd 0.1 0.1 0.1
+Ks 0.9 0.7 0.2
+Ns 1000
+newmtl glass
+Ni 1.45
+d 0.2
+newmtl lamp
+Kd 0.8 0.8 0.8
+Ke 10 10 8
+";
+        let mats = parse_mtl(source, Path::new("test.mtl")).unwrap();
+        assert!(matches!(mats["red"].to_material(), Material::Lambertian(_)));
+        match mats["gold"].to_material() {
+            Material::Conductor(c) => assert!(c.roughness < 0.1),
+            _ => panic!("expected a metal"),
+        }
+        match mats["glass"].to_material() {
+            Material::Dielectric(d) => assert_eq!(d.ref_idx, 1.45),
+            _ => panic!("expected a dielectric"),
+        }
+        assert!(matches!(mats["lamp"].to_material(), Material::DiffuseLight(_)));
+
+        let err = parse_mtl("Kd 1 1 1\n", Path::new("test.mtl")).err().unwrap();
+        assert_eq!(line_of(err), 1);
+        let err = parse_mtl("newmtl a\nNs\n", Path::new("test.mtl")).err().unwrap();
+        assert_eq!(line_of(err), 2);
+    }
+}
@@ -0,0 +1,28 @@
+use crate::vec3::Vec3;
+
+/// Orthonormal basis with `w` along a given direction, used to turn
+/// samples around the z axis into samples around that direction.
+#[derive(Debug, Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn from_w(n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+        Self { u, v, w }
+    }
+
+    pub fn local(&self, a: f64, b: f64, c: f64) -> Vec3 {
+        &(a * &self.u) + &(b * &self.v) + (c * &self.w)
+    }
+}
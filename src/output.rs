@@ -0,0 +1,297 @@
+use crate::image::Image;
+use crate::hdr;
+
+use std::fs::File;
+use std::io::{self, BufWriter, Cursor, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BitDepth {
+    Eight,
+    Sixteen,
+}
+
+impl BitDepth {
+    fn from_bits(bits: Option<u8>) -> Result<BitDepth, String> {
+        match bits {
+            None | Some(8) => Ok(BitDepth::Eight),
+            Some(16) => Ok(BitDepth::Sixteen),
+            Some(bits) => Err(format!("unsupported bit depth {}, expected 8 or 16", bits)),
+        }
+    }
+
+    fn max_value(&self) -> u32 {
+        match self {
+            BitDepth::Eight => 255,
+            BitDepth::Sixteen => 65535,
+        }
+    }
+}
+
+/// Sample type of floating point formats.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precision {
+    Half,
+    Float,
+}
+
+impl Precision {
+    fn from_bits(bits: Option<u8>) -> Result<Precision, String> {
+        match bits {
+            None | Some(16) => Ok(Precision::Half),
+            Some(32) => Ok(Precision::Float),
+            Some(bits) => Err(format!("unsupported bit depth {} for .exr, expected 16 or 32", bits)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png(BitDepth),
+    /// Binary PPM (P6).
+    Ppm(BitDepth),
+    /// Plain text PPM (P3).
+    PpmAscii(BitDepth),
+    /// Scanline OpenEXR with linear, unclamped radiance.
+    Exr(Precision),
+    /// Radiance RGBE with linear, unclamped radiance.
+    Hdr,
+}
+
+impl Format {
+    /// Picks the encoder from the file extension. `bits` defaults to 8 for
+    /// integer formats and 16 (half floats) for EXR. `ascii` selects P3 for
+    /// `.ppm` files and is rejected for anything else.
+    pub fn from_path(path: &Path, bits: Option<u8>, ascii: bool) -> Result<Format, String> {
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+
+        match (extension.as_deref(), ascii) {
+            (Some("ppm"), false) => Ok(Format::Ppm(BitDepth::from_bits(bits)?)),
+            (Some("ppm"), true) => Ok(Format::PpmAscii(BitDepth::from_bits(bits)?)),
+            (Some("png"), false) => Ok(Format::Png(BitDepth::from_bits(bits)?)),
+            (Some("exr"), false) => Ok(Format::Exr(Precision::from_bits(bits)?)),
+            (Some("hdr"), false) => match bits {
+                None => Ok(Format::Hdr),
+                Some(_) => Err("the bit depth of .hdr files is fixed".to_string()),
+            },
+            (Some(_), true) => Err("ascii output is only available for .ppm files".to_string()),
+            (Some(e), false) => Err(format!("unsupported output format `.{}`, expected .png, .ppm, .exr or .hdr", e)),
+            (None, _) => Err(format!("{}: cannot pick a format without a file extension", path.display())),
+        }
+    }
+}
+
+pub fn write_image(path: &Path, image: &Image, format: Format) -> io::Result<()> {
+    // Fail before leaving an empty file behind.
+    check_size(image)?;
+    let mut writer = BufWriter::new(File::create(path)?);
+    encode(&mut writer, image, format)?;
+    writer.flush()
+}
+
+pub fn encode<W: Write>(writer: &mut W, image: &Image, format: Format) -> io::Result<()> {
+    check_size(image)?;
+    match format {
+        Format::Png(depth) => write_png(writer, image, depth),
+        Format::Ppm(depth) => write_ppm(writer, image, depth),
+        Format::PpmAscii(depth) => write_ppm_ascii(writer, image, depth),
+        Format::Exr(precision) => write_exr(writer, image, precision),
+        Format::Hdr => hdr::write_hdr(writer, image),
+    }
+}
+
+/// None of the formats can hold an image without pixels.
+fn check_size(image: &Image) -> io::Result<()> {
+    if image.width == 0 || image.height == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "image has no pixels"));
+    }
+    Ok(())
+}
+
+/// 8 bit samples as bytes, 16 bit samples as big-endian pairs, which is
+/// what both PNG and binary PPM expect.
+fn samples(image: &Image, depth: BitDepth) -> Vec<u8> {
+    let values = image.quantize(depth.max_value());
+    match depth {
+        BitDepth::Eight => values.into_iter().map(|v| v as u8).collect(),
+        BitDepth::Sixteen => values.into_iter().flat_map(|v| (v as u16).to_be_bytes()).collect(),
+    }
+}
+
+fn write_png<W: Write>(writer: &mut W, image: &Image, depth: BitDepth) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, image.width as u32, image.height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(match depth {
+        BitDepth::Eight => png::BitDepth::Eight,
+        BitDepth::Sixteen => png::BitDepth::Sixteen,
+    });
+    let mut png_writer = encoder.write_header().map_err(io::Error::other)?;
+    png_writer.write_image_data(&samples(image, depth)).map_err(io::Error::other)?;
+    png_writer.finish().map_err(io::Error::other)
+}
+
+fn write_ppm<W: Write>(writer: &mut W, image: &Image, depth: BitDepth) -> io::Result<()> {
+    writeln!(writer, "P6\n{} {}\n{}", image.width, image.height, depth.max_value())?;
+    writer.write_all(&samples(image, depth))
+}
+
+fn write_ppm_ascii<W: Write>(writer: &mut W, image: &Image, depth: BitDepth) -> io::Result<()> {
+    writeln!(writer, "P3\n{} {}\n{}", image.width, image.height, depth.max_value())?;
+    for rgb in image.quantize(depth.max_value()).chunks(3) {
+        writeln!(writer, "{} {} {}", rgb[0], rgb[1], rgb[2])?;
+    }
+    Ok(())
+}
+
+fn write_exr<W: Write>(writer: &mut W, image: &Image, precision: Precision) -> io::Result<()> {
+    use exr::prelude::{f16, Encoding, Image as ExrImage, Layer, LayerAttributes, SpecificChannels, Vec2, WritableImage};
+
+    let pixel = |Vec2(x, y): Vec2<usize>| &image.pixels[y * image.width + x];
+    let size = (image.width, image.height);
+    let attributes = LayerAttributes::default();
+    // ZIP compressed blocks of 16 scanlines.
+    let encoding = Encoding::SMALL_LOSSLESS;
+
+    // The encoder needs to seek back to fill in the block offsets.
+    let mut buffer = Cursor::new(Vec::new());
+    let result = match precision {
+        Precision::Half => {
+            let channels = SpecificChannels::rgb(|p| {
+                let c = pixel(p);
+                (f16::from_f64(c.r()), f16::from_f64(c.g()), f16::from_f64(c.b()))
+            });
+            ExrImage::from_layer(Layer::new(size, attributes, encoding, channels))
+                .write()
+                .to_buffered(&mut buffer)
+        }
+        Precision::Float => {
+            let channels = SpecificChannels::rgb(|p| {
+                let c = pixel(p);
+                (c.r() as f32, c.g() as f32, c.b() as f32)
+            });
+            ExrImage::from_layer(Layer::new(size, attributes, encoding, channels))
+                .write()
+                .to_buffered(&mut buffer)
+        }
+    };
+    result.map_err(io::Error::other)?;
+    writer.write_all(&buffer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn gradient() -> Image {
+        let (width, height) = (4, 3);
+        let pixels = (0..width * height)
+            .map(|i| Color::new(i as f64 / 11.0, 0.25, 4.0))
+            .collect();
+        Image::new(width, height, pixels)
+    }
+
+    #[test]
+    fn test_format_from_extension() {
+        let d = BitDepth::Eight;
+        assert_eq!(Format::from_path(Path::new("a.PNG"), None, false), Ok(Format::Png(d)));
+        assert_eq!(Format::from_path(Path::new("a.png"), Some(16), false), Ok(Format::Png(BitDepth::Sixteen)));
+        assert_eq!(Format::from_path(Path::new("a.ppm"), None, false), Ok(Format::Ppm(d)));
+        assert_eq!(Format::from_path(Path::new("a.ppm"), None, true), Ok(Format::PpmAscii(d)));
+        assert_eq!(Format::from_path(Path::new("a.exr"), None, false), Ok(Format::Exr(Precision::Half)));
+        assert_eq!(Format::from_path(Path::new("a.exr"), Some(32), false), Ok(Format::Exr(Precision::Float)));
+        assert_eq!(Format::from_path(Path::new("a.hdr"), None, false), Ok(Format::Hdr));
+        assert!(Format::from_path(Path::new("a.png"), Some(32), false).is_err());
+        assert!(Format::from_path(Path::new("a.exr"), Some(8), false).is_err());
+        assert!(Format::from_path(Path::new("a.hdr"), Some(8), false).is_err());
+        assert!(Format::from_path(Path::new("a.png"), None, true).is_err());
+        assert!(Format::from_path(Path::new("a.jpg"), None, false).is_err());
+        assert!(Format::from_path(Path::new("a"), None, false).is_err());
+    }
+
+    #[test]
+    fn test_ppm_encoders_agree() {
+        let image = gradient();
+        let mut binary = Vec::new();
+        let mut ascii = Vec::new();
+        encode(&mut binary, &image, Format::Ppm(BitDepth::Eight)).unwrap();
+        encode(&mut ascii, &image, Format::PpmAscii(BitDepth::Eight)).unwrap();
+
+        let header = b"P6\n4 3\n255\n";
+        assert_eq!(&binary[..header.len()], header);
+        let body = &binary[header.len()..];
+
+        let text = String::from_utf8(ascii).unwrap();
+        let values: Vec<u8> = text.split_whitespace().skip(4).map(|v| v.parse().unwrap()).collect();
+        assert_eq!(body, &values[..]);
+        // Over-exposed channels clamp to white, 0.25 is gamma corrected to half.
+        assert_eq!(&body[..3], &[0, 128, 255]);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let image = gradient();
+        for depth in [BitDepth::Eight, BitDepth::Sixteen] {
+            let mut bytes = Vec::new();
+            encode(&mut bytes, &image, Format::Png(depth)).unwrap();
+
+            let decoder = png::Decoder::new(&bytes[..]);
+            let mut reader = decoder.read_info().unwrap();
+            let mut buf = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut buf).unwrap();
+            assert_eq!((info.width, info.height), (4, 3));
+            assert_eq!(&buf[..info.buffer_size()], &samples(&image, depth)[..]);
+        }
+    }
+
+    #[test]
+    fn test_empty_image() {
+        let formats = [
+            Format::Png(BitDepth::Eight),
+            Format::Ppm(BitDepth::Sixteen),
+            Format::PpmAscii(BitDepth::Eight),
+            Format::Exr(Precision::Half),
+            Format::Hdr,
+        ];
+        for image in [Image::new(0, 3, Vec::new()), Image::new(4, 0, Vec::new())] {
+            for format in formats {
+                let err = encode(&mut Vec::new(), &image, format).unwrap_err();
+                assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            }
+        }
+    }
+
+    #[test]
+    fn test_exr_keeps_linear_radiance() {
+        use exr::prelude::{read, ReadChannels, ReadLayers};
+
+        let image = gradient();
+        for precision in [Precision::Half, Precision::Float] {
+            let mut bytes = Vec::new();
+            encode(&mut bytes, &image, Format::Exr(precision)).unwrap();
+
+            let read = read()
+                .no_deep_data()
+                .largest_resolution_level()
+                .rgba_channels(
+                    |size, _| vec![[0.0f32; 3]; size.width() * size.height()],
+                    |pixels, p, (r, g, b, _): (f32, f32, f32, f32)| pixels[p.y() * 4 + p.x()] = [r, g, b],
+                )
+                .first_valid_layer()
+                .all_attributes()
+                .from_buffered(Cursor::new(&bytes))
+                .unwrap();
+
+            let pixels = read.layer_data.channel_data.pixels;
+            for (read, expected) in pixels.iter().zip(&image.pixels) {
+                for (r, e) in read.iter().zip(expected.iter()) {
+                    // Values above one survive; half floats keep ~3 significant digits.
+                    assert!((*r as f64 - e).abs() <= e * 1e-3);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use crate::vec3::Vec3;
+
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise on a lattice of random unit vectors, blended with the
+/// quintic fade of Perlin's improved noise so it is smooth to the second
+/// derivative. Values lie roughly in [-1, 1].
+#[derive(Debug)]
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new<R: Rng>(rng: &mut R) -> Self {
+        let gradients = (0..POINT_COUNT).map(|_| Vec3::rand_unit_vector(rng)).collect();
+        let mut permutation = || {
+            let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+        let perm_x = permutation();
+        let perm_y = permutation();
+        let perm_z = permutation();
+        Self { gradients, perm_x, perm_y, perm_z }
+    }
+
+    pub fn noise(&self, p: &Vec3) -> f64 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+        let wrap = |n: i64| (n & (POINT_COUNT as i64 - 1)) as usize;
+
+        let (uu, vv, ww) = (fade(u), fade(v), fade(w));
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[wrap(i + di)] ^ self.perm_y[wrap(j + dj)] ^ self.perm_z[wrap(k + dk)];
+                    let (a, b, c) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - a, v - b, w - c);
+                    sum += (a * uu + (1.0 - a) * (1.0 - uu))
+                        * (b * vv + (1.0 - b) * (1.0 - vv))
+                        * (c * ww + (1.0 - c) * (1.0 - ww))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+        sum
+    }
+
+    /// Sum of `depth` octaves of noise, each at twice the frequency and
+    /// half the weight of the last.
+    pub fn turbulence(&self, p: &Vec3, depth: usize) -> f64 {
+        let mut sum = 0.0;
+        let mut point = p.clone();
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            sum += weight * self.noise(&point);
+            weight *= 0.5;
+            point *= 2.0;
+        }
+        sum.abs()
+    }
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (6.0 * t - 15.0) + 10.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_noise_is_smooth_and_bounded() {
+        let perlin = Perlin::new(&mut StdRng::seed_from_u64(1));
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..1000 {
+            let p = Vec3::rand_range(&mut rng, -50.0, 50.0);
+            let n = perlin.noise(&p);
+            assert!((-1.0..=1.0).contains(&n));
+            let nearby = perlin.noise(&(&p + Vec3::new(1e-6, 1e-6, 1e-6)));
+            assert!((n - nearby).abs() < 1e-4);
+        }
+        // Gradient noise vanishes on the lattice.
+        assert_eq!(perlin.noise(&Vec3::new(3.0, -2.0, 7.0)), 0.0);
+    }
+}
@@ -0,0 +1,216 @@
+use crate::hitrecord::{HitRecord, FaceNormal};
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+
+use std::f64::consts::PI;
+
+/// Distance along `ray` to the plane through `point` with normal `normal`,
+/// if it lies in the range. Rays parallel to the plane miss it.
+fn intersect_plane(ray: &Ray, point: &Vec3, normal: &Vec3, t_min: f64, t_max: f64) -> Option<f64> {
+    let denom = normal.dot(&ray.direction);
+    if denom.abs() < 1e-12 {
+        return None;
+    }
+    let t = normal.dot(&(point - &ray.origin)) / denom;
+    if t < t_min || t_max < t {
+        return None;
+    }
+    Some(t)
+}
+
+/// Distance to the parallelogram with corner `q` and edges `u` and `v`,
+/// with the hit's coordinates along the two edges.
+pub fn intersect_quad(ray: &Ray, q: &Vec3, u: &Vec3, v: &Vec3, t_min: f64, t_max: f64) -> Option<(f64, f64, f64)> {
+    let n = u.cross(v);
+    let t = intersect_plane(ray, q, &n, t_min, t_max)?;
+
+    // Coordinates of the hit in the (u, v) frame.
+    let w = &n / n.length_squared();
+    let h = ray.at(t) - q;
+    let alpha = w.dot(&h.cross(v));
+    let beta = w.dot(&u.cross(&h));
+    if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+    Some((t, alpha, beta))
+}
+
+pub fn intersect_disk(ray: &Ray, center: &Vec3, normal: &Vec3, radius: f64, t_min: f64, t_max: f64) -> Option<f64> {
+    let t = intersect_plane(ray, center, normal, t_min, t_max)?;
+    if (ray.at(t) - center).length_squared() > radius * radius {
+        return None;
+    }
+    Some(t)
+}
+
+fn record<'a>(ray: &Ray, point: Vec3, outward_normal: &Vec3, t: f64, material: &'a Material) -> HitRecord<'a> {
+    match HitRecord::get_face_normal(ray, outward_normal) {
+        FaceNormal::Front(normal) => HitRecord::new(point, normal, t, true, material),
+        FaceNormal::Back(normal) => HitRecord::new(point, normal, t, false, material),
+    }
+}
+
+/// Infinite plane. Its UVs are distances along two fixed directions in the
+/// plane, so they are unbounded and in world units.
+#[derive(Debug, Clone)]
+pub struct Plane {
+    pub point: Vec3,
+    pub normal: Vec3,
+    pub material: Material,
+    basis: Onb,
+}
+
+impl Plane {
+    /// The front side is the one `normal` points to.
+    pub fn new(point: Vec3, normal: Vec3, material: Material) -> Self {
+        let basis = Onb::from_w(&normal);
+        Self { point, normal: basis.w.clone(), material, basis }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let t = intersect_plane(ray, &self.point, &self.normal, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = &point - &self.point;
+        let (u, v) = (offset.dot(&self.basis.u), offset.dot(&self.basis.v));
+        Some(record(ray, point, &self.normal, t, &self.material).with_uv(u, v))
+    }
+}
+
+/// Parallelogram with a corner at `q` and edges `u` and `v`. UVs run from
+/// 0 to 1 along the two edges.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    pub q: Vec3,
+    pub u: Vec3,
+    pub v: Vec3,
+    pub material: Material,
+}
+
+impl Quad {
+    /// The front faces the side `u × v` points to.
+    pub fn new(q: Vec3, u: Vec3, v: Vec3, material: Material) -> Self {
+        Self { q, u, v, material }
+    }
+
+    pub fn normal(&self) -> Vec3 {
+        self.u.cross(&self.v).unit_vector()
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (t, alpha, beta) = intersect_quad(ray, &self.q, &self.u, &self.v, t_min, t_max)?;
+        Some(record(ray, ray.at(t), &self.normal(), t, &self.material).with_uv(alpha, beta))
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        let far = &self.q + &self.u + &self.v;
+        Aabb::new(self.q.clone(), self.q.clone())
+            .include(&(&self.q + &self.u))
+            .include(&(&self.q + &self.v))
+            .include(&far)
+    }
+}
+
+/// Flat disk. u is the angle around the center and v the distance from
+/// it, both scaled to [0, 1].
+#[derive(Debug, Clone)]
+pub struct Disk {
+    pub center: Vec3,
+    pub normal: Vec3,
+    pub radius: f64,
+    pub material: Material,
+    basis: Onb,
+}
+
+impl Disk {
+    pub fn new(center: Vec3, normal: Vec3, radius: f64, material: Material) -> Self {
+        let basis = Onb::from_w(&normal);
+        Self { center, normal: basis.w.clone(), radius: radius.abs(), material, basis }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let t = intersect_disk(ray, &self.center, &self.normal, self.radius, t_min, t_max)?;
+        let point = ray.at(t);
+        let offset = &point - &self.center;
+        let distance = offset.length();
+        let phi = offset.dot(&self.basis.v).atan2(offset.dot(&self.basis.u));
+        let u = if phi < 0.0 { phi + 2.0 * PI } else { phi } / (2.0 * PI);
+        let v = distance / self.radius;
+        Some(record(ray, point, &self.normal, t, &self.material).with_uv(u, v))
+    }
+
+    /// Along each axis the disk reaches `radius * sin` of the angle between
+    /// that axis and the normal.
+    pub fn bounding_box(&self) -> Aabb {
+        let reach = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let half = Vec3::new(reach(self.normal.x()), reach(self.normal.y()), reach(self.normal.z()));
+        Aabb::new(&self.center - &half, &self.center + &half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn white() -> Material {
+        Material::lambertian(Color::new(1.0, 1.0, 1.0))
+    }
+
+    #[test]
+    fn test_plane() {
+        let plane = Plane::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), white());
+        let down = Ray::new(Vec3::new(3.0, 5.0, -4.0), Vec3::new(0.0, -1.0, 0.0));
+        let rec = plane.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.t, 5.0);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert!(rec.front_face);
+        assert!((rec.u * rec.u + rec.v * rec.v - 25.0).abs() < 1e-9);
+
+        // From below the normal faces the ray.
+        let up = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = plane.hit(&up, 0.001, f64::INFINITY).unwrap();
+        assert_eq!(rec.normal, Vec3::new(0.0, -1.0, 0.0));
+        assert!(!rec.front_face);
+
+        // Grazing rays never hit.
+        let grazing = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(plane.hit(&grazing, 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_quad() {
+        let quad = Quad::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 0.0), white());
+        let at = |x: f64, y: f64| {
+            let ray = Ray::new(Vec3::new(x, y, 1.0), Vec3::new(0.0, 0.0, -1.0));
+            quad.hit(&ray, 0.001, f64::INFINITY).map(|rec| (rec.u, rec.v, rec.normal))
+        };
+        let (u, v, normal) = at(1.5, 0.5).unwrap();
+        assert!((u - 0.5).abs() < 1e-12 && (v - 0.5).abs() < 1e-12);
+        assert_eq!(normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!(at(0.2, 0.5).is_none());
+        assert!(at(2.4, 0.5).is_some());
+        assert!(at(2.6, 0.5).is_none());
+
+        let bounds = quad.bounding_box();
+        assert_eq!((bounds.min, bounds.max), (Vec3::new(0.0, 0.0, 0.0), Vec3::new(3.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn test_disk() {
+        let disk = Disk::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.0, 0.0, 1.0), 2.0, white());
+        let at = |x: f64, y: f64| {
+            let ray = Ray::new(Vec3::new(x, y, 3.0), Vec3::new(0.0, 0.0, -1.0));
+            disk.hit(&ray, 0.001, f64::INFINITY)
+        };
+        let rec = at(2.0, 1.0).unwrap();
+        assert!((rec.v - 0.5).abs() < 1e-12);
+        assert!((0.0..1.0).contains(&rec.u));
+        assert!(at(2.5, 2.5).is_none());
+
+        let bounds = disk.bounding_box();
+        assert_eq!((bounds.min, bounds.max), (Vec3::new(-1.0, -1.0, 1.0), Vec3::new(3.0, 3.0, 1.0)));
+    }
+}
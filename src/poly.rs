@@ -0,0 +1,145 @@
+/*
+ * Real roots of the polynomials that ray-surface intersections reduce to.
+ * Coefficients are listed from the highest degree down.
+ */
+
+/// Roots of `a t² + b t + c`, smallest first, or `None` if there are no
+/// real ones. Uses the form that avoids cancelling `b` against the square
+/// root; `a` must not be zero.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Option<(f64, f64)> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 {
+        // b and the discriminant are both zero.
+        (0.0, 0.0)
+    } else {
+        (q / a, c / q)
+    };
+    Some(if t0 <= t1 { (t0, t1) } else { (t1, t0) })
+}
+
+pub fn evaluate(coeffs: &[f64], t: f64) -> f64 {
+    coeffs.iter().fold(0.0, |acc, c| acc * t + c)
+}
+
+fn derivative(coeffs: &[f64]) -> Vec<f64> {
+    let degree = coeffs.len() - 1;
+    coeffs[..degree]
+        .iter()
+        .enumerate()
+        .map(|(i, c)| c * (degree - i) as f64)
+        .collect()
+}
+
+/// Every real root in `[lo, hi]`, ascending. The polynomial is monotonic
+/// between consecutive roots of its derivative, so each of those pieces
+/// holds at most one root, found by safeguarded Newton iteration.
+/// Tangent (even multiplicity) roots are reported when the polynomial
+/// comes within rounding error of zero at a turning point.
+pub fn roots_in(coeffs: &[f64], lo: f64, hi: f64) -> Vec<f64> {
+    let first = coeffs.iter().position(|c| *c != 0.0).unwrap_or(coeffs.len());
+    let coeffs = &coeffs[first..];
+    let mut roots = Vec::new();
+    match coeffs.len() {
+        0 | 1 => return roots,
+        2 => {
+            let t = -coeffs[1] / coeffs[0];
+            if lo <= t && t <= hi {
+                roots.push(t);
+            }
+            return roots;
+        }
+        _ => {}
+    }
+
+    let slope = derivative(coeffs);
+    let mut bounds = vec![lo];
+    bounds.extend(roots_in(&slope, lo, hi).into_iter().filter(|t| lo < *t && *t < hi));
+    bounds.push(hi);
+
+    // Size of the terms at t, to tell rounding noise from a real value.
+    let magnitude = |t: f64| coeffs.iter().fold(0.0, |acc: f64, c| acc * t.abs() + c.abs());
+    let tiny = |t: f64| evaluate(coeffs, t).abs() <= 1e-12 * magnitude(t);
+
+    for (i, pair) in bounds.windows(2).enumerate() {
+        let (a, b) = (pair[0], pair[1]);
+        let (fa, fb) = (evaluate(coeffs, a), evaluate(coeffs, b));
+        // Interior bounds are turning points, which may be tangent roots.
+        if i > 0 && tiny(a) || i == 0 && fa == 0.0 {
+            if roots.last().is_none_or(|r| *r < a) {
+                roots.push(a);
+            }
+            continue;
+        }
+        if fa.signum() != fb.signum() && fb != 0.0 {
+            roots.push(refine(coeffs, &slope, a, b, fa));
+        }
+    }
+    let last = *bounds.last().unwrap();
+    if evaluate(coeffs, last) == 0.0 && roots.last().is_none_or(|r| *r < last) {
+        roots.push(last);
+    }
+    roots
+}
+
+/// Root of a polynomial that changes sign between `a` and `b`.
+fn refine(coeffs: &[f64], slope: &[f64], mut a: f64, mut b: f64, fa: f64) -> f64 {
+    let rising = fa < 0.0;
+    let mut t = 0.5 * (a + b);
+    for _ in 0..100 {
+        let f = evaluate(coeffs, t);
+        if f == 0.0 {
+            return t;
+        }
+        if (f < 0.0) == rising {
+            a = t;
+        } else {
+            b = t;
+        }
+        // Newton's step if it stays inside the bracket, bisection otherwise.
+        let newton = t - f / evaluate(slope, t);
+        let next = if a < newton && newton < b { newton } else { 0.5 * (a + b) };
+        if (next - t).abs() <= 1e-15 * t.abs().max(1.0) {
+            return next;
+        }
+        t = next;
+    }
+    t
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quadratic() {
+        assert_eq!(solve_quadratic(1.0, -3.0, 2.0), Some((1.0, 2.0)));
+        assert_eq!(solve_quadratic(1.0, 0.0, 1.0), None);
+        // Tiny roots next to a huge one keep their precision.
+        let (small, large) = solve_quadratic(1.0, -1e8, 1.0).unwrap();
+        assert!((small - 1e-8).abs() < 1e-20);
+        assert!((large - 1e8).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_quartic_roots() {
+        // (t - 1)(t - 2)(t - 3)(t - 4)
+        let coeffs = [1.0, -10.0, 35.0, -50.0, 24.0];
+        let roots = roots_in(&coeffs, -10.0, 10.0);
+        assert_eq!(roots.len(), 4);
+        for (r, e) in roots.iter().zip([1.0, 2.0, 3.0, 4.0]) {
+            assert!((r - e).abs() < 1e-12);
+        }
+        assert_eq!(roots_in(&coeffs, 1.5, 3.5).len(), 2);
+
+        // (t - 1)² (t² + 1) touches zero without crossing it.
+        let roots = roots_in(&[1.0, -2.0, 2.0, -2.0, 1.0], -10.0, 10.0);
+        assert_eq!(roots.len(), 1);
+        assert!((roots[0] - 1.0).abs() < 1e-9);
+
+        assert!(roots_in(&[1.0, 0.0, 0.0, 0.0, 1.0], -10.0, 10.0).is_empty());
+    }
+}
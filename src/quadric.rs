@@ -0,0 +1,386 @@
+use crate::hitrecord::{HitRecord, FaceNormal};
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::figure::Sphere;
+use crate::poly::solve_quadratic;
+
+use std::f64::consts::PI;
+
+/// Coordinates of `p` in the frame with origin `base` and axes `basis`.
+/// The basis is orthonormal, so distances along rays are unchanged.
+pub fn to_local(basis: &Onb, base: &Vec3, p: &Vec3) -> Vec3 {
+    let offset = p - base;
+    Vec3::new(offset.dot(&basis.u), offset.dot(&basis.v), offset.dot(&basis.w))
+}
+
+pub fn local_ray(basis: &Onb, base: &Vec3, ray: &Ray) -> (Vec3, Vec3) {
+    let d = &ray.direction;
+    let direction = Vec3::new(d.dot(&basis.u), d.dot(&basis.v), d.dot(&basis.w));
+    (to_local(basis, base, &ray.origin), direction)
+}
+
+/// Angle of `(x, y)` around the origin, scaled to [0, 1).
+pub fn turn(y: f64, x: f64) -> f64 {
+    let phi = y.atan2(x);
+    (if phi < 0.0 { phi + 2.0 * PI } else { phi }) / (2.0 * PI)
+}
+
+pub fn record<'a>(ray: &Ray, t: f64, outward_normal: &Vec3, material: &'a Material) -> HitRecord<'a> {
+    match HitRecord::get_face_normal(ray, outward_normal) {
+        FaceNormal::Front(normal) => HitRecord::new(ray.at(t), normal, t, true, material),
+        FaceNormal::Back(normal) => HitRecord::new(ray.at(t), normal, t, false, material),
+    }
+}
+
+/// Closest of several candidate hits, in local coordinates.
+struct Nearest {
+    t_min: f64,
+    t_max: f64,
+    hit: Option<(f64, Vec3, f64, f64)>,
+}
+
+impl Nearest {
+    fn new(t_min: f64, t_max: f64) -> Self {
+        Self { t_min, t_max, hit: None }
+    }
+
+    fn in_range(&self, t: f64) -> bool {
+        self.t_min <= t && t <= self.t_max
+    }
+
+    fn offer(&mut self, t: f64, normal: Vec3, u: f64, v: f64) {
+        if self.in_range(t) {
+            self.t_max = t;
+            self.hit = Some((t, normal, u, v));
+        }
+    }
+}
+
+/// Side of a frustum along the local z axis from radius `r0` at z = 0 to
+/// `r1` at z = `height`, with optional flat caps. Cylinders and cones are
+/// the cases of equal radii and of a zero one.
+#[allow(clippy::too_many_arguments)]
+fn hit_frustum<'a>(
+    basis: &Onb,
+    base: &Vec3,
+    r0: f64,
+    r1: f64,
+    height: f64,
+    capped: bool,
+    material: &'a Material,
+    ray: &Ray,
+    t_min: f64,
+    t_max: f64,
+) -> Option<HitRecord<'a>> {
+    let (o, d) = local_ray(basis, base, ray);
+    let mut nearest = Nearest::new(t_min, t_max);
+
+    // x² + y² = (r0 + k z)², kept to the nappe between the caps.
+    let k = (r1 - r0) / height;
+    let r_o = r0 + k * o.z();
+    let a = d.x() * d.x() + d.y() * d.y() - k * k * d.z() * d.z();
+    let b = 2.0 * (o.x() * d.x() + o.y() * d.y() - k * r_o * d.z());
+    let c = o.x() * o.x() + o.y() * o.y() - r_o * r_o;
+    let roots = if a.abs() > 1e-12 * d.length_squared() {
+        solve_quadratic(a, b, c)
+    } else if b != 0.0 {
+        // Parallel to a cone's side, which it crosses once.
+        Some((-c / b, -c / b))
+    } else {
+        // Parallel to a cylinder's side.
+        None
+    };
+    if let Some((t0, t1)) = roots {
+        for t in [t0, t1] {
+            let p = &o + (t * &d);
+            if nearest.in_range(t) && (0.0..=height).contains(&p.z()) {
+                let radius = r0 + k * p.z();
+                let normal = Vec3::new(p.x(), p.y(), -k * radius);
+                // Only the apex has no gradient; it faces along the axis.
+                let normal = if normal.near_zero() { Vec3::new(0.0, 0.0, -k.signum()) } else { normal.unit_vector() };
+                nearest.offer(t, normal, turn(p.y(), p.x()), p.z() / height);
+            }
+        }
+    }
+
+    if capped && d.z() != 0.0 {
+        for (z, radius, facing) in [(0.0, r0, -1.0), (height, r1, 1.0)] {
+            let t = (z - o.z()) / d.z();
+            let p = &o + (t * &d);
+            let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+            if radius > 0.0 && rho <= radius {
+                nearest.offer(t, Vec3::new(0.0, 0.0, facing), turn(p.y(), p.x()), rho / radius);
+            }
+        }
+    }
+
+    let (t, normal, u, v) = nearest.hit?;
+    let outward_normal = basis.local(normal.x(), normal.y(), normal.z());
+    Some(record(ray, t, &outward_normal, material).with_uv(u, v))
+}
+
+/// Box around a frustum, from the disks at either end.
+fn frustum_box(basis: &Onb, base: &Vec3, r0: f64, r1: f64, height: f64) -> Aabb {
+    let axis = &basis.w;
+    let reach = |r: f64| {
+        let reach = |n: f64| r * (1.0 - n * n).max(0.0).sqrt();
+        Vec3::new(reach(axis.x()), reach(axis.y()), reach(axis.z()))
+    };
+    let top = base + (height * axis);
+    let (near, far) = (reach(r0), reach(r1));
+    Aabb::new(base - &near, base + &near).surrounding(&Aabb::new(&top - &far, &top + &far))
+}
+
+/// Circular cylinder from `base` to `top`, open or closed at the ends.
+/// On the side u goes around the axis and v from base to top; caps use the
+/// same u and the distance from the axis as v.
+#[derive(Debug, Clone)]
+pub struct Cylinder {
+    pub base: Vec3,
+    pub radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Material,
+    basis: Onb,
+}
+
+impl Cylinder {
+    pub fn new(base: Vec3, top: Vec3, radius: f64, capped: bool, material: Material) -> Self {
+        let axis = &top - &base;
+        let basis = Onb::from_w(&axis);
+        Self { base, radius: radius.abs(), height: axis.length(), capped, material, basis }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let r = self.radius;
+        hit_frustum(&self.basis, &self.base, r, r, self.height, self.capped, &self.material, ray, t_min, t_max)
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        frustum_box(&self.basis, &self.base, self.radius, self.radius, self.height)
+    }
+}
+
+/// Cone or frustum from a disk of `base_radius` at `base` to one of
+/// `top_radius` at `top`; a zero radius makes that end the apex. UVs are
+/// laid out as on a cylinder.
+#[derive(Debug, Clone)]
+pub struct Cone {
+    pub base: Vec3,
+    pub base_radius: f64,
+    pub top_radius: f64,
+    pub height: f64,
+    pub capped: bool,
+    pub material: Material,
+    basis: Onb,
+}
+
+impl Cone {
+    pub fn new(base: Vec3, top: Vec3, base_radius: f64, top_radius: f64, capped: bool, material: Material) -> Self {
+        let axis = &top - &base;
+        let basis = Onb::from_w(&axis);
+        let (base_radius, top_radius) = (base_radius.abs(), top_radius.abs());
+        assert!(base_radius > 0.0 || top_radius > 0.0, "cone needs a nonzero radius");
+        Self { base, base_radius, top_radius, height: axis.length(), capped, material, basis }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (r0, r1) = (self.base_radius, self.top_radius);
+        hit_frustum(&self.basis, &self.base, r0, r1, self.height, self.capped, &self.material, ray, t_min, t_max)
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        frustum_box(&self.basis, &self.base, self.base_radius, self.top_radius, self.height)
+    }
+}
+
+/// Implicit surface
+/// `a x² + b y² + c z² + d xy + e xz + f yz + g x + h y + i z + j = 0`,
+/// optionally clipped to a box. Its outside is where the polynomial is
+/// positive. UVs are the longitude and latitude of the normal.
+#[derive(Debug, Clone)]
+pub struct Quadric {
+    pub coefficients: [f64; 10],
+    pub bounds: Option<Aabb>,
+    pub material: Material,
+}
+
+impl Quadric {
+    pub fn new(coefficients: [f64; 10], bounds: Option<Aabb>, material: Material) -> Self {
+        Self { coefficients, bounds, material }
+    }
+
+    /// Value of the quadratic terms alone, `pᵀ M q` with M symmetric.
+    fn quadratic(&self, p: &Vec3, q: &Vec3) -> f64 {
+        let [a, b, c, d, e, f, ..] = self.coefficients;
+        a * p.x() * q.x()
+            + b * p.y() * q.y()
+            + c * p.z() * q.z()
+            + 0.5 * d * (p.x() * q.y() + p.y() * q.x())
+            + 0.5 * e * (p.x() * q.z() + p.z() * q.x())
+            + 0.5 * f * (p.y() * q.z() + p.z() * q.y())
+    }
+
+    fn linear(&self, p: &Vec3) -> f64 {
+        let [.., g, h, i, _] = self.coefficients;
+        g * p.x() + h * p.y() + i * p.z()
+    }
+
+    pub fn gradient(&self, p: &Vec3) -> Vec3 {
+        let [a, b, c, d, e, f, g, h, i, _] = self.coefficients;
+        Vec3::new(
+            2.0 * a * p.x() + d * p.y() + e * p.z() + g,
+            2.0 * b * p.y() + d * p.x() + f * p.z() + h,
+            2.0 * c * p.z() + e * p.x() + f * p.y() + i,
+        )
+    }
+
+    fn contains(&self, p: &Vec3) -> bool {
+        self.bounds
+            .as_ref()
+            .is_none_or(|b| (0..3).all(|i| b.min.data[i] <= p.data[i] && p.data[i] <= b.max.data[i]))
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (o, d) = (&ray.origin, &ray.direction);
+        let a = self.quadratic(d, d);
+        let b = 2.0 * self.quadratic(o, d) + self.linear(d);
+        let c = self.quadratic(o, o) + self.linear(o) + self.coefficients[9];
+        let roots = if a.abs() > 1e-12 * d.length_squared() {
+            solve_quadratic(a, b, c)?
+        } else if b != 0.0 {
+            (-c / b, -c / b)
+        } else {
+            return None;
+        };
+
+        let t = [roots.0, roots.1]
+            .into_iter()
+            .find(|t| t_min <= *t && *t <= t_max && self.contains(&ray.at(*t)))?;
+        let gradient = self.gradient(&ray.at(t));
+        // Singular points such as a cone's apex have no gradient.
+        let outward_normal = if gradient.near_zero() { -ray.direction.unit_vector() } else { gradient.unit_vector() };
+        let (u, v) = Sphere::uv(&outward_normal);
+        Some(record(ray, t, &outward_normal, &self.material).with_uv(u, v))
+    }
+
+    pub fn bounding_box(&self) -> Option<Aabb> {
+        self.bounds.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn white() -> Material {
+        Material::lambertian(Color::new(1.0, 1.0, 1.0))
+    }
+
+    fn close(a: &Vec3, b: &Vec3) -> bool {
+        (a - b).length() < 1e-9
+    }
+
+    #[test]
+    fn test_cylinder() {
+        let open = Cylinder::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), 1.0, false, white());
+        let closed = Cylinder::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 2.0, 0.0), 1.0, true, white());
+
+        let side = Ray::new(Vec3::new(-3.0, 1.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = open.hit(&side, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.0).abs() < 1e-12);
+        assert!(close(&rec.normal, &Vec3::new(-1.0, 0.0, 0.0)));
+        assert!((rec.v - 0.5).abs() < 1e-12);
+
+        // Straight down the axis only the caps can stop the ray.
+        let down = Ray::new(Vec3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(open.hit(&down, 0.001, f64::INFINITY).is_none());
+        let rec = closed.hit(&down, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-12);
+        assert!(close(&rec.normal, &Vec3::new(0.0, 1.0, 0.0)));
+        assert!((rec.v - 0.5).abs() < 1e-12);
+
+        // From inside, rays leave through the side with the normal facing in.
+        let inside = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = closed.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(!rec.front_face);
+        assert!(close(&rec.normal, &Vec3::new(0.0, 0.0, -1.0)));
+
+        // Grazing rays touch the side once, slightly further out they miss.
+        let graze = |x: f64| Ray::new(Vec3::new(x, 1.0, -3.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = open.hit(&graze(1.0), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+        assert!(open.hit(&graze(1.0 + 1e-9), 0.001, f64::INFINITY).is_none());
+    }
+
+    #[test]
+    fn test_cone() {
+        let cone = Cone::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.0, true, white());
+        let side = Ray::new(Vec3::new(-3.0, 0.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = cone.hit(&side, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 2.5).abs() < 1e-12);
+        let slant = Vec3::new(-1.0, 1.0, 0.0).unit_vector();
+        assert!(close(&rec.normal, &slant));
+
+        // Above the apex the other nappe of the double cone is ignored.
+        let above = Ray::new(Vec3::new(-3.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(cone.hit(&above, 0.001, f64::INFINITY).is_none());
+
+        let up = Ray::new(Vec3::new(0.25, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = cone.hit(&up, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12);
+        assert!(close(&rec.normal, &Vec3::new(0.0, -1.0, 0.0)));
+
+        // A frustum seen from inside: out through the smaller cap.
+        let frustum = Cone::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.5, true, white());
+        let inside = Ray::new(Vec3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = frustum.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 0.5).abs() < 1e-12);
+        assert!(!rec.front_face);
+
+        // Grazing the slanted side from outside along its own line.
+        let along = Ray::new(Vec3::new(-2.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 0.0));
+        let open = Cone::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 1.0, 0.0, false, white());
+        let rec = open.hit(&along, 0.001, f64::INFINITY);
+        assert!(rec.is_none_or(|rec| rec.normal.iter().all(|c| c.is_finite())));
+
+        let bounds = frustum.bounding_box();
+        assert!(close(&bounds.min, &Vec3::new(-1.0, 0.0, -1.0)));
+        assert!(close(&bounds.max, &Vec3::new(1.0, 1.0, 1.0)));
+    }
+
+    #[test]
+    fn test_quadric() {
+        // The unit sphere x² + y² + z² - 1 matches the analytic sphere.
+        let sphere = Quadric::new([1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0], None, white());
+        let ray = Ray::new(Vec3::new(0.3, 0.2, -5.0), Vec3::new(0.0, 0.0, 1.0));
+        let rec = sphere.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        let expected = crate::figure::Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 1.0, white());
+        let expected = expected.hit(&ray, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - expected.t).abs() < 1e-12);
+        assert!(close(&rec.normal, &expected.normal));
+        assert!((rec.u - expected.u).abs() < 1e-9 && (rec.v - expected.v).abs() < 1e-9);
+
+        let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = sphere.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 1.0).abs() < 1e-12 && !rec.front_face);
+
+        // Hyperboloid of one sheet x² + z² - y² = 1 clipped to |y| <= 1.
+        let bounds = Aabb::new(Vec3::new(-2.0, -1.0, -2.0), Vec3::new(2.0, 1.0, 2.0));
+        let hyperboloid = Quadric::new([1.0, -1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, -1.0], Some(bounds), white());
+        let waist = Ray::new(Vec3::new(-5.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!((hyperboloid.hit(&waist, 0.001, f64::INFINITY).unwrap().t - 4.0).abs() < 1e-12);
+        let beyond = Ray::new(Vec3::new(-5.0, 1.5, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(hyperboloid.hit(&beyond, 0.001, f64::INFINITY).is_none());
+
+        // Tangent to the waist circle: a double root, not a pair of misses.
+        let graze = Ray::new(Vec3::new(-5.0, 0.0, 1.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = hyperboloid.hit(&graze, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 5.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,1446 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::material::{Material, ConductorPreset, Dielectric, Fresnel};
+use crate::figure::Figure;
+use crate::world::World;
+use crate::camera::Camera;
+use crate::obj::{self, ObjError};
+use crate::background::Background;
+use crate::texture::{Texture, NoiseKind};
+use crate::perlin::Perlin;
+use crate::image::Image;
+use crate::matrix::Matrix4;
+use crate::cuboid::Cuboid;
+use crate::aabb::Aabb;
+use crate::csg::CsgOp;
+use crate::grid::VoxelGrid;
+use crate::medium::GridMedium;
+use crate::environment::Environment;
+use crate::daylight::{self, Daylight};
+use crate::light::Light;
+use crate::integrator::Termination;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/*
+ * Scenes are TOML files:
+ *
+ *   [render]
+ *   width = 400
+ *   height = 225
+ *
+ *   [camera]
+ *   lookfrom = [13.0, 2.0, 3.0]
+ *   lookat = [0.0, 0.0, 0.0]
+ *   up = [0.0, 1.0, 0.0]
+ *   fov = 20.0
+ *
+ *   [textures.checker]
+ *   type = "checker"
+ *   size = 1.0
+ *   even = [0.2, 0.3, 0.1]
+ *   odd = [0.9, 0.9, 0.9]
+ *
+ *   [materials.ground]
+ *   type = "lambertian"
+ *   albedo = "checker"
+ *
+ *   [[figures]]
+ *   type = "sphere"
+ *   center = [0.0, -1000.0, 0.0]
+ *   radius = 1000.0
+ *   material = "ground"
+ *
+ *   [[lights]]
+ *   type = "point"
+ *   position = [0.0, 4.0, 0.0]
+ *   color = [1.0, 1.0, 1.0]
+ *   intensity = 20.0
+ *
+ * An `[environment]` table lights the scene with an equirectangular .hdr
+ * or .exr image instead of the render background, and a `[sky]` table with
+ * a daylight sky and sun.
+ */
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(PathBuf, String),
+    Obj(ObjError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(file, err) => write!(f, "{}: {}", file.display(), err),
+            SceneError::Parse(file, err) => write!(f, "{}: {}", file.display(), err),
+            SceneError::Invalid(file, message) => write!(f, "{}: {}", file.display(), message),
+            SceneError::Obj(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+/// Render settings a scene may set. Anything left out falls back to the
+/// command line or the renderer's defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RenderSettings {
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    pub samples: Option<usize>,
+    pub max_depth: Option<u32>,
+    /// `"depth"` or `"roulette"`.
+    pub termination: Option<Termination>,
+    pub min_bounces: Option<u32>,
+    /// `"sky"`, `"black"` or a color.
+    pub background: Option<Background>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    lookfrom: [f64; 3],
+    lookat: [f64; 3],
+    up: [f64; 3],
+    fov: f64,
+    // Defaults to the image's width / height.
+    aspect: Option<f64>,
+    // Lens diameter; 0 (the default) is a pinhole.
+    aperture: Option<f64>,
+    // Defaults to the distance from lookfrom to lookat.
+    focus_dist: Option<f64>,
+    // Open and close times; closed at time 0 by default.
+    shutter: Option<[f64; 2]>,
+}
+
+/// A color, or the name of an entry in `[textures]`.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum TextureRef {
+    Color([f64; 3]),
+    Name(String),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid { color: [f64; 3] },
+    Checker { #[serde(default = "one")] size: f64, even: TextureRef, odd: TextureRef },
+    Noise {
+        #[serde(default = "one")] scale: f64,
+        #[serde(default)] pattern: NoiseKind,
+        #[serde(default)] seed: u64,
+    },
+    // PNG or PPM, relative to the scene file.
+    Image { path: PathBuf },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian { albedo: TextureRef },
+    Metal { albedo: TextureRef, #[serde(default)] fuzz: f64 },
+    // A named metal, or the complex index of refraction per channel.
+    Conductor {
+        preset: Option<ConductorPreset>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)] roughness: f64,
+    },
+    Dielectric {
+        ior: f64,
+        #[serde(default)] roughness: f64,
+        // "schlick" or "exact".
+        #[serde(default)] fresnel: Fresnel,
+    },
+    DiffuseLight { color: [f64; 3], #[serde(default = "one")] intensity: f64 },
+    // Phase functions, only for media.
+    Isotropic { albedo: TextureRef },
+    HenyeyGreenstein { albedo: TextureRef, g: f64 },
+}
+
+fn yes() -> bool {
+    true
+}
+
+fn up() -> [f64; 3] {
+    [0.0, 1.0, 0.0]
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum FigureDesc {
+    Sphere { center: [f64; 3], radius: f64, material: String },
+    MovingSphere {
+        center0: [f64; 3],
+        center1: [f64; 3],
+        #[serde(default)] time0: f64,
+        #[serde(default = "one")] time1: f64,
+        radius: f64,
+        material: String,
+    },
+    Triangle { vertices: [[f64; 3]; 3], material: String },
+    Plane { point: [f64; 3], normal: [f64; 3], material: String },
+    // A parallelogram with edges `u` and `v` from `corner`.
+    Quad { corner: [f64; 3], u: [f64; 3], v: [f64; 3], material: String },
+    Disk { center: [f64; 3], normal: [f64; 3], radius: f64, material: String },
+    // Either opposite corners `min` and `max`, or `center` and `size`
+    // with optional edge directions for a rotated box.
+    Box {
+        min: Option<[f64; 3]>,
+        max: Option<[f64; 3]>,
+        center: Option<[f64; 3]>,
+        size: Option<[f64; 3]>,
+        x_axis: Option<[f64; 3]>,
+        y_axis: Option<[f64; 3]>,
+        material: String,
+    },
+    Cylinder {
+        base: [f64; 3],
+        top: [f64; 3],
+        radius: f64,
+        #[serde(default = "yes")] capped: bool,
+        material: String,
+    },
+    // A zero radius at either end makes a cone, otherwise a frustum.
+    Cone {
+        base: [f64; 3],
+        top: [f64; 3],
+        base_radius: f64,
+        #[serde(default)] top_radius: f64,
+        #[serde(default = "yes")] capped: bool,
+        material: String,
+    },
+    Torus {
+        center: [f64; 3],
+        #[serde(default = "up")] axis: [f64; 3],
+        major_radius: f64,
+        minor_radius: f64,
+        material: String,
+    },
+    // a x² + b y² + c z² + d xy + e xz + f yz + g x + h y + i z + j = 0,
+    // clipped to the box between `min` and `max` when both are given.
+    Quadric {
+        coefficients: [f64; 10],
+        min: Option<[f64; 3]>,
+        max: Option<[f64; 3]>,
+        material: String,
+    },
+    // `operation` is "union", "intersection" or "difference" (left minus
+    // right) of two closed figures.
+    Csg { operation: CsgOp, left: Box<FigureDesc>, right: Box<FigureDesc> },
+    // Fog filling a closed figure, whose own material is ignored. The
+    // material must be a phase function.
+    Medium { boundary: Box<FigureDesc>, density: f64, material: String },
+    // A voxel grid file stretched over the box from `min` to `max`, its
+    // values times `density` giving the extinction. `emission` and
+    // `temperature` scale the glow of those channels.
+    Grid {
+        path: PathBuf,
+        min: [f64; 3],
+        max: [f64; 3],
+        #[serde(default = "one")] density: f64,
+        material: String,
+        #[serde(default)] emission: f64,
+        #[serde(default)] temperature: f64,
+    },
+    // Materials come from the MTL file unless `material` overrides them.
+    Obj { path: PathBuf, material: Option<String> },
+    // Instances of one OBJ file share its meshes.
+    Instance { transform: Vec<TransformDesc>, figure: Box<FigureDesc> },
+}
+
+/// One step of an instance transform; steps apply in the order listed.
+#[derive(Debug, Deserialize)]
+#[serde(untagged, deny_unknown_fields)]
+enum TransformDesc {
+    Translate { translate: [f64; 3] },
+    // Counter-clockwise around the axis.
+    Rotate { rotate: [f64; 3], degrees: f64 },
+    Scale { scale: ScaleDesc },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes([f64; 3]),
+}
+
+impl TransformDesc {
+    fn matrix(&self) -> Result<Matrix4, String> {
+        match self {
+            TransformDesc::Translate { translate } => Ok(Matrix4::translation(&vec3(translate))),
+            TransformDesc::Rotate { rotate, degrees } => {
+                let axis = vec3(rotate);
+                if axis.near_zero() {
+                    return Err("rotation axis can't be zero".to_string());
+                }
+                Ok(Matrix4::rotation(&axis, *degrees))
+            }
+            TransformDesc::Scale { scale: ScaleDesc::Uniform(s) } => Ok(Matrix4::scaling(&Vec3::new(*s, *s, *s))),
+            TransformDesc::Scale { scale: ScaleDesc::Axes(s) } => Ok(Matrix4::scaling(&vec3(s))),
+        }
+    }
+}
+
+/// An equirectangular map around the scene; `path` is relative to the
+/// scene file and `rotation` in degrees around the up axis.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EnvironmentDesc {
+    path: PathBuf,
+    #[serde(default)]
+    rotation: f64,
+    #[serde(default = "one")]
+    intensity: f64,
+}
+
+/// A daylight sky. The sun is placed either by `elevation` and `azimuth`
+/// (clockwise from north, which is -z) in degrees, or by where and when it
+/// is seen: `latitude`, `longitude`, a `date` ("2024-06-21") and a local
+/// `time` ("14:30") `timezone` hours ahead of UTC.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SkyDesc {
+    #[serde(default = "default_turbidity")]
+    turbidity: f64,
+    #[serde(default = "one")]
+    intensity: f64,
+    elevation: Option<f64>,
+    azimuth: Option<f64>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    date: Option<String>,
+    time: Option<String>,
+    #[serde(default)]
+    timezone: f64,
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+impl SkyDesc {
+    fn build(&self) -> Result<Daylight, String> {
+        if !(2.0..=10.0).contains(&self.turbidity) {
+            return Err("sky turbidity must be between 2 and 10".to_string());
+        }
+        if self.intensity < 0.0 {
+            return Err("sky intensity can't be negative".to_string());
+        }
+        let sun = match (self.elevation, self.azimuth, self.latitude, self.longitude, &self.date, &self.time) {
+            (Some(elevation), Some(azimuth), None, None, None, None) => daylight::direction(elevation, azimuth),
+            (None, None, Some(latitude), Some(longitude), Some(date), Some(time)) => {
+                if !(-90.0..=90.0).contains(&latitude) {
+                    return Err("sky latitude must be between -90 and 90".to_string());
+                }
+                let day = parse_date(date).ok_or_else(|| format!("sky date `{}` is not a YYYY-MM-DD date", date))?;
+                let hours = parse_time(time).ok_or_else(|| format!("sky time `{}` is not a HH:MM[:SS] time", time))?;
+                let (elevation, azimuth) = daylight::sun_position(latitude, longitude, day, hours - self.timezone);
+                daylight::direction(elevation, azimuth)
+            }
+            _ => return Err("sky needs either elevation and azimuth or latitude, longitude, date and time".to_string()),
+        };
+        Ok(Daylight::new(&sun, self.turbidity).with_intensity(self.intensity))
+    }
+}
+
+/// Day of the year of a `YYYY-MM-DD` date.
+fn parse_date(date: &str) -> Option<u32> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    daylight::day_of_year(year, month, day)
+}
+
+/// Hours since midnight of a `HH:MM` or `HH:MM:SS` time.
+fn parse_time(time: &str) -> Option<f64> {
+    let parts = time.trim().split(':').map(|p| p.parse::<u32>().ok()).collect::<Option<Vec<u32>>>()?;
+    let (hours, minutes, seconds) = match parts[..] {
+        [h, m] => (h, m, 0),
+        [h, m, s] => (h, m, s),
+        _ => return None,
+    };
+    (hours < 24 && minutes < 60 && seconds < 60).then(|| hours as f64 + minutes as f64 / 60.0 + seconds as f64 / 3600.0)
+}
+
+/// Lights without a shape. `color` times `intensity` is the radiant
+/// intensity of points and spots, which falls off with the square of the
+/// distance, and the irradiance of directional lights.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum LightDesc {
+    Point {
+        position: [f64; 3],
+        color: [f64; 3],
+        #[serde(default = "one")] intensity: f64,
+    },
+    /// Cone angles are from the axis to the edge, in degrees.
+    Spot {
+        position: [f64; 3],
+        target: [f64; 3],
+        color: [f64; 3],
+        #[serde(default = "one")] intensity: f64,
+        inner_angle: f64,
+        outer_angle: f64,
+    },
+    /// `direction` is the way the light travels.
+    Directional {
+        direction: [f64; 3],
+        color: [f64; 3],
+        #[serde(default = "one")] intensity: f64,
+    },
+}
+
+impl LightDesc {
+    fn build(&self) -> Result<Light, String> {
+        let (LightDesc::Point { color, intensity, .. } | LightDesc::Spot { color, intensity, .. } | LightDesc::Directional { color, intensity, .. }) = self;
+        if *intensity < 0.0 || color.iter().any(|&c| c < 0.0) {
+            return Err("has a negative color or intensity".to_string());
+        }
+        let power = *intensity * vec3(color);
+        match self {
+            LightDesc::Point { position, .. } => Ok(Light::Point { position: vec3(position), intensity: power }),
+            LightDesc::Spot { position, target, inner_angle, outer_angle, .. } => {
+                if !(0.0 <= *inner_angle && inner_angle <= outer_angle && *outer_angle <= 180.0) {
+                    return Err("needs 0 <= inner_angle <= outer_angle <= 180".to_string());
+                }
+                if (vec3(target) - vec3(position)).near_zero() {
+                    return Err("has its target at its position".to_string());
+                }
+                Ok(Light::spot(vec3(position), &vec3(target), power, *inner_angle, *outer_angle))
+            }
+            LightDesc::Directional { direction, .. } => {
+                let direction = vec3(direction);
+                if direction.near_zero() {
+                    return Err("has a zero direction".to_string());
+                }
+                Ok(Light::Directional { direction: direction.unit_vector(), irradiance: power })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    render: RenderSettings,
+    camera: CameraDesc,
+    environment: Option<EnvironmentDesc>,
+    sky: Option<SkyDesc>,
+    #[serde(default)]
+    textures: HashMap<String, TextureDesc>,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    figures: Vec<FigureDesc>,
+    #[serde(default)]
+    lights: Vec<LightDesc>,
+}
+
+pub struct Scene {
+    pub world: World,
+    camera: CameraDesc,
+    pub settings: RenderSettings,
+}
+
+impl Scene {
+    /// The camera depends on the final image size, which the command line
+    /// may override, so it is only built once that is known. The other
+    /// arguments are command line overrides of the scene's lens and shutter.
+    pub fn camera(&self, aspect_ratio: f64, aperture: Option<f64>, focus_dist: Option<f64>, shutter: Option<(f64, f64)>) -> Camera {
+        let c = &self.camera;
+        let (lookfrom, lookat) = (vec3(&c.lookfrom), vec3(&c.lookat));
+        let focus_dist = focus_dist
+            .or(c.focus_dist)
+            .unwrap_or_else(|| (&lookfrom - &lookat).length());
+        let camera = Camera::new(
+            lookfrom,
+            lookat,
+            vec3(&c.up),
+            c.fov,
+            c.aspect.unwrap_or(aspect_ratio),
+            aperture.or(c.aperture).unwrap_or(0.0),
+            focus_dist,
+        );
+        let (open, close) = shutter
+            .or(c.shutter.map(|[open, close]| (open, close)))
+            .unwrap_or((0.0, 0.0));
+        camera.with_shutter(open, close)
+    }
+}
+
+fn vec3(v: &[f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+fn color(v: &[f64; 3]) -> Color {
+    Color::new_color(v[0], v[1], v[2])
+}
+
+/// Builds named textures on first use, so materials and checkers that
+/// share one also share its image or noise generator.
+struct Textures<'a> {
+    descs: &'a HashMap<String, TextureDesc>,
+    built: HashMap<&'a str, Texture>,
+    dir: &'a Path,
+    file: &'a Path,
+}
+
+impl<'a> Textures<'a> {
+    fn resolve(&mut self, texture: &'a TextureRef, user: &str, stack: &mut Vec<&'a str>) -> Result<Texture, SceneError> {
+        let name = match texture {
+            TextureRef::Color(c) => return Ok(Texture::from(color(c))),
+            TextureRef::Name(name) => name.as_str(),
+        };
+        if let Some(texture) = self.built.get(name) {
+            return Ok(texture.clone());
+        }
+        let invalid = |message: String| SceneError::Invalid(self.file.to_path_buf(), message);
+        let (name, desc) = self
+            .descs
+            .get_key_value(name)
+            .ok_or_else(|| invalid(format!("{} references unknown texture `{}`", user, name)))?;
+        if stack.contains(&name.as_str()) {
+            return Err(invalid(format!("texture `{}` contains itself", name)));
+        }
+
+        stack.push(name);
+        let user = format!("texture `{}`", name);
+        let texture = match desc {
+            TextureDesc::Solid { color: c } => Texture::from(color(c)),
+            TextureDesc::Checker { size, even, odd } => {
+                let even = self.resolve(even, &user, stack)?;
+                let odd = self.resolve(odd, &user, stack)?;
+                Texture::checker(*size, even, odd)
+            }
+            TextureDesc::Noise { scale, pattern, seed } => {
+                let perlin = Perlin::new(&mut StdRng::seed_from_u64(*seed));
+                Texture::noise(Arc::new(perlin), *scale, *pattern)
+            }
+            TextureDesc::Image { path } => {
+                let path = self.dir.join(path);
+                let image = Image::load(&path).map_err(|e| SceneError::Io(path, e))?;
+                Texture::image(Arc::new(image))
+            }
+        };
+        stack.pop();
+
+        self.built.insert(name, texture.clone());
+        Ok(texture)
+    }
+}
+
+impl MaterialDesc {
+    fn build<'a>(&'a self, name: &str, textures: &mut Textures<'a>) -> Result<Material, SceneError> {
+        let user = format!("material `{}`", name);
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => Material::lambertian(textures.resolve(albedo, &user, &mut Vec::new())?),
+            MaterialDesc::Metal { albedo, fuzz } => Material::metal(textures.resolve(albedo, &user, &mut Vec::new())?, *fuzz),
+            MaterialDesc::Conductor { preset, eta, k, roughness } => {
+                let invalid = |message: &str| SceneError::Invalid(textures.file.to_path_buf(), format!("{} {}", user, message));
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err(invalid("needs a roughness between 0 and 1"));
+                }
+                match (preset, eta, k) {
+                    (Some(preset), None, None) => Material::conductor_preset(*preset, *roughness),
+                    (None, Some(eta), Some(k)) => Material::conductor(color(eta), color(k), *roughness),
+                    _ => return Err(invalid("needs either a preset or both eta and k")),
+                }
+            }
+            MaterialDesc::Dielectric { ior, roughness, fresnel } => {
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err(SceneError::Invalid(textures.file.to_path_buf(), format!("{} needs a roughness between 0 and 1", user)));
+                }
+                match fresnel {
+                    Fresnel::Schlick => Material::rough_dielectric(*ior, *roughness),
+                    fresnel => Material::Dielectric(Dielectric::new(*ior).with_roughness(*roughness).with_fresnel(*fresnel)),
+                }
+            }
+            MaterialDesc::DiffuseLight { color: c, intensity } => Material::diffuse_light(color(c), *intensity),
+            MaterialDesc::Isotropic { albedo } => Material::isotropic(textures.resolve(albedo, &user, &mut Vec::new())?),
+            MaterialDesc::HenyeyGreenstein { albedo, g } => {
+                if g.abs() >= 1.0 {
+                    return Err(SceneError::Invalid(textures.file.to_path_buf(), format!("{} needs g between -1 and 1", user)));
+                }
+                Material::henyey_greenstein(textures.resolve(albedo, &user, &mut Vec::new())?, *g)
+            }
+        })
+    }
+}
+
+struct Figures<'a> {
+    file: &'a Path,
+    dir: &'a Path,
+    materials: HashMap<&'a str, Material>,
+    // OBJ files loaded for instances, by path and material override.
+    shared: HashMap<(PathBuf, Option<String>), Vec<Arc<Figure>>>,
+}
+
+impl Figures<'_> {
+    fn invalid(&self, message: String) -> SceneError {
+        SceneError::Invalid(self.file.to_path_buf(), message)
+    }
+
+    fn lookup(&self, index: usize, name: &str) -> Result<Material, SceneError> {
+        self.materials
+            .get(name)
+            .cloned()
+            .ok_or_else(|| self.invalid(format!("figure {} references unknown material `{}`", index + 1, name)))
+    }
+
+    fn direction(&self, i: usize, name: &str, v: &[f64; 3]) -> Result<Vec3, SceneError> {
+        let v = vec3(v);
+        if v.near_zero() {
+            return Err(self.invalid(format!("figure {} has a zero {}", i + 1, name)));
+        }
+        Ok(v)
+    }
+
+    /// Builds a figure that must not expand to several, such as the
+    /// operands of CSG.
+    fn single(&mut self, figure: &FigureDesc, i: usize) -> Result<Figure, SceneError> {
+        let mut figures = self.build(figure, i)?;
+        match figures.len() {
+            1 => Ok(figures.pop().unwrap()),
+            _ => Err(self.invalid(format!("figure {} contains something that isn't a single figure", i + 1))),
+        }
+    }
+
+    /// `i` is the position in the scene's figure list, for errors.
+    fn build(&mut self, figure: &FigureDesc, i: usize) -> Result<Vec<Figure>, SceneError> {
+        Ok(match figure {
+            FigureDesc::Sphere { center, radius, material } => {
+                vec![Figure::sphere(vec3(center), *radius, self.lookup(i, material)?)]
+            }
+            FigureDesc::MovingSphere { center0, center1, time0, time1, radius, material } => {
+                if time1 <= time0 {
+                    return Err(self.invalid(format!("figure {} needs time1 to be after time0", i + 1)));
+                }
+                let material = self.lookup(i, material)?;
+                vec![Figure::moving_sphere(vec3(center0), vec3(center1), *time0, *time1, *radius, material)]
+            }
+            FigureDesc::Triangle { vertices, material } => {
+                let [v0, v1, v2] = vertices;
+                vec![Figure::triangle(vec3(v0), vec3(v1), vec3(v2), self.lookup(i, material)?)]
+            }
+            FigureDesc::Plane { point, normal, material } => {
+                let normal = self.direction(i, "normal", normal)?;
+                vec![Figure::plane(vec3(point), normal, self.lookup(i, material)?)]
+            }
+            FigureDesc::Quad { corner, u, v, material } => {
+                let (u, v) = (vec3(u), vec3(v));
+                if u.cross(&v).near_zero() {
+                    return Err(self.invalid(format!("figure {} has parallel quad edges", i + 1)));
+                }
+                vec![Figure::quad(vec3(corner), u, v, self.lookup(i, material)?)]
+            }
+            FigureDesc::Disk { center, normal, radius, material } => {
+                let normal = self.direction(i, "normal", normal)?;
+                vec![Figure::disk(vec3(center), normal, *radius, self.lookup(i, material)?)]
+            }
+            FigureDesc::Box { min, max, center, size, x_axis, y_axis, material } => {
+                let material = self.lookup(i, material)?;
+                match (min, max, center, size) {
+                    (Some(min), Some(max), None, None) if x_axis.is_none() && y_axis.is_none() => {
+                        vec![Figure::cuboid(vec3(min), vec3(max), material)]
+                    }
+                    (None, None, Some(center), Some(size)) => {
+                        let x = self.direction(i, "x_axis", x_axis.as_ref().unwrap_or(&[1.0, 0.0, 0.0]))?;
+                        let y = self.direction(i, "y_axis", y_axis.as_ref().unwrap_or(&[0.0, 1.0, 0.0]))?;
+                        if x.cross(&y).near_zero() {
+                            return Err(self.invalid(format!("figure {} has parallel box axes", i + 1)));
+                        }
+                        let half = size.map(|s| 0.5 * s);
+                        vec![Figure::Box(Cuboid::oriented(vec3(center), half, &x, &y, material))]
+                    }
+                    _ => {
+                        return Err(self.invalid(format!(
+                            "figure {} needs either min and max, or center and size with optional axes",
+                            i + 1
+                        )))
+                    }
+                }
+            }
+            FigureDesc::Cylinder { base, top, radius, capped, material } => {
+                if base == top {
+                    return Err(self.invalid(format!("figure {} has the same base and top", i + 1)));
+                }
+                vec![Figure::cylinder(vec3(base), vec3(top), *radius, *capped, self.lookup(i, material)?)]
+            }
+            FigureDesc::Cone { base, top, base_radius, top_radius, capped, material } => {
+                if base == top {
+                    return Err(self.invalid(format!("figure {} has the same base and top", i + 1)));
+                }
+                if *base_radius == 0.0 && *top_radius == 0.0 {
+                    return Err(self.invalid(format!("figure {} needs a nonzero cone radius", i + 1)));
+                }
+                let material = self.lookup(i, material)?;
+                vec![Figure::cone(vec3(base), vec3(top), *base_radius, *top_radius, *capped, material)]
+            }
+            FigureDesc::Torus { center, axis, major_radius, minor_radius, material } => {
+                let axis = self.direction(i, "axis", axis)?;
+                vec![Figure::torus(vec3(center), axis, *major_radius, *minor_radius, self.lookup(i, material)?)]
+            }
+            FigureDesc::Quadric { coefficients, min, max, material } => {
+                let bounds = match (min, max) {
+                    (Some(min), Some(max)) => Some(Aabb::new(vec3(min), vec3(min)).include(&vec3(max))),
+                    (None, None) => None,
+                    _ => return Err(self.invalid(format!("figure {} needs both min and max to clip a quadric", i + 1))),
+                };
+                vec![Figure::quadric(*coefficients, bounds, self.lookup(i, material)?)]
+            }
+            FigureDesc::Csg { operation, left, right } => {
+                let left = self.single(left, i)?;
+                let right = self.single(right, i)?;
+                vec![Figure::csg(*operation, left, right)]
+            }
+            FigureDesc::Medium { boundary, density, material } => {
+                if *density <= 0.0 {
+                    return Err(self.invalid(format!("figure {} needs a positive density", i + 1)));
+                }
+                let phase = self.lookup(i, material)?;
+                if !matches!(phase, Material::Isotropic(_) | Material::HenyeyGreenstein(_)) {
+                    return Err(self.invalid(format!("figure {} needs an isotropic or henyey_greenstein material", i + 1)));
+                }
+                vec![Figure::constant_medium(self.single(boundary, i)?, *density, phase)]
+            }
+            FigureDesc::Grid { path, min, max, density, material, emission, temperature } => {
+                if (0..3).any(|axis| min[axis] >= max[axis]) {
+                    return Err(self.invalid(format!("figure {} needs min below max on every axis", i + 1)));
+                }
+                if *density < 0.0 || *emission < 0.0 || *temperature < 0.0 {
+                    return Err(self.invalid(format!("figure {} can't have negative scales", i + 1)));
+                }
+                let phase = self.lookup(i, material)?;
+                if !matches!(phase, Material::Isotropic(_) | Material::HenyeyGreenstein(_)) {
+                    return Err(self.invalid(format!("figure {} needs an isotropic or henyey_greenstein material", i + 1)));
+                }
+                let path = self.dir.join(path);
+                let grid = VoxelGrid::load(&path).map_err(|e| SceneError::Io(path, e))?;
+                let medium = GridMedium::new(vec3(min), vec3(max), Arc::new(grid), *density, phase)
+                    .with_emission(*emission)
+                    .with_temperature(*temperature);
+                vec![Figure::Grid(medium)]
+            }
+            FigureDesc::Obj { path, material } => {
+                let over = material.as_ref().map(|m| self.lookup(i, m)).transpose()?;
+                let mut figures = obj::load_obj(self.dir.join(path)).map_err(SceneError::Obj)?;
+                for figure in figures.iter_mut() {
+                    if let (Figure::Mesh(mesh), Some(m)) = (figure, &over) {
+                        mesh.material = m.clone();
+                    }
+                }
+                figures
+            }
+            FigureDesc::Instance { transform, figure } => {
+                let mut matrix = Matrix4::identity();
+                for step in transform {
+                    let step = step.matrix().map_err(|e| self.invalid(format!("figure {}: {}", i + 1, e)))?;
+                    matrix = step * matrix;
+                }
+                if matrix.inverse().is_none() {
+                    return Err(self.invalid(format!("figure {} has a transform that can't be inverted", i + 1)));
+                }
+
+                let shared = match figure.as_ref() {
+                    FigureDesc::Obj { path, material } => {
+                        let key = (path.clone(), material.clone());
+                        match self.shared.get(&key) {
+                            Some(shared) => shared.clone(),
+                            None => {
+                                let shared: Vec<Arc<Figure>> = self.build(figure, i)?.into_iter().map(Arc::new).collect();
+                                self.shared.insert(key, shared.clone());
+                                shared
+                            }
+                        }
+                    }
+                    _ => self.build(figure, i)?.into_iter().map(Arc::new).collect(),
+                };
+                shared.into_iter().map(|object| Figure::instance(object, matrix.clone())).collect()
+            }
+        })
+    }
+}
+
+pub fn parse_scene(source: &str, file: &Path) -> Result<Scene, SceneError> {
+    let desc: SceneDesc = toml::from_str(source).map_err(|e| SceneError::Parse(file.to_path_buf(), e))?;
+    if desc.camera.aperture.is_some_and(|a| a < 0.0) {
+        return Err(SceneError::Invalid(file.to_path_buf(), "camera aperture can't be negative".to_string()));
+    }
+    if desc.camera.focus_dist.is_some_and(|d| d <= 0.0) {
+        return Err(SceneError::Invalid(file.to_path_buf(), "camera focus_dist must be positive".to_string()));
+    }
+    if desc.camera.shutter.is_some_and(|[open, close]| close < open) {
+        return Err(SceneError::Invalid(file.to_path_buf(), "camera shutter closes before it opens".to_string()));
+    }
+    let dir = file.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut settings = desc.render;
+    let backgrounds = [settings.background.is_some(), desc.environment.is_some(), desc.sky.is_some()];
+    if backgrounds.iter().filter(|&&b| b).count() > 1 {
+        return Err(SceneError::Invalid(file.to_path_buf(), "set only one of a render background, an environment or a sky".to_string()));
+    }
+    if let Some(sky) = &desc.sky {
+        let daylight = sky.build().map_err(|e| SceneError::Invalid(file.to_path_buf(), e))?;
+        settings.background = Some(Background::Daylight(daylight));
+    }
+    if let Some(environment) = &desc.environment {
+        if environment.intensity < 0.0 {
+            return Err(SceneError::Invalid(file.to_path_buf(), "environment intensity can't be negative".to_string()));
+        }
+        let path = dir.join(&environment.path);
+        let image = Image::load(&path).map_err(|e| SceneError::Io(path, e))?;
+        let environment = Environment::new(image, environment.rotation, environment.intensity);
+        settings.background = Some(Background::Environment(Arc::new(environment)));
+    }
+
+    let mut textures = Textures { descs: &desc.textures, built: HashMap::new(), dir, file };
+    let materials: HashMap<&str, Material> = desc
+        .materials
+        .iter()
+        .map(|(name, m)| Ok((name.as_str(), m.build(name, &mut textures)?)))
+        .collect::<Result<_, SceneError>>()?;
+
+    let mut figures = Figures { file, dir, materials, shared: HashMap::new() };
+    let mut world = World::new();
+    for (i, figure) in desc.figures.iter().enumerate() {
+        for figure in figures.build(figure, i)? {
+            world.add(figure);
+        }
+    }
+    for (i, light) in desc.lights.iter().enumerate() {
+        let light = light.build().map_err(|e| SceneError::Invalid(file.to_path_buf(), format!("light {} {}", i + 1, e)))?;
+        world.add_light(light);
+    }
+
+    Ok(Scene { world, camera: desc.camera, settings })
+}
+
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = std::fs::read_to_string(path).map_err(|e| SceneError::Io(path.to_path_buf(), e))?;
+    parse_scene(&source, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "
+[camera]
+lookfrom = [0.0, 0.0, 0.0]
+lookat = [0.0, 0.0, -1.0]
+up = [0.0, 1.0, 0.0]
+fov = 90.0
+";
+
+    fn parse(body: &str) -> Result<Scene, SceneError> {
+        parse_scene(&format!("{}{}", CAMERA, body), Path::new("test.toml"))
+    }
+
+    /// Directory for the files a scene refers to, private to one test and
+    /// one run, and removed when dropped so a failing test cleans up too.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("rust-ray-tracer-{}-{}", test, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+
+        fn join(&self, name: &str) -> PathBuf {
+            self.0.join(name)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_parse_scene() {
+        let scene = parse(
+            "
+[render]
+width = 64
+samples = 4
+max_depth = 500
+termination = \"roulette\"
+min_bounces = 5
+
+[materials.red]
+type = \"lambertian\"
+albedo = [1.0, 0.0, 0.0]
+
+[materials.chrome]
+type = \"metal\"
+albedo = [0.8, 0.8, 0.8]
+
+[[figures]]
+type = \"sphere\"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = \"red\"
+
+[[figures]]
+type = \"triangle\"
+vertices = [[0.0, 0.0, -2.0], [1.0, 0.0, -2.0], [0.0, 1.0, -2.0]]
+material = \"chrome\"
+",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.settings.width, Some(64));
+        assert_eq!(scene.settings.height, None);
+        assert_eq!(scene.settings.background, None);
+        assert_eq!(scene.settings.max_depth, Some(500));
+        assert_eq!(scene.settings.termination, Some(Termination::Roulette));
+        assert_eq!(scene.settings.min_bounces, Some(5));
+    }
+
+    #[test]
+    fn test_background() {
+        let scene = parse("[render]\nbackground = \"black\"\n").unwrap();
+        assert_eq!(scene.settings.background, Some(Background::Constant(Color::new(0.0, 0.0, 0.0))));
+        let scene = parse("[render]\nbackground = [0.1, 0.2, 0.3]\n").unwrap();
+        assert_eq!(scene.settings.background, Some(Background::Constant(Color::new(0.1, 0.2, 0.3))));
+        assert!(parse("[render]\nbackground = \"dusk\"\n").is_err());
+    }
+
+    #[test]
+    fn test_unknown_key() {
+        let err = parse("[render]\nwidht = 10\n").err().unwrap();
+        assert!(matches!(err, SceneError::Parse(..)));
+        assert!(err.to_string().contains("widht"));
+
+        let err = parse("[materials.a]\ntype = \"lambertian\"\nalbedo = [1.0, 1.0, 1.0]\nfuzz = 0.1\n").err().unwrap();
+        assert!(err.to_string().contains("fuzz"));
+    }
+
+    #[test]
+    fn test_wrong_type() {
+        let err = parse("[render]\nwidth = \"wide\"\n").err().unwrap();
+        assert!(matches!(err, SceneError::Parse(..)));
+        assert!(err.to_string().contains("invalid type"));
+    }
+
+    #[test]
+    fn test_dangling_material() {
+        let err = parse("[[figures]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"nope\"\n")
+            .err()
+            .unwrap();
+        assert!(matches!(err, SceneError::Invalid(..)));
+        assert!(err.to_string().contains("`nope`"));
+    }
+
+    #[test]
+    fn test_lens() {
+        let lens = "[camera]\nlookfrom = [0.0, 0.0, 0.0]\nlookat = [0.0, 0.0, -2.0]\nup = [0.0, 1.0, 0.0]\nfov = 90.0\n";
+        let scene = parse_scene(&format!("{}aperture = 0.5\n", lens), Path::new("test.toml")).unwrap();
+        let camera = scene.camera(1.0, None, None, None);
+        assert_eq!(camera.lens_radius, 0.25);
+        assert_eq!(camera.focus_dist, 2.0);
+        let camera = scene.camera(1.0, Some(0.0), Some(3.0), None);
+        assert_eq!((camera.lens_radius, camera.focus_dist), (0.0, 3.0));
+
+        assert!(parse_scene(&format!("{}aperture = -1.0\n", lens), Path::new("test.toml")).is_err());
+        assert!(parse_scene(&format!("{}focus_dist = 0.0\n", lens), Path::new("test.toml")).is_err());
+    }
+
+    #[test]
+    fn test_motion() {
+        let scene = parse(
+            "shutter = [0.0, 0.5]
+
+[materials.red]
+type = \"lambertian\"
+albedo = [1.0, 0.0, 0.0]
+
+[[figures]]
+type = \"moving_sphere\"
+center0 = [0.0, 0.0, -1.0]
+center1 = [0.0, 1.0, -1.0]
+radius = 0.5
+material = \"red\"
+",
+        )
+        .unwrap();
+        let camera = scene.camera(1.0, None, None, None);
+        assert_eq!((camera.time0, camera.time1), (0.0, 0.5));
+        let camera = scene.camera(1.0, None, None, Some((0.25, 0.25)));
+        assert_eq!((camera.time0, camera.time1), (0.25, 0.25));
+        match &scene.world.objects[0] {
+            Figure::MovingSphere(sphere) => assert_eq!((sphere.time0, sphere.time1), (0.0, 1.0)),
+            _ => panic!("expected a moving sphere"),
+        }
+
+        let err = parse(
+            "[[figures]]\ntype = \"moving_sphere\"\ncenter0 = [0.0, 0.0, 0.0]\ncenter1 = [1.0, 0.0, 0.0]\n\
+             time0 = 1.0\ntime1 = 1.0\nradius = 1.0\nmaterial = \"red\"\n",
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("time1 to be after time0"));
+        assert!(parse("shutter = [1.0, 0.0]\n").is_err());
+    }
+
+    #[test]
+    fn test_primitives() {
+        let scene = parse(
+            "
+[materials.white]
+type = \"lambertian\"
+albedo = [1.0, 1.0, 1.0]
+
+[[figures]]
+type = \"plane\"
+point = [0.0, -1.0, 0.0]
+normal = [0.0, 1.0, 0.0]
+material = \"white\"
+
+[[figures]]
+type = \"quad\"
+corner = [0.0, 0.0, -2.0]
+u = [1.0, 0.0, 0.0]
+v = [0.0, 1.0, 0.0]
+material = \"white\"
+
+[[figures]]
+type = \"disk\"
+center = [0.0, 0.0, -3.0]
+normal = [0.0, 0.0, 1.0]
+radius = 0.5
+material = \"white\"
+
+[[figures]]
+type = \"box\"
+min = [0.0, 0.0, 0.0]
+max = [1.0, 1.0, 1.0]
+material = \"white\"
+
+[[figures]]
+type = \"box\"
+center = [0.0, 0.0, 0.0]
+size = [1.0, 2.0, 3.0]
+x_axis = [1.0, 0.0, 1.0]
+material = \"white\"
+",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 5);
+        assert!(scene.world.objects[0].bounding_box().is_none());
+
+        let both = "[[figures]]\ntype = \"box\"\nmin = [0.0, 0.0, 0.0]\nmax = [1.0, 1.0, 1.0]\n\
+                    center = [0.0, 0.0, 0.0]\nmaterial = \"white\"\n[materials.white]\ntype = \"lambertian\"\n\
+                    albedo = [1.0, 1.0, 1.0]\n";
+        assert!(parse(both).err().unwrap().to_string().contains("needs either"));
+        let flat = "[[figures]]\ntype = \"plane\"\npoint = [0.0, 0.0, 0.0]\nnormal = [0.0, 0.0, 0.0]\n\
+                    material = \"white\"\n[materials.white]\ntype = \"lambertian\"\nalbedo = [1.0, 1.0, 1.0]\n";
+        assert!(parse(flat).err().unwrap().to_string().contains("zero normal"));
+    }
+
+    #[test]
+    fn test_curved_primitives() {
+        let scene = parse(
+            "
+[materials.white]
+type = \"lambertian\"
+albedo = [1.0, 1.0, 1.0]
+
+[[figures]]
+type = \"cylinder\"
+base = [0.0, 0.0, 0.0]
+top = [0.0, 1.0, 0.0]
+radius = 0.5
+capped = false
+material = \"white\"
+
+[[figures]]
+type = \"cone\"
+base = [2.0, 0.0, 0.0]
+top = [2.0, 1.0, 0.0]
+base_radius = 0.5
+material = \"white\"
+
+[[figures]]
+type = \"torus\"
+center = [0.0, 0.0, 3.0]
+major_radius = 1.0
+minor_radius = 0.25
+material = \"white\"
+
+[[figures]]
+type = \"quadric\"
+coefficients = [1.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, -1.0, 0.0, 0.0]
+min = [-1.0, 0.0, -1.0]
+max = [1.0, 1.0, 1.0]
+material = \"white\"
+",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        assert!(matches!(&scene.world.objects[0], Figure::Cylinder(c) if !c.capped));
+        assert!(matches!(&scene.world.objects[1], Figure::Cone(c) if c.capped && c.top_radius == 0.0));
+        assert!(scene.world.objects[3].bounding_box().is_some());
+
+        let white = "[materials.white]\ntype = \"lambertian\"\nalbedo = [1.0, 1.0, 1.0]\n";
+        let flat = format!("{}[[figures]]\ntype = \"cylinder\"\nbase = [0.0, 0.0, 0.0]\ntop = [0.0, 0.0, 0.0]\n\
+                            radius = 1.0\nmaterial = \"white\"\n", white);
+        assert!(parse(&flat).err().unwrap().to_string().contains("same base and top"));
+        let half = format!("{}[[figures]]\ntype = \"quadric\"\ncoefficients = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0]\n\
+                            min = [0.0, 0.0, 0.0]\nmaterial = \"white\"\n", white);
+        assert!(parse(&half).err().unwrap().to_string().contains("both min and max"));
+    }
+
+    #[test]
+    fn test_csg() {
+        let white = "[materials.white]\ntype = \"lambertian\"\nalbedo = [1.0, 1.0, 1.0]\n";
+        let scene = parse(&format!(
+            "{}
+[[figures]]
+type = \"csg\"
+operation = \"difference\"
+left = {{ type = \"box\", min = [-1.0, -1.0, -1.0], max = [1.0, 1.0, 1.0], material = \"white\" }}
+
+[figures.right]
+type = \"csg\"
+operation = \"union\"
+left = {{ type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.3, material = \"white\" }}
+right = {{ type = \"cylinder\", base = [0.0, -2.0, 0.0], top = [0.0, 2.0, 0.0], radius = 0.5, material = \"white\" }}
+",
+            white
+        ))
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 1);
+        match &scene.world.objects[0] {
+            Figure::Csg(csg) => {
+                assert_eq!(csg.op, CsgOp::Difference);
+                assert!(matches!(csg.right.as_ref(), Figure::Csg(inner) if inner.op == CsgOp::Union));
+            }
+            _ => panic!("expected a csg figure"),
+        }
+
+        let bad = format!(
+            "{}[[figures]]\ntype = \"csg\"\noperation = \"xor\"\n\
+             left = {{ type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"white\" }}\n\
+             right = {{ type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"white\" }}\n",
+            white
+        );
+        assert!(parse(&bad).is_err());
+    }
+
+    #[test]
+    fn test_media() {
+        let scene = parse(
+            "
+[materials.smoke]
+type = \"henyey_greenstein\"
+albedo = [0.8, 0.8, 0.8]
+g = 0.6
+
+[materials.glass]
+type = \"dielectric\"
+ior = 1.5
+
+[[figures]]
+type = \"medium\"
+density = 0.5
+material = \"smoke\"
+boundary = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"glass\" }
+",
+        )
+        .unwrap();
+        assert!(matches!(&scene.world.objects[0], Figure::Medium(m) if m.density == 0.5));
+
+        let medium = "[[figures]]\ntype = \"medium\"\ndensity = 1.0\nmaterial = \"glass\"\n\
+                      boundary = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"glass\" }\n\
+                      [materials.glass]\ntype = \"dielectric\"\nior = 1.5\n";
+        assert!(parse(medium).err().unwrap().to_string().contains("isotropic or henyey_greenstein"));
+        let g = "[materials.fog]\ntype = \"henyey_greenstein\"\nalbedo = [1.0, 1.0, 1.0]\ng = 1.0\n";
+        assert!(parse(g).err().unwrap().to_string().contains("between -1 and 1"));
+    }
+
+    #[test]
+    fn test_conductor() {
+        let scene = parse(
+            "
+[materials.gold]
+type = \"conductor\"
+preset = \"gold\"
+roughness = 0.3
+
+[materials.custom]
+type = \"conductor\"
+eta = [0.2, 0.9, 1.1]
+k = [3.9, 2.4, 2.1]
+
+[[figures]]
+type = \"sphere\"
+center = [0.0, 0.0, 0.0]
+radius = 1.0
+material = \"gold\"
+
+[[figures]]
+type = \"sphere\"
+center = [2.0, 0.0, 0.0]
+radius = 1.0
+material = \"custom\"
+",
+        )
+        .unwrap();
+        match &scene.world.objects[0] {
+            Figure::Sphere(s) => assert!(matches!(&s.material, Material::Conductor(c) if c.roughness == 0.3)),
+            _ => panic!("expected a sphere"),
+        }
+
+        let both = "[materials.m]\ntype = \"conductor\"\npreset = \"silver\"\neta = [1.0, 1.0, 1.0]\n";
+        assert!(parse(both).err().unwrap().to_string().contains("either a preset or both eta and k"));
+        let rough = "[materials.m]\ntype = \"conductor\"\npreset = \"aluminum\"\nroughness = 2.0\n";
+        assert!(parse(rough).err().unwrap().to_string().contains("roughness between 0 and 1"));
+
+        let frosted = "[materials.m]\ntype = \"dielectric\"\nior = 1.5\nroughness = 0.2\n\
+                       [[figures]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\nmaterial = \"m\"\n";
+        match &parse(frosted).unwrap().world.objects[0] {
+            Figure::Sphere(s) => assert!(matches!(&s.material, Material::Dielectric(d) if d.roughness == 0.2 && d.fresnel == Fresnel::Schlick)),
+            _ => panic!("expected a sphere"),
+        }
+        assert!(parse(&frosted.replace("0.2", "-0.1")).is_err());
+        // Glass uses Schlick unless told otherwise.
+        for (fresnel, expected) in [("", Fresnel::Schlick), ("fresnel = \"exact\"", Fresnel::Exact)] {
+            match &parse(&frosted.replace("roughness = 0.2", fresnel)).unwrap().world.objects[0] {
+                Figure::Sphere(s) => assert!(matches!(&s.material, Material::Dielectric(d) if d.fresnel == expected)),
+                _ => panic!("expected a sphere"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid() {
+        let dir = TempDir::new("grid");
+        std::fs::write(dir.join("puff.vgrid"), "grid 2 1 1 temperature\n0 300\n1 1800\n").unwrap();
+        let source = format!(
+            "{}
+[materials.smoke]
+type = \"isotropic\"
+albedo = [0.5, 0.5, 0.5]
+
+[[figures]]
+type = \"grid\"
+path = \"puff.vgrid\"
+min = [0.0, 0.0, 0.0]
+max = [2.0, 1.0, 1.0]
+density = 3.0
+temperature = 0.5
+material = \"smoke\"
+",
+            CAMERA
+        );
+        let scene = parse_scene(&source, &dir.join("scene.toml")).unwrap();
+        match &scene.world.objects[0] {
+            Figure::Grid(grid) => {
+                assert_eq!(grid.density_scale, 3.0);
+                assert_eq!(grid.grid.size, [2, 1, 1]);
+                assert!(matches!(&grid.material, Material::Volume(v) if v.temperature_scale == 0.5));
+            }
+            _ => panic!("expected a grid medium"),
+        }
+
+        let missing = source.replace("puff.vgrid", "gone.vgrid");
+        assert!(parse_scene(&missing, &dir.join("scene.toml")).is_err());
+    }
+
+    #[test]
+    fn test_environment() {
+        let dir = TempDir::new("environment");
+        let image = Image::new(2, 1, vec![Color::new(1.0, 2.0, 4.0), Color::new(0.5, 0.5, 0.5)]);
+        crate::output::write_image(&dir.join("sky.hdr"), &image, crate::output::Format::Hdr).unwrap();
+        let source = format!("{}\n[environment]\npath = \"sky.hdr\"\nrotation = 90.0\nintensity = 2.0\n", CAMERA);
+        let scene = parse_scene(&source, &dir.join("scene.toml")).unwrap();
+        match &scene.settings.background {
+            // Turned a quarter, the left half of the map is towards +z.
+            Some(Background::Environment(environment)) => {
+                let radiance = environment.radiance(&Vec3::new(0.0, 0.0, 1.0));
+                assert!((radiance - Color::new(2.0, 4.0, 8.0)).length() < 0.1);
+            }
+            other => panic!("expected an environment, got {:?}", other),
+        }
+
+        let both = format!("{}\n[render]\nbackground = \"black\"\n", source);
+        assert!(matches!(parse_scene(&both, &dir.join("scene.toml")), Err(SceneError::Invalid(..))));
+        let missing = source.replace("sky.hdr", "gone.hdr");
+        assert!(matches!(parse_scene(&missing, &dir.join("scene.toml")), Err(SceneError::Io(..))));
+    }
+
+    #[test]
+    fn test_sky() {
+        let scene = parse("[sky]\nelevation = 30.0\nazimuth = 90.0\nturbidity = 4.0\n").unwrap();
+        let expected = Daylight::new(&daylight::direction(30.0, 90.0), 4.0);
+        assert_eq!(scene.settings.background, Some(Background::Daylight(expected)));
+
+        // Greenwich at noon in summer time, with the sun due south.
+        let scene = parse("[sky]\nlatitude = 51.48\nlongitude = 0.0\ndate = \"2024-06-20\"\ntime = \"13:00\"\ntimezone = 1.0\n").unwrap();
+        let (elevation, azimuth) = daylight::sun_position(51.48, 0.0, 172, 12.0);
+        let expected = Daylight::new(&daylight::direction(elevation, azimuth), 3.0);
+        assert_eq!(scene.settings.background, Some(Background::Daylight(expected)));
+
+        for invalid in [
+            "[sky]\nelevation = 30.0\n",
+            "[sky]\nelevation = 30.0\nazimuth = 90.0\nlatitude = 10.0\n",
+            "[sky]\nelevation = 30.0\nazimuth = 90.0\nturbidity = 1.0\n",
+            "[sky]\nlatitude = 51.48\nlongitude = 0.0\ndate = \"2023-02-29\"\ntime = \"12:00\"\n",
+            "[sky]\nlatitude = 51.48\nlongitude = 0.0\ndate = \"2023-06-20\"\ntime = \"noon\"\n",
+            "[render]\nbackground = \"black\"\n[sky]\nelevation = 30.0\nazimuth = 90.0\n",
+        ] {
+            assert!(matches!(parse(invalid), Err(SceneError::Invalid(..))), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn test_lights() {
+        let scene = parse(
+            "
+[[lights]]
+type = \"point\"
+position = [0.0, 2.0, 0.0]
+color = [1.0, 0.5, 0.25]
+intensity = 4.0
+
+[[lights]]
+type = \"spot\"
+position = [0.0, 3.0, 0.0]
+target = [0.0, 0.0, 0.0]
+color = [1.0, 1.0, 1.0]
+inner_angle = 15.0
+outer_angle = 30.0
+
+[[lights]]
+type = \"directional\"
+direction = [0.0, -2.0, 0.0]
+color = [1.0, 1.0, 1.0]
+",
+        )
+        .unwrap();
+        let lights = scene.world.lights();
+        assert_eq!(lights.len(), 3);
+        assert!(matches!(&lights[0], Light::Point { intensity, .. } if *intensity == Color::new(4.0, 2.0, 1.0)));
+        assert!(matches!(&lights[1], Light::Spot { direction, .. } if *direction == Vec3::new(0.0, -1.0, 0.0)));
+        assert!(matches!(&lights[2], Light::Directional { direction, .. } if *direction == Vec3::new(0.0, -1.0, 0.0)));
+
+        for invalid in [
+            "[[lights]]\ntype = \"spot\"\nposition = [0.0, 3.0, 0.0]\ntarget = [0.0, 0.0, 0.0]\ncolor = [1.0, 1.0, 1.0]\ninner_angle = 40.0\nouter_angle = 30.0\n",
+            "[[lights]]\ntype = \"directional\"\ndirection = [0.0, 0.0, 0.0]\ncolor = [1.0, 1.0, 1.0]\n",
+            "[[lights]]\ntype = \"point\"\nposition = [0.0, 0.0, 0.0]\ncolor = [1.0, 1.0, 1.0]\nintensity = -1.0\n",
+        ] {
+            assert!(matches!(parse(invalid), Err(SceneError::Invalid(..))), "{}", invalid);
+        }
+        assert!(matches!(parse("[[lights]]\ntype = \"area\"\n"), Err(SceneError::Parse(..))));
+    }
+
+    #[test]
+    fn test_instances() {
+        let dir = TempDir::new("instances");
+        std::fs::write(dir.join("tri.obj"), "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n").unwrap();
+        let source = format!(
+            "{}
+[materials.white]
+type = \"lambertian\"
+albedo = [1.0, 1.0, 1.0]
+
+[[figures]]
+type = \"instance\"
+transform = [{{ scale = [2.0, 1.0, 1.0] }}, {{ rotate = [0.0, 1.0, 0.0], degrees = 90.0 }}, {{ translate = [0.0, 0.0, -3.0] }}]
+figure = {{ type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"white\" }}
+
+[[figures]]
+type = \"instance\"
+transform = [{{ translate = [5.0, 0.0, 0.0] }}]
+figure = {{ type = \"obj\", path = \"tri.obj\", material = \"white\" }}
+
+[[figures]]
+type = \"instance\"
+transform = [{{ scale = 3.0 }}]
+figure = {{ type = \"obj\", path = \"tri.obj\", material = \"white\" }}
+",
+            CAMERA
+        );
+        let scene = parse_scene(&source, &dir.join("scene.toml")).unwrap();
+
+        // The stretched x axis now points along z.
+        let bounds = scene.world.objects[0].bounding_box().unwrap();
+        assert!((bounds.min - Vec3::new(-1.0, -1.0, -5.0)).near_zero());
+        assert!((bounds.max - Vec3::new(1.0, 1.0, -1.0)).near_zero());
+
+        match (&scene.world.objects[1], &scene.world.objects[2]) {
+            (Figure::Instance(a), Figure::Instance(b)) => assert!(Arc::ptr_eq(&a.object, &b.object)),
+            _ => panic!("expected instances"),
+        }
+
+        let singular = "[[figures]]\ntype = \"instance\"\ntransform = [{ scale = [1.0, 0.0, 1.0] }]\n\
+                        figure = { type = \"sphere\", center = [0.0, 0.0, 0.0], radius = 1.0, material = \"a\" }\n";
+        assert!(parse(singular).err().unwrap().to_string().contains("can't be inverted"));
+        let typo = singular.replace("scale = [1.0, 0.0, 1.0]", "translate = [1.0, 0.0, 1.0], degrees = 5.0");
+        assert!(matches!(parse(&typo).err().unwrap(), SceneError::Parse(..)));
+    }
+
+    #[test]
+    fn test_textures() {
+        let scene = parse(
+            "
+[textures.marble]
+type = \"noise\"
+pattern = \"marble\"
+scale = 4.0
+
+[textures.floor]
+type = \"checker\"
+size = 0.5
+even = \"marble\"
+odd = [0.1, 0.1, 0.1]
+
+[materials.ground]
+type = \"lambertian\"
+albedo = \"floor\"
+
+[[figures]]
+type = \"sphere\"
+center = [0.0, 0.0, -1.0]
+radius = 0.5
+material = \"ground\"
+",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 1);
+
+        let err = parse("[materials.a]\ntype = \"metal\"\nalbedo = \"rust\"\n").err().unwrap();
+        assert!(err.to_string().contains("material `a` references unknown texture `rust`"));
+
+        let err = parse(
+            "[textures.a]\ntype = \"checker\"\neven = \"b\"\nodd = [0.0, 0.0, 0.0]\n\
+             [textures.b]\ntype = \"checker\"\neven = [0.0, 0.0, 0.0]\nodd = \"a\"\n\
+             [materials.m]\ntype = \"lambertian\"\nalbedo = \"a\"\n",
+        )
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("contains itself"), "{}", err);
+    }
+}
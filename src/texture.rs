@@ -0,0 +1,192 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::image::Image;
+use crate::perlin::Perlin;
+use crate::util::clamp;
+
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Arc;
+
+/// Spatially varying color, looked up with the surface coordinates and
+/// the position of a hit.
+#[derive(Debug, Clone)]
+pub enum Texture {
+    Solid(Color),
+    Checker(Checker),
+    Noise(Noise),
+    Image(ImageTexture),
+}
+
+impl Texture {
+    pub fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        match self {
+            Texture::Solid(color) => color.clone(),
+            Texture::Checker(c) => c.value(u, v, p),
+            Texture::Noise(n) => n.value(p),
+            Texture::Image(i) => i.value(u, v),
+        }
+    }
+
+    pub fn checker(size: f64, even: Texture, odd: Texture) -> Texture {
+        Texture::Checker(Checker::new(size, even, odd))
+    }
+
+    pub fn noise(perlin: Arc<Perlin>, scale: f64, kind: NoiseKind) -> Texture {
+        Texture::Noise(Noise::new(perlin, scale, kind))
+    }
+
+    pub fn image(image: Arc<Image>) -> Texture {
+        Texture::Image(ImageTexture::new(image))
+    }
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Texture {
+        Texture::Solid(color)
+    }
+}
+
+/// Alternates between two textures in cubes of `size` along each axis.
+#[derive(Debug, Clone)]
+pub struct Checker {
+    pub size: f64,
+    pub even: Box<Texture>,
+    pub odd: Box<Texture>,
+}
+
+impl Checker {
+    pub fn new(size: f64, even: Texture, odd: Texture) -> Checker {
+        Checker { size, even: Box::new(even), odd: Box::new(odd) }
+    }
+
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Color {
+        let cell: i64 = p.iter().map(|c| (c / self.size).floor() as i64).sum();
+        if cell.rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NoiseKind {
+    /// Plain noise mapped to [0, 1].
+    #[default]
+    Perlin,
+    /// Seven octaves of absolute noise.
+    Turbulence,
+    /// Veins from a sine along z phase shifted by turbulence.
+    Marble,
+}
+
+/// Gray noise. The generator is shared so every copy of a material sees
+/// the same pattern.
+#[derive(Debug, Clone)]
+pub struct Noise {
+    pub perlin: Arc<Perlin>,
+    pub scale: f64,
+    pub kind: NoiseKind,
+}
+
+impl Noise {
+    pub fn new(perlin: Arc<Perlin>, scale: f64, kind: NoiseKind) -> Noise {
+        Noise { perlin, scale, kind }
+    }
+
+    fn value(&self, p: &Vec3) -> Color {
+        let scaled = self.scale * p;
+        let gray = match self.kind {
+            NoiseKind::Perlin => 0.5 * (1.0 + self.perlin.noise(&scaled)),
+            // Octaves can add up past one, which would make an albedo
+            // reflect more light than arrives.
+            NoiseKind::Turbulence => self.perlin.turbulence(&scaled, 7).min(1.0),
+            NoiseKind::Marble => 0.5 * (1.0 + (scaled.z() + 10.0 * self.perlin.turbulence(&scaled, 7)).sin()),
+        };
+        Color::new(gray, gray, gray)
+    }
+}
+
+/// Maps u to columns and v to rows, bottom row at v = 0, with the nearest
+/// pixel and coordinates clamped to the image.
+#[derive(Clone)]
+pub struct ImageTexture {
+    pub image: Arc<Image>,
+}
+
+impl ImageTexture {
+    pub fn new(image: Arc<Image>) -> ImageTexture {
+        ImageTexture { image }
+    }
+
+    fn value(&self, u: f64, v: f64) -> Color {
+        let image = &self.image;
+        let u = clamp(u, 0.0, 1.0);
+        let v = 1.0 - clamp(v, 0.0, 1.0);
+        let x = ((u * image.width as f64) as usize).min(image.width - 1);
+        let y = ((v * image.height as f64) as usize).min(image.height - 1);
+        image.pixels[y * image.width + x].clone()
+    }
+}
+
+impl fmt::Debug for ImageTexture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ImageTexture({}x{})", self.image.width, self.image.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn gray(g: f64) -> Texture {
+        Texture::from(Color::new(g, g, g))
+    }
+
+    #[test]
+    fn test_checker() {
+        let checker = Texture::checker(0.5, gray(1.0), gray(0.0));
+        let at = |x, y, z| checker.value(0.0, 0.0, &Vec3::new(x, y, z)).r();
+        assert_eq!(at(0.1, 0.1, 0.1), 1.0);
+        assert_eq!(at(0.6, 0.1, 0.1), 0.0);
+        assert_eq!(at(0.6, 0.6, 0.1), 1.0);
+        // No mirrored double-width cells around zero.
+        assert_eq!(at(-0.1, 0.1, 0.1), 0.0);
+        assert_eq!(at(-0.6, 0.1, 0.1), 1.0);
+    }
+
+    #[test]
+    fn test_image_texture() {
+        // Top row red, green; bottom row blue, white.
+        let pixels = vec![
+            Color::new(1.0, 0.0, 0.0),
+            Color::new(0.0, 1.0, 0.0),
+            Color::new(0.0, 0.0, 1.0),
+            Color::new(1.0, 1.0, 1.0),
+        ];
+        let texture = Texture::image(Arc::new(Image::new(2, 2, pixels)));
+        let p = Vec3::new(0.0, 0.0, 0.0);
+        assert_eq!(texture.value(0.25, 0.75, &p), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(texture.value(1.0, 1.0, &p), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(texture.value(0.0, 0.0, &p), Color::new(0.0, 0.0, 1.0));
+        assert_eq!(texture.value(2.0, -1.0, &p), Color::new(1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn test_noise_range() {
+        let perlin = Arc::new(Perlin::new(&mut StdRng::seed_from_u64(5)));
+        let mut rng = StdRng::seed_from_u64(6);
+        for kind in [NoiseKind::Perlin, NoiseKind::Turbulence, NoiseKind::Marble] {
+            let texture = Texture::noise(perlin.clone(), 4.0, kind);
+            for _ in 0..200 {
+                let c = texture.value(0.0, 0.0, &Vec3::rand_range(&mut rng, -5.0, 5.0));
+                assert!(c.r() >= 0.0 && c.r() <= 1.0);
+                assert_eq!(c.r(), c.g());
+            }
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use crate::hitrecord::HitRecord;
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::onb::Onb;
+use crate::poly::{roots_in, solve_quadratic};
+use crate::quadric::{local_ray, record, to_local, turn};
+
+/// Ring swept by a circle of `minor_radius` whose center runs around
+/// `axis` at `major_radius` from `center`. u goes around the axis and v
+/// around the tube, starting from its outer equator.
+#[derive(Debug, Clone)]
+pub struct Torus {
+    pub center: Vec3,
+    pub major_radius: f64,
+    pub minor_radius: f64,
+    pub material: Material,
+    basis: Onb,
+}
+
+impl Torus {
+    pub fn new(center: Vec3, axis: Vec3, major_radius: f64, minor_radius: f64, material: Material) -> Self {
+        let basis = Onb::from_w(&axis);
+        Self { center, major_radius: major_radius.abs(), minor_radius: minor_radius.abs(), material, basis }
+    }
+
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord<'_>> {
+        let (big, small) = (self.major_radius, self.minor_radius);
+        let (o, d) = local_ray(&self.basis, &self.center, ray);
+
+        // The quartic only needs solving where the ray is inside the
+        // bounding sphere. Measuring t from where it enters keeps the
+        // coefficients small for distant origins, which would otherwise
+        // cancel away the roots.
+        let reach = big + small;
+        let (enter, exit) = solve_quadratic(d.length_squared(), 2.0 * o.dot(&d), o.length_squared() - reach * reach)?;
+        let (lo, hi) = (enter.max(t_min), exit.min(t_max));
+        if lo > hi {
+            return None;
+        }
+        let o = &o + (enter * &d);
+
+        // (|p|² + R² - r²)² = 4R² (x² + y²) along p = o + t d.
+        let g = d.length_squared();
+        let h = 2.0 * o.dot(&d);
+        let i = o.length_squared() + big * big - small * small;
+        let ring = 4.0 * big * big;
+        let coeffs = [
+            g * g,
+            2.0 * g * h,
+            h * h + 2.0 * g * i - ring * (d.x() * d.x() + d.y() * d.y()),
+            2.0 * h * i - ring * 2.0 * (o.x() * d.x() + o.y() * d.y()),
+            i * i - ring * (o.x() * o.x() + o.y() * o.y()),
+        ];
+        let t = enter + *roots_in(&coeffs, lo - enter, hi - enter).first()?;
+
+        // The normal points away from the nearest point of the tube's core.
+        let p = to_local(&self.basis, &self.center, &ray.at(t));
+        let rho = (p.x() * p.x() + p.y() * p.y()).sqrt();
+        let core = if rho > 0.0 { (big / rho) * Vec3::new(p.x(), p.y(), 0.0) } else { Vec3::new(big, 0.0, 0.0) };
+        let normal = (&p - &core).unit_vector();
+        let outward_normal = self.basis.local(normal.x(), normal.y(), normal.z());
+        let (u, v) = (turn(p.y(), p.x()), turn(p.z(), rho - big));
+        Some(record(ray, t, &outward_normal, &self.material).with_uv(u, v))
+    }
+
+    pub fn bounding_box(&self) -> Aabb {
+        let axis = &self.basis.w;
+        let reach = |n: f64| self.major_radius * (1.0 - n * n).max(0.0).sqrt() + self.minor_radius;
+        let half = Vec3::new(reach(axis.x()), reach(axis.y()), reach(axis.z()));
+        Aabb::new(&self.center - &half, &self.center + &half)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+
+    fn torus() -> Torus {
+        let white = Material::lambertian(Color::new(1.0, 1.0, 1.0));
+        Torus::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0), 2.0, 0.5, white)
+    }
+
+    #[test]
+    fn test_torus_hits() {
+        let torus = torus();
+        // Through the middle: the near side of the tube, then the hole.
+        let across = Ray::new(Vec3::new(-10.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = torus.hit(&across, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 7.5).abs() < 1e-9);
+        assert!((&rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+        let rec = torus.hit(&across, 8.0, f64::INFINITY).unwrap();
+        assert!((rec.t - 8.5).abs() < 1e-9);
+        assert!((&rec.normal - Vec3::new(-1.0, 0.0, 0.0)).length() < 1e-9);
+        assert!(!rec.front_face);
+
+        // Straight through the hole.
+        let hole = Ray::new(Vec3::new(0.0, 10.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        assert!(torus.hit(&hole, 0.001, f64::INFINITY).is_none());
+
+        // From a far origin the roots keep their precision.
+        let far = Ray::new(Vec3::new(-1e6, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let rec = torus.hit(&far, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - (1e6 - 2.5)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_torus_inside_and_grazing() {
+        let torus = torus();
+        // From the core of the tube every direction leaves after r.
+        let inside = Ray::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        let rec = torus.hit(&inside, 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 0.5).abs() < 1e-9);
+        assert!(!rec.front_face);
+        assert!((&rec.normal - Vec3::new(0.0, -1.0, 0.0)).length() < 1e-9);
+        assert!((rec.v - 0.25).abs() < 1e-9);
+
+        // Skimming the top of the tube, a hair above and below.
+        let skim = |y: f64| Ray::new(Vec3::new(-5.0, y, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        assert!(torus.hit(&skim(0.5 + 1e-6), 0.001, f64::INFINITY).is_none());
+        let rec = torus.hit(&skim(0.5 - 1e-6), 0.001, f64::INFINITY).unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-2);
+        assert!(rec.normal.y() > 0.99);
+        // Exactly tangent either just touches or just misses, never worse.
+        if let Some(rec) = torus.hit(&skim(0.5), 0.001, f64::INFINITY) {
+            assert!((rec.t - 3.0).abs() < 1e-3);
+            assert!(rec.normal.iter().all(|c| c.is_finite()));
+        }
+    }
+
+    #[test]
+    fn test_torus_bounds() {
+        let bounds = torus().bounding_box();
+        assert!((&bounds.min - Vec3::new(-2.5, -0.5, -2.5)).length() < 1e-12);
+        assert!((&bounds.max - Vec3::new(2.5, 0.5, 2.5)).length() < 1e-12);
+    }
+}
@@ -1,4 +1,3 @@
-use crate::vec3::Vec3;
 //use rand::{Rng, self};
 
 pub fn clamp<T: PartialOrd>(value: T, min: T, max: T) -> T {
@@ -18,8 +18,8 @@ impl<const N: usize> Vector<N> {
 
     pub fn rand<R: Rng>(rng: &mut R) -> Self {
         let mut data = [0.0; N];
-        for i in 0..N {
-            data[i] = rng.gen();
+        for x in data.iter_mut() {
+            *x = rng.gen();
         }
         Vector { data }
     }
@@ -37,8 +37,8 @@ impl<const N: usize> Vector<N> {
 
     pub fn rand_range<R: Rng>(rng: &mut R, min: f64, max: f64) -> Self {
         let mut data = [0.0; N];
-        for i in 0..N {
-            data[i] = rng.gen_range(min..=max);
+        for x in data.iter_mut() {
+            *x = rng.gen_range(min..=max);
         }
         Vector { data }
     }
@@ -66,27 +66,15 @@ impl<const N: usize> Vector<N> {
     }
 
     fn add_vec(&self, other: &Self) -> Self {
-        let mut data = [0.0; N];
-        for i in 0..N {
-            data[i] = self.data[i] + other.data[i];
-        }
-        Self { data }
+        Self { data: std::array::from_fn(|i| self.data[i] + other.data[i]) }
     }
 
     fn sub_vec(&self, other: &Self) -> Self {
-        let mut data = [0.0; N];
-        for i in 0..N {
-            data[i] = self.data[i] - other.data[i];
-        }
-        Self { data }
+        Self { data: std::array::from_fn(|i| self.data[i] - other.data[i]) }
     }
 
     fn scale(&self, scalar: f64) -> Self {
-        let mut data = [0.0; N];
-        for i in 0..N {
-            data[i] = self.data[i] * scalar;
-        }
-        Self { data }
+        Self { data: std::array::from_fn(|i| self.data[i] * scalar) }
     }
 
     pub fn length_squared(&self) -> f64 {
@@ -130,11 +118,7 @@ impl<const N: usize> Vector<N> {
     }
 
     pub fn prod(&self, other: &Self) -> Self {
-        let mut data = [0.0; N];
-        for i in 0..N {
-            data[i] = self.data[i] * other.data[i];
-        }
-        Self { data }
+        Self { data: std::array::from_fn(|i| self.data[i] * other.data[i]) }
     }
 }
 
@@ -159,7 +143,7 @@ impl<const N: usize> Add<&Vector<N>> for &Vector<N> {
     type Output = Vector<N>;
 
     fn add(self, other: &Vector<N>) -> Self::Output {
-        self.add_vec(&other)
+        self.add_vec(other)
     }
 }
 
@@ -193,7 +177,7 @@ impl<const N: usize> Add<Vector<N>> for Vector<N> {
 // += &Vector
 impl<const N: usize> AddAssign<&Vector<N>> for Vector<N> {
     fn add_assign(&mut self, other: &Vector<N>) {
-        self.add_assign(&other)
+        self.add_assign(other)
     }
 }
 
@@ -209,7 +193,7 @@ impl<const N: usize> Sub<&Vector<N>> for &Vector<N> {
     type Output = Vector<N>;
 
     fn sub(self, other: &Vector<N>) -> Self::Output {
-        self.sub_vec(&other)
+        self.sub_vec(other)
     }
 }
 
@@ -242,7 +226,7 @@ impl<const N: usize> Sub<Vector<N>> for Vector<N> {
 
 impl<const N: usize> SubAssign<&Vector<N>> for Vector<N> {
     fn sub_assign(&mut self, other: &Vector<N>) {
-        self.sub_assign(&other)
+        self.sub_assign(other)
     }
 }
 
@@ -351,6 +335,7 @@ impl<const N: usize> DivAssign<f64> for Vector<N> {
 }
 
 #[cfg(test)]
+#[allow(unused_variables, clippy::single_component_path_imports)]
 mod tests {
     use super::*;
     use rand;
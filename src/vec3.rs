@@ -1,5 +1,7 @@
 use crate::vec;
 
+use rand::Rng;
+
 pub type Vec3 = vec::Vector<3>;
 
 impl Vec3 {
@@ -28,4 +30,14 @@ impl Vec3 {
             self.x() * other.y() - self.y() * other.x(),
         )
     }
+
+    /// Uniform point in the unit disk on the xy plane.
+    pub fn rand_in_unit_disk<R: Rng>(rng: &mut R) -> Vec3 {
+        loop {
+            let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), 0.0);
+            if p.length_squared() < 1.0 {
+                return p;
+            }
+        }
+    }
 }
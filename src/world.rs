@@ -1,39 +1,343 @@
 use crate::hitrecord::HitRecord;
-use crate::vec3::Vec3;
 use crate::ray::Ray;
-use crate::color::Color;
 use crate::figure::Figure;
+use crate::bvh::Bvh;
+use crate::light::Light;
+
+use std::ops::Range;
+use std::sync::OnceLock;
 
 /*pub struct World {
     pub objects: Vec<Box<dyn Hittable>>,
 }*/
 
 pub struct World {
-    pub objects: Vec<Figure>
+    pub objects: Vec<Figure>,
+    // Built on the first hit; `add` throws it away.
+    bvh: OnceLock<Bvh>,
+    // Figures without a bounding box are tested on every ray.
+    unbounded: OnceLock<Vec<usize>>,
+    // Lights without a figure: points, spots and directional lights.
+    punctual: Vec<Light>,
+    // Emissive figures and punctual lights, sampled directly by the
+    // integrator.
+    lights: OnceLock<Lights>,
+}
+
+struct Lights {
+    all: Vec<Light>,
+    // The lights each figure became, by figure index.
+    by_figure: Vec<Range<usize>>,
 }
 
 impl World {
     pub fn new() -> World {
         World {
             objects: Vec::new(),
+            bvh: OnceLock::new(),
+            unbounded: OnceLock::new(),
+            punctual: Vec::new(),
+            lights: OnceLock::new(),
         }
     }
 
     pub fn add(&mut self, object: Figure) {
         self.objects.push(object);
+        self.bvh = OnceLock::new();
+        self.unbounded = OnceLock::new();
+        self.lights = OnceLock::new();
+    }
+
+    /// Adds a light that isn't a figure, so it's only seen through what it
+    /// lights.
+    pub fn add_light(&mut self, light: Light) {
+        self.punctual.push(light);
+        self.lights = OnceLock::new();
+    }
+
+    /// Builds the acceleration structure now instead of on the first ray.
+    pub fn build_bvh(&self) {
+        self.bvh();
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| {
+            let boxes = self
+                .objects
+                .iter()
+                .enumerate()
+                .filter_map(|(i, object)| object.bounding_box().map(|b| (i, b)))
+                .collect();
+            Bvh::new(boxes)
+        })
+    }
+
+    fn unbounded(&self) -> &[usize] {
+        self.unbounded.get_or_init(|| {
+            self.objects
+                .iter()
+                .enumerate()
+                .filter(|(_, object)| object.bounding_box().is_none())
+                .map(|(i, _)| i)
+                .collect()
+        })
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.light_list().all
+    }
+
+    fn light_list(&self) -> &Lights {
+        self.lights.get_or_init(|| {
+            let mut lights = Vec::new();
+            let mut by_figure = Vec::with_capacity(self.objects.len());
+            for object in &self.objects {
+                let start = lights.len();
+                match object {
+                    Figure::Sphere(sphere) => {
+                        let radiance = sphere.material.emitted();
+                        if !radiance.near_zero() {
+                            lights.push(Light::Sphere {
+                                center: sphere.center.clone(),
+                                radius: sphere.radius.abs(),
+                                radiance,
+                            });
+                        }
+                    }
+                    Figure::Triangle(triangle) => {
+                        let radiance = triangle.material.emitted();
+                        if !radiance.near_zero() {
+                            lights.push(Light::Triangle {
+                                p0: triangle.v0.clone(),
+                                p1: triangle.v1.clone(),
+                                p2: triangle.v2.clone(),
+                                radiance,
+                            });
+                        }
+                    }
+                    Figure::Quad(quad) => {
+                        let radiance = quad.material.emitted();
+                        if !radiance.near_zero() {
+                            lights.push(Light::Quad { q: quad.q.clone(), u: quad.u.clone(), v: quad.v.clone(), radiance });
+                        }
+                    }
+                    Figure::Disk(disk) => {
+                        let radiance = disk.material.emitted();
+                        if !radiance.near_zero() {
+                            lights.push(Light::Disk {
+                                center: disk.center.clone(),
+                                normal: disk.normal.clone(),
+                                radius: disk.radius,
+                                radiance,
+                            });
+                        }
+                    }
+                    Figure::Box(cuboid) => {
+                        let radiance = cuboid.material.emitted();
+                        if !radiance.near_zero() {
+                            let (axes, half) = (&cuboid.axes, &cuboid.half_extents);
+                            for k in 0..3 {
+                                let (i, j) = ((k + 1) % 3, (k + 2) % 3);
+                                let u = (2.0 * half[i]) * &axes[i];
+                                let v = (2.0 * half[j]) * &axes[j];
+                                for side in [-1.0, 1.0] {
+                                    let q = &cuboid.center + &((side * half[k]) * &axes[k]) - (0.5 * &u) - (0.5 * &v);
+                                    lights.push(Light::Quad { q, u: u.clone(), v: v.clone(), radiance: radiance.clone() });
+                                }
+                            }
+                        }
+                    }
+                    // Infinite planes can't be sampled by area. Moving and
+                    // instanced lights still glow when hit, they just aren't
+                    // sampled directly.
+                    Figure::Plane(_)
+                    | Figure::MovingSphere(_)
+                    | Figure::Instance(_)
+                    | Figure::Cylinder(_)
+                    | Figure::Cone(_)
+                    | Figure::Torus(_)
+                    | Figure::Quadric(_)
+                    | Figure::Csg(_)
+                    | Figure::Medium(_)
+                    | Figure::Grid(_) => {}
+                    Figure::Mesh(mesh) => {
+                        let radiance = mesh.material.emitted();
+                        if !radiance.near_zero() {
+                            lights.extend(mesh.indices.iter().map(|&[i0, i1, i2]| Light::Triangle {
+                                p0: mesh.positions[i0].clone(),
+                                p1: mesh.positions[i1].clone(),
+                                p2: mesh.positions[i2].clone(),
+                                radiance: radiance.clone(),
+                            }));
+                        }
+                    }
+                }
+                by_figure.push(start..lights.len());
+            }
+            lights.extend(self.punctual.iter().cloned());
+            Lights { all: lights, by_figure }
+        })
+    }
+
+    /// Density with which sampling the light `ray` first hits at `t`, on
+    /// the figure with index `figure`, gives its direction, not counting
+    /// the choice of light.
+    pub fn light_pdf(&self, figure: usize, ray: &Ray, t: f64) -> f64 {
+        let lights = self.light_list();
+        let range = lights.by_figure[figure].clone();
+        let tolerance = 1e-6 * t.max(1.0);
+        let light = match &self.objects[figure] {
+            // A mesh's lights are its triangles, in order.
+            Figure::Mesh(mesh) => mesh.hit_index(ray, t - tolerance, t + tolerance).map(|i| &lights.all[range.start + i]),
+            // One light, or the six faces of a box.
+            _ => lights.all[range].iter().find(|light| light.intersect(ray, t - tolerance, t + tolerance).is_some()),
+        };
+        light.map_or(0.0, |light| light.pdf(ray, t))
+    }
+
+    /// Fraction of light that gets through along the ray, for shadow rays:
+    /// zero if any surface is in the way, otherwise the product of each
+    /// medium's (estimated) transmittance.
+    pub fn transmittance(&self, ray: &Ray, t_min: f64, t_max: f64) -> f64 {
+        let mut transmittance = 1.0;
+        self.bvh().hit(ray, t_min, t_max, |i, _| {
+            if transmittance > 0.0 {
+                transmittance *= self.objects[i].transmittance(ray, t_min, t_max);
+            }
+            None
+        });
+        for &i in self.unbounded() {
+            if transmittance > 0.0 {
+                transmittance *= self.objects[i].transmittance(ray, t_min, t_max);
+            }
+        }
+        transmittance
     }
 
-    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<HitRecord> {
+    /// Closest hit along the ray, with the index of the figure hit. Gives
+    /// exactly the same result as `hit_linear`, including which figure
+    /// wins a tie.
+    pub fn hit(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord<'_>)> {
+        let mut closest = self
+            .bvh()
+            .hit(ray, t_min, t_max, |i, t_max| self.objects[i].hit(ray, t_min, t_max));
+
+        for &i in self.unbounded() {
+            let closest_so_far = closest.as_ref().map_or(t_max, |(_, rec)| rec.t);
+            if let Some(rec) = self.objects[i].hit(ray, t_min, closest_so_far) {
+                let better = match &closest {
+                    None => true,
+                    Some((best, best_rec)) => rec.t < best_rec.t || i > *best,
+                };
+                if better {
+                    closest = Some((i, rec));
+                }
+            }
+        }
+
+        closest
+    }
+
+    /// Tests every figure in order. Kept as the reference for `hit`.
+    #[cfg(test)]
+    pub fn hit_linear(&self, ray: &Ray, t_min: f64, t_max: f64) -> Option<(usize, HitRecord<'_>)> {
         let mut hit_anything = None;
         let mut closest_so_far = t_max;
 
-        for object in &self.objects {
+        for (i, object) in self.objects.iter().enumerate() {
             if let Some(hit_record) = object.hit(ray, t_min, closest_so_far) {
                 closest_so_far = hit_record.t;
-                hit_anything = Some(hit_record);
+                hit_anything = Some((i, hit_record));
             }
         }
 
         hit_anything
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Material;
+    use crate::vec3::Vec3;
+    use crate::mesh::Mesh;
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    fn random_world<R: Rng>(rng: &mut R, count: usize) -> World {
+        let mut world = World::new();
+        for _ in 0..count {
+            let center = Vec3::rand_range(rng, -10.0, 10.0);
+            let radius = rng.gen_range(0.05..1.0);
+            world.add(Figure::sphere(center, radius, Material::lambertian(Color::rand(rng))));
+        }
+        // Coincident figures make sure ties resolve the same way.
+        world.add(Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 0.5, Material::dielectric(1.5)));
+        world.add(Figure::sphere(Vec3::new(0.0, 0.0, 0.0), 0.5, Material::dielectric(1.3)));
+        world.add(Figure::sphere(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Material::lambertian(Color::rand(rng))));
+        world
+    }
+
+    #[test]
+    fn test_bvh_matches_linear_scan() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let world = random_world(&mut rng, 2000);
+
+        for _ in 0..5000 {
+            let origin = Vec3::rand_range(&mut rng, -15.0, 15.0);
+            let direction = Vec3::rand_in_unit_sphere(&mut rng);
+            let ray = Ray::new(origin, direction);
+
+            let fast = world.hit(&ray, 0.001, f64::INFINITY);
+            let slow = world.hit_linear(&ray, 0.001, f64::INFINITY);
+            match (fast, slow) {
+                (None, None) => {}
+                (Some((i, a)), Some((j, b))) => {
+                    assert_eq!(i, j);
+                    assert_eq!(a.t, b.t);
+                    assert_eq!(a.p, b.p);
+                    assert_eq!(a.normal, b.normal);
+                    assert_eq!(a.front_face, b.front_face);
+                    assert!(std::ptr::eq(a.material, b.material));
+                }
+                _ => panic!("bvh and linear scan disagree"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_light_pdf_by_figure() {
+        // A lamp behind a plain sphere, then an emissive box and a mesh
+        // of a few quads: each hit finds the light it came from.
+        let lamp = || Material::diffuse_light(Color::new(1.0, 1.0, 1.0), 1.0);
+        let mut world = World::new();
+        world.add(Figure::sphere(Vec3::new(0.0, 0.0, -5.0), 1.0, Material::lambertian(Color::new(0.5, 0.5, 0.5))));
+        world.add(Figure::cuboid(Vec3::new(-4.0, -1.0, -12.0), Vec3::new(-2.0, 1.0, -10.0), lamp()));
+        let positions = (0..8).flat_map(|x| [Vec3::new(x as f64, 3.0, -8.0), Vec3::new(x as f64, 4.0, -8.0)]).collect();
+        let indices = (0..7).flat_map(|i| [[2 * i, 2 * i + 2, 2 * i + 1], [2 * i + 1, 2 * i + 2, 2 * i + 3]]).collect();
+        world.add(Figure::mesh(Mesh::new(positions, indices, None, None, lamp())));
+
+        let mut rng = StdRng::seed_from_u64(2);
+        for _ in 0..200 {
+            let target = Vec3::new(rng.gen_range(-4.0..7.0), rng.gen_range(-1.0..4.0), -8.0);
+            let ray = Ray::new(Vec3::new(0.5, 0.5, 5.0), target - Vec3::new(0.5, 0.5, 5.0));
+            let Some((figure, rec)) = world.hit(&ray, 0.001, f64::INFINITY) else { continue };
+            let tolerance = 1e-6 * rec.t.max(1.0);
+            let expected = world
+                .lights()
+                .iter()
+                .find(|light| light.intersect(&ray, rec.t - tolerance, rec.t + tolerance).is_some())
+                .map_or(0.0, |light| light.pdf(&ray, rec.t));
+            assert_eq!(world.light_pdf(figure, &ray, rec.t), expected);
+            assert_eq!(expected > 0.0, figure > 0);
+        }
+    }
+
+    #[test]
+    fn test_empty_world() {
+        let world = World::new();
+        let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(world.hit(&ray, 0.001, f64::INFINITY).is_none());
+    }
+}